@@ -0,0 +1,308 @@
+//! Rule-based deinflection of Japanese verbs and adjectives back to their dictionary form.
+//!
+//! Rules strip an inflected ending and put back the one of the dictionary form, and can be chained, like
+//! 食べさせられた → 食べさせられる → 食べさせる → 食べる. Each rule only applies to words of the kinds it can
+//! inflect, so the candidates it gives must still be checked against the part of speech of dictionary entries.
+
+use crate::database::dictionary::DictionaryEntry;
+
+/// Ichidan verbs.
+pub const V1: u8 = 1 << 0;
+/// Godan verbs.
+pub const V5: u8 = 1 << 1;
+/// する verbs.
+pub const VS: u8 = 1 << 2;
+/// くる verbs.
+pub const VK: u8 = 1 << 3;
+/// い adjectives.
+pub const ADJ_I: u8 = 1 << 4;
+/// Polite forms ending in ます, which only ever lead back to a verb.
+const MASU: u8 = 1 << 5;
+/// Forms ending in て or で, including those followed by いる.
+const TE: u8 = 1 << 6;
+
+/// A possible dictionary form of a word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deinflection {
+    /// The word with its inflections removed.
+    pub term: String,
+    /// Kinds of words `term` must be, or 0 if it is the word as given and could be any.
+    pub word_types: u8,
+    /// Names of the inflections which were removed, outermost last.
+    pub reasons: Vec<&'static str>,
+}
+
+impl Deinflection {
+    /// Whether `entry` has a part of speech which could have been inflected like this.
+    pub fn matches(&self, entry: &DictionaryEntry) -> bool {
+        self.word_types == 0 || self.word_types & entry_word_types(entry) != 0
+    }
+}
+
+struct Rule {
+    inflected: &'static str,
+    uninflected: &'static str,
+    /// Kinds of words the inflected form must be usable as, or 0 if it can't be inflected further.
+    types_in: u8,
+    types_out: u8,
+    reason: &'static str,
+}
+
+const fn rule(
+    inflected: &'static str,
+    uninflected: &'static str,
+    types_in: u8,
+    types_out: u8,
+    reason: &'static str,
+) -> Rule {
+    Rule {
+        inflected,
+        uninflected,
+        types_in,
+        types_out,
+        reason,
+    }
+}
+
+#[rustfmt::skip]
+const RULES: &[Rule] = &[
+    // Negative, which inflects like an い adjective.
+    rule("ない", "る", ADJ_I, V1, "negative"),
+    rule("かない", "く", ADJ_I, V5, "negative"),
+    rule("がない", "ぐ", ADJ_I, V5, "negative"),
+    rule("さない", "す", ADJ_I, V5, "negative"),
+    rule("たない", "つ", ADJ_I, V5, "negative"),
+    rule("なない", "ぬ", ADJ_I, V5, "negative"),
+    rule("ばない", "ぶ", ADJ_I, V5, "negative"),
+    rule("まない", "む", ADJ_I, V5, "negative"),
+    rule("らない", "る", ADJ_I, V5, "negative"),
+    rule("わない", "う", ADJ_I, V5, "negative"),
+    rule("しない", "する", ADJ_I, VS, "negative"),
+    rule("こない", "くる", ADJ_I, VK, "negative"),
+    rule("来ない", "来る", ADJ_I, VK, "negative"),
+    rule("くない", "い", ADJ_I, ADJ_I, "negative"),
+    // Past.
+    rule("た", "る", 0, V1, "past"),
+    rule("いた", "く", 0, V5, "past"),
+    rule("いだ", "ぐ", 0, V5, "past"),
+    rule("した", "す", 0, V5, "past"),
+    rule("った", "う", 0, V5, "past"),
+    rule("った", "つ", 0, V5, "past"),
+    rule("った", "る", 0, V5, "past"),
+    rule("んだ", "ぬ", 0, V5, "past"),
+    rule("んだ", "ぶ", 0, V5, "past"),
+    rule("んだ", "む", 0, V5, "past"),
+    rule("行った", "行く", 0, V5, "past"),
+    rule("いった", "いく", 0, V5, "past"),
+    rule("した", "する", 0, VS, "past"),
+    rule("きた", "くる", 0, VK, "past"),
+    rule("来た", "来る", 0, VK, "past"),
+    rule("かった", "い", 0, ADJ_I, "past"),
+    // Te form, also found before いる.
+    rule("て", "る", TE, V1, "te"),
+    rule("いて", "く", TE, V5, "te"),
+    rule("いで", "ぐ", TE, V5, "te"),
+    rule("して", "す", TE, V5, "te"),
+    rule("って", "う", TE, V5, "te"),
+    rule("って", "つ", TE, V5, "te"),
+    rule("って", "る", TE, V5, "te"),
+    rule("んで", "ぬ", TE, V5, "te"),
+    rule("んで", "ぶ", TE, V5, "te"),
+    rule("んで", "む", TE, V5, "te"),
+    rule("行って", "行く", TE, V5, "te"),
+    rule("いって", "いく", TE, V5, "te"),
+    rule("して", "する", TE, VS, "te"),
+    rule("きて", "くる", TE, VK, "te"),
+    rule("来て", "来る", TE, VK, "te"),
+    rule("くて", "い", TE, ADJ_I, "te"),
+    rule("ている", "て", V1, TE, "progressive"),
+    rule("てる", "て", V1, TE, "progressive"),
+    rule("でいる", "で", V1, TE, "progressive"),
+    rule("でる", "で", V1, TE, "progressive"),
+    // Polite.
+    rule("ます", "る", MASU, V1, "polite"),
+    rule("きます", "く", MASU, V5, "polite"),
+    rule("ぎます", "ぐ", MASU, V5, "polite"),
+    rule("します", "す", MASU, V5, "polite"),
+    rule("ちます", "つ", MASU, V5, "polite"),
+    rule("にます", "ぬ", MASU, V5, "polite"),
+    rule("びます", "ぶ", MASU, V5, "polite"),
+    rule("みます", "む", MASU, V5, "polite"),
+    rule("ります", "る", MASU, V5, "polite"),
+    rule("います", "う", MASU, V5, "polite"),
+    rule("します", "する", MASU, VS, "polite"),
+    rule("きます", "くる", MASU, VK, "polite"),
+    rule("来ます", "来る", MASU, VK, "polite"),
+    rule("ました", "ます", 0, MASU, "past"),
+    rule("ません", "ます", 0, MASU, "negative"),
+    rule("ましょう", "ます", 0, MASU, "volitional"),
+    rule("まして", "ます", 0, MASU, "te"),
+    // Desire, which inflects like an い adjective.
+    rule("たい", "る", ADJ_I, V1, "desire"),
+    rule("きたい", "く", ADJ_I, V5, "desire"),
+    rule("ぎたい", "ぐ", ADJ_I, V5, "desire"),
+    rule("したい", "す", ADJ_I, V5, "desire"),
+    rule("ちたい", "つ", ADJ_I, V5, "desire"),
+    rule("にたい", "ぬ", ADJ_I, V5, "desire"),
+    rule("びたい", "ぶ", ADJ_I, V5, "desire"),
+    rule("みたい", "む", ADJ_I, V5, "desire"),
+    rule("りたい", "る", ADJ_I, V5, "desire"),
+    rule("いたい", "う", ADJ_I, V5, "desire"),
+    rule("したい", "する", ADJ_I, VS, "desire"),
+    rule("きたい", "くる", ADJ_I, VK, "desire"),
+    // Potential, passive and causative, which all inflect like ichidan verbs.
+    rule("える", "う", V1, V5, "potential"),
+    rule("ける", "く", V1, V5, "potential"),
+    rule("げる", "ぐ", V1, V5, "potential"),
+    rule("せる", "す", V1, V5, "potential"),
+    rule("てる", "つ", V1, V5, "potential"),
+    rule("ねる", "ぬ", V1, V5, "potential"),
+    rule("べる", "ぶ", V1, V5, "potential"),
+    rule("める", "む", V1, V5, "potential"),
+    rule("れる", "る", V1, V5, "potential"),
+    rule("られる", "る", V1, V1, "potential or passive"),
+    rule("できる", "する", V1, VS, "potential"),
+    rule("こられる", "くる", V1, VK, "potential or passive"),
+    rule("かれる", "く", V1, V5, "passive"),
+    rule("がれる", "ぐ", V1, V5, "passive"),
+    rule("される", "す", V1, V5, "passive"),
+    rule("たれる", "つ", V1, V5, "passive"),
+    rule("なれる", "ぬ", V1, V5, "passive"),
+    rule("ばれる", "ぶ", V1, V5, "passive"),
+    rule("まれる", "む", V1, V5, "passive"),
+    rule("られる", "る", V1, V5, "passive"),
+    rule("われる", "う", V1, V5, "passive"),
+    rule("される", "する", V1, VS, "passive"),
+    rule("させる", "る", V1, V1, "causative"),
+    rule("かせる", "く", V1, V5, "causative"),
+    rule("がせる", "ぐ", V1, V5, "causative"),
+    rule("させる", "す", V1, V5, "causative"),
+    rule("たせる", "つ", V1, V5, "causative"),
+    rule("なせる", "ぬ", V1, V5, "causative"),
+    rule("ばせる", "ぶ", V1, V5, "causative"),
+    rule("ませる", "む", V1, V5, "causative"),
+    rule("らせる", "る", V1, V5, "causative"),
+    rule("わせる", "う", V1, V5, "causative"),
+    rule("させる", "する", V1, VS, "causative"),
+    rule("こさせる", "くる", V1, VK, "causative"),
+    // Conditional.
+    rule("えば", "う", 0, V5, "conditional"),
+    rule("けば", "く", 0, V5, "conditional"),
+    rule("げば", "ぐ", 0, V5, "conditional"),
+    rule("せば", "す", 0, V5, "conditional"),
+    rule("てば", "つ", 0, V5, "conditional"),
+    rule("ねば", "ぬ", 0, V5, "conditional"),
+    rule("べば", "ぶ", 0, V5, "conditional"),
+    rule("めば", "む", 0, V5, "conditional"),
+    rule("れば", "る", 0, V1 | V5, "conditional"),
+    rule("すれば", "する", 0, VS, "conditional"),
+    rule("くれば", "くる", 0, VK, "conditional"),
+    rule("ければ", "い", 0, ADJ_I, "conditional"),
+    // Volitional.
+    rule("よう", "る", 0, V1, "volitional"),
+    rule("おう", "う", 0, V5, "volitional"),
+    rule("こう", "く", 0, V5, "volitional"),
+    rule("ごう", "ぐ", 0, V5, "volitional"),
+    rule("そう", "す", 0, V5, "volitional"),
+    rule("とう", "つ", 0, V5, "volitional"),
+    rule("のう", "ぬ", 0, V5, "volitional"),
+    rule("ぼう", "ぶ", 0, V5, "volitional"),
+    rule("もう", "む", 0, V5, "volitional"),
+    rule("ろう", "る", 0, V5, "volitional"),
+    rule("しよう", "する", 0, VS, "volitional"),
+    rule("こよう", "くる", 0, VK, "volitional"),
+    // Adverbial and noun forms of い adjectives.
+    rule("く", "い", 0, ADJ_I, "adverbial"),
+    rule("さ", "い", 0, ADJ_I, "noun"),
+];
+
+/// Gets every possible dictionary form of `word`, starting with the word itself.
+pub fn deinflect(word: &str) -> Vec<Deinflection> {
+    let mut results = vec![Deinflection {
+        term: word.to_owned(),
+        word_types: 0,
+        reasons: Vec::new(),
+    }];
+
+    // Results are appended as they are found, so that each is deinflected further in turn.
+    let mut index = 0;
+    while index < results.len() {
+        for rule in RULES {
+            let current = &results[index];
+            if current.word_types != 0 && current.word_types & rule.types_in == 0 {
+                continue;
+            }
+            let Some(stem) = current.term.strip_suffix(rule.inflected) else {
+                continue;
+            };
+
+            let mut reasons = vec![rule.reason];
+            reasons.extend(&current.reasons);
+            let deinflection = Deinflection {
+                term: format!("{stem}{}", rule.uninflected),
+                word_types: rule.types_out,
+                reasons,
+            };
+            if !results.contains(&deinflection) {
+                results.push(deinflection);
+            }
+        }
+        index += 1;
+    }
+
+    results
+}
+
+/// Kinds of words an entry is, from the JMdict part of speech tags.
+fn entry_word_types(entry: &DictionaryEntry) -> u8 {
+    entry.tags.iter().fold(0, |types, tag| {
+        types
+            | match tag.as_str() {
+                "v1" | "v1-s" => V1,
+                "vs-i" | "vs-s" => VS,
+                "vk" => VK,
+                "adj-i" | "adj-ix" => ADJ_I,
+                tag if tag.starts_with("v5") => V5,
+                _ => 0,
+            }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let find = |word: &str, term: &str| {
+            deinflect(word)
+                .into_iter()
+                .find(|deinflection| deinflection.term == term)
+        };
+
+        let eaten = find("食べさせられた", "食べる").unwrap();
+        assert_eq!(eaten.word_types, V1);
+        assert_eq!(eaten.reasons, ["causative", "potential or passive", "past"]);
+        assert_eq!(
+            find("読んでいます", "読む").unwrap().reasons,
+            ["te", "progressive", "polite"]
+        );
+        assert_eq!(
+            find("高くなかった", "高い").unwrap().reasons,
+            ["negative", "past"]
+        );
+        assert_eq!(find("しました", "する").unwrap().word_types, VS);
+        // Past can only be the outermost inflection.
+        assert!(find("食べたた", "食べる").is_none());
+
+        let entry = |tags: &[&str]| DictionaryEntry {
+            readings: vec![],
+            gloss: String::new(),
+            tags: tags.iter().map(|tag| (*tag).to_owned()).collect(),
+            id: None,
+        };
+        assert!(eaten.matches(&entry(&["v1", "vt"])));
+        assert!(!eaten.matches(&entry(&["v5r", "vi"])));
+        assert!(!eaten.matches(&entry(&["n"])));
+    }
+}
@@ -0,0 +1,453 @@
+//! Segmentation of whole sentences into words, using the dictionaries of a [`Database`].
+//!
+//! Every substring of the sentence which is a dictionary word, as it is or once deinflected, becomes a node of a
+//! lattice, along with unknown words so that any sentence can be covered. The segmentation is the path through the
+//! lattice with the lowest cost, where each word costs the same so that longer words are preferred, common words
+//! cost less, and some parts of speech are cheaper to follow others, like particles after nouns.
+
+use std::sync::Arc;
+
+use crate::database::{
+    dictionary::{Dictionary, DictionaryEntry},
+    Database,
+};
+
+pub mod deinflect;
+
+/// Longest word looked up, in characters.
+const DEFAULT_MAX_WORD_LEN: usize = 12;
+/// Cost of every word, which makes a segmentation into fewer, longer words cheaper.
+const WORD_COST: u32 = 1000;
+/// Cost of an unknown word, which is only used where no dictionary word fits.
+const UNKNOWN_WORD_COST: u32 = 3000;
+/// Cost of each inflection removed to find a word, so that words found as they are come first.
+const INFLECTION_COST: u32 = 20;
+/// Largest amount a word's cost is lowered by when it is common.
+const MAX_FREQUENCY_BONUS: u32 = 300;
+
+/// A word of a sentence, and the dictionary entries it was found in.
+#[derive(Debug)]
+pub struct Token<D> {
+    /// The word as found in the sentence.
+    pub surface: String,
+    /// Byte offset of the word in the sentence.
+    pub start: usize,
+    /// The word as it was looked up, once deinflected.
+    pub dictionary_form: String,
+    /// Names of the inflections removed to find the dictionary form, outermost last.
+    pub inflections: Vec<&'static str>,
+    /// Matching entries of every dictionary which has some, empty for unknown words and punctuation.
+    pub entries: Vec<(Arc<D>, Vec<DictionaryEntry>)>,
+}
+
+impl<D> Token<D> {
+    /// Whether the word was found in a dictionary.
+    pub fn is_known(&self) -> bool {
+        !self.entries.is_empty()
+    }
+}
+
+/// Splits sentences into words found in the dictionaries of a database.
+pub struct Tokenizer<'a, D: Dictionary> {
+    database: &'a Database<D>,
+    max_word_len: usize,
+}
+
+/// A possible word of the sentence, spanning characters `start..end`.
+struct Node {
+    start: usize,
+    end: usize,
+    /// Index of the word in the candidates, or `None` for unknown words.
+    word: Option<usize>,
+    class: WordClass,
+    cost: u32,
+}
+
+/// A dictionary word found in the sentence.
+struct Word<D> {
+    dictionary_form: String,
+    inflections: Vec<&'static str>,
+    entries: Vec<(Arc<D>, Vec<DictionaryEntry>)>,
+}
+
+impl<'a, D: Dictionary> Tokenizer<'a, D> {
+    pub fn new(database: &'a Database<D>) -> Self {
+        Self {
+            database,
+            max_word_len: DEFAULT_MAX_WORD_LEN,
+        }
+    }
+
+    /// Sets the length of the longest word looked up, in characters.
+    pub fn with_max_word_len(mut self, max_word_len: usize) -> Self {
+        self.max_word_len = max_word_len.max(1);
+        self
+    }
+
+    /// Splits `sentence` into words, covering all of it.
+    pub fn tokenize(&self, sentence: &str) -> Vec<Token<D>> {
+        let offsets = sentence
+            .char_indices()
+            .map(|(offset, _)| offset)
+            .chain([sentence.len()])
+            .collect::<Vec<_>>();
+        let chars = sentence.chars().collect::<Vec<_>>();
+
+        let mut words = Vec::new();
+        let mut nodes = Vec::new();
+        for start in 0..chars.len() {
+            for end in start + 1..=chars.len().min(start + self.max_word_len) {
+                let surface = &sentence[offsets[start]..offsets[end]];
+                for word in self.lookup(surface) {
+                    let cost = word_cost(&word);
+                    for class in word_classes(&word) {
+                        nodes.push(Node {
+                            start,
+                            end,
+                            word: Some(words.len()),
+                            class,
+                            cost,
+                        });
+                    }
+                    words.push(word);
+                }
+            }
+
+            let end = unknown_word_end(&chars, start);
+            nodes.push(Node {
+                start,
+                end,
+                word: None,
+                class: match CharKind::of(chars[start]) {
+                    CharKind::Symbol => WordClass::Symbol,
+                    _ => WordClass::Unknown,
+                },
+                cost: UNKNOWN_WORD_COST,
+            });
+        }
+
+        let mut words = words.into_iter().map(Some).collect::<Vec<_>>();
+        best_path(&nodes, chars.len())
+            .into_iter()
+            .map(|node| {
+                let node = &nodes[node];
+                let surface = sentence[offsets[node.start]..offsets[node.end]].to_owned();
+                match node.word.and_then(|word| words[word].take()) {
+                    Some(word) => Token {
+                        surface,
+                        start: offsets[node.start],
+                        dictionary_form: word.dictionary_form,
+                        inflections: word.inflections,
+                        entries: word.entries,
+                    },
+                    None => Token {
+                        dictionary_form: surface.clone(),
+                        surface,
+                        start: offsets[node.start],
+                        inflections: Vec::new(),
+                        entries: Vec::new(),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    /// Finds the dictionary words `surface` can be, as it is or once deinflected.
+    fn lookup(&self, surface: &str) -> Vec<Word<D>> {
+        deinflect::deinflect(surface)
+            .into_iter()
+            .filter_map(|deinflection| {
+                let entries = self
+                    .database
+                    .get(&deinflection.term)
+                    .into_iter()
+                    .map(|(dictionary, entries)| {
+                        let entries = entries
+                            .into_iter()
+                            .filter(|entry| deinflection.matches(entry))
+                            .collect::<Vec<_>>();
+                        (dictionary, entries)
+                    })
+                    .filter(|(_, entries)| !entries.is_empty())
+                    .collect::<Vec<_>>();
+
+                (!entries.is_empty()).then_some(Word {
+                    dictionary_form: deinflection.term,
+                    inflections: deinflection.reasons,
+                    entries,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Finds the indices of the nodes along the cheapest path from the start of the sentence to its end.
+fn best_path(nodes: &[Node], len: usize) -> Vec<usize> {
+    let mut ending_at = vec![Vec::new(); len + 1];
+    for (index, node) in nodes.iter().enumerate() {
+        ending_at[node.end].push(index);
+    }
+
+    // Nodes are in order of their start, so the nodes before each one are done first.
+    let mut best = vec![(u32::MAX, None); nodes.len()];
+    for (index, node) in nodes.iter().enumerate() {
+        best[index] = if node.start == 0 {
+            (
+                node.cost + connection_cost(WordClass::Start, node.class),
+                None,
+            )
+        } else {
+            ending_at[node.start]
+                .iter()
+                .filter(|previous| best[**previous].0 != u32::MAX)
+                .map(|previous| {
+                    let cost = best[*previous].0
+                        + connection_cost(nodes[*previous].class, node.class)
+                        + node.cost;
+                    (cost, Some(*previous))
+                })
+                .min()
+                .unwrap_or((u32::MAX, None))
+        };
+    }
+
+    let mut node = ending_at[len]
+        .iter()
+        .filter(|node| best[**node].0 != u32::MAX)
+        .min_by_key(|node| best[**node].0 + connection_cost(nodes[**node].class, WordClass::End))
+        .copied();
+    let mut path = Vec::new();
+    while let Some(index) = node {
+        path.push(index);
+        node = best[index].1;
+    }
+    path.reverse();
+    path
+}
+
+/// Cost of a dictionary word, lower for common words.
+fn word_cost<D>(word: &Word<D>) -> u32 {
+    let frequency_bonus = word
+        .entries
+        .iter()
+        .flat_map(|(_, entries)| entries)
+        .flat_map(|entry| &entry.tags)
+        .map(|tag| frequency_bonus(tag))
+        .max()
+        .unwrap_or(0);
+    WORD_COST + INFLECTION_COST * word.inflections.len() as u32 - frequency_bonus
+}
+
+/// How much a tag lowers the cost of a word, from the JMdict priority markers.
+fn frequency_bonus(tag: &str) -> u32 {
+    match tag {
+        "news1" | "ichi1" | "spec1" | "gai1" | "P" | "common" => MAX_FREQUENCY_BONUS,
+        "news2" | "ichi2" | "spec2" | "gai2" => MAX_FREQUENCY_BONUS / 2,
+        // Ranks in the frequency list, by groups of 500 words.
+        _ => match tag
+            .strip_prefix("nf")
+            .and_then(|rank| rank.parse::<u32>().ok())
+        {
+            Some(rank @ 1..=48) => MAX_FREQUENCY_BONUS - rank * MAX_FREQUENCY_BONUS / 50,
+            _ => 0,
+        },
+    }
+}
+
+/// Broad part of speech of a word, which decides how well it follows the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum WordClass {
+    Start,
+    End,
+    Noun,
+    Verb,
+    Adjective,
+    Adverb,
+    Particle,
+    Auxiliary,
+    Prefix,
+    Suffix,
+    Conjunction,
+    Interjection,
+    Expression,
+    Symbol,
+    Unknown,
+}
+
+/// Classes of the entries of a word, from their JMdict part of speech tags, or the names used by Wiktionary.
+fn word_classes<D>(word: &Word<D>) -> Vec<WordClass> {
+    let mut classes = word
+        .entries
+        .iter()
+        .flat_map(|(_, entries)| entries)
+        .flat_map(|entry| &entry.tags)
+        .filter_map(|tag| {
+            Some(match tag.as_str() {
+                "prt" | "particle" => WordClass::Particle,
+                "aux" | "aux-v" | "aux-adj" | "cop" | "cop-da" => WordClass::Auxiliary,
+                "pref" | "prefix" => WordClass::Prefix,
+                "suf" | "n-suf" | "ctr" | "suffix" | "counter" => WordClass::Suffix,
+                "adv" | "adv-to" => WordClass::Adverb,
+                "conj" => WordClass::Conjunction,
+                "int" | "intj" => WordClass::Interjection,
+                "exp" | "phrase" => WordClass::Expression,
+                "adj-i" | "adj-ix" | "adj" => WordClass::Adjective,
+                "verb" | "vk" => WordClass::Verb,
+                tag if tag.starts_with("v1") || tag.starts_with("v5") || tag.starts_with("vs-") => {
+                    WordClass::Verb
+                }
+                "n" | "pn" | "num" | "noun" | "name" => WordClass::Noun,
+                tag if tag.starts_with("n-") || tag.starts_with("adj-") => WordClass::Noun,
+                _ => return None,
+            })
+        })
+        .collect::<Vec<_>>();
+    // Deinflected words are only ever verbs and adjectives, which behave like verbs once inflected.
+    if !word.inflections.is_empty() {
+        classes.retain(|class| matches!(class, WordClass::Verb | WordClass::Adjective));
+        classes.push(WordClass::Verb);
+    }
+    classes.sort();
+    classes.dedup();
+    if classes.is_empty() {
+        classes.push(WordClass::Noun);
+    }
+    classes
+}
+
+/// Cost of a word of class `next` following one of class `previous`.
+fn connection_cost(previous: WordClass, next: WordClass) -> u32 {
+    use WordClass::*;
+
+    const GOOD: u32 = 0;
+    const NEUTRAL: u32 = 100;
+    const BAD: u32 = 400;
+
+    match (previous, next) {
+        (Start, Particle | Auxiliary | Suffix) => BAD,
+        (Prefix, End | Particle | Auxiliary | Symbol) => BAD,
+        (Prefix, Noun) => GOOD,
+        (Noun, Particle | Auxiliary | Suffix) => GOOD,
+        (Verb | Adjective, Particle | Auxiliary | Noun | End | Symbol) => GOOD,
+        (Adjective, Verb) => GOOD,
+        (Adverb, Verb | Adjective) => GOOD,
+        (Particle, Noun | Verb | Adjective | Adverb | Prefix) => GOOD,
+        (Suffix, Particle | Auxiliary) => GOOD,
+        (Symbol, _) | (_, Symbol) => GOOD,
+        _ => NEUTRAL,
+    }
+}
+
+/// Kinds of characters, which unknown words are made of runs of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharKind {
+    Kanji,
+    Hiragana,
+    Katakana,
+    Alphanumeric,
+    Symbol,
+}
+
+impl CharKind {
+    fn of(c: char) -> Self {
+        match c {
+            'ぁ'..='ゟ' => Self::Hiragana,
+            'ァ'..='ヿ' | 'ｦ'..='ﾟ' => Self::Katakana,
+            '一'..='鿿' | '㐀'..='䶿' | '々' => Self::Kanji,
+            c if c.is_alphanumeric() => Self::Alphanumeric,
+            _ => Self::Symbol,
+        }
+    }
+}
+
+/// End of the unknown word starting at `start`.
+///
+/// Katakana and alphanumeric runs are usually a single word, such as a loanword or a name missing from the
+/// dictionaries. Other unknown characters are words of their own, so that any dictionary word right after can start.
+fn unknown_word_end(chars: &[char], start: usize) -> usize {
+    let kind = CharKind::of(chars[start]);
+    match kind {
+        CharKind::Katakana | CharKind::Alphanumeric => {
+            start
+                + chars[start..]
+                    .iter()
+                    .take_while(|c| CharKind::of(**c) == kind)
+                    .count()
+        }
+        _ => start + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::dictionary::{
+        cdb::{CDBDictionary, CDBDictionaryBuilder},
+        DictionaryBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let mut dict_builder = CDBDictionaryBuilder::new(temp_dir.path().join("words")).unwrap();
+        for (key, tags) in [
+            ("東", &["n"][..]),
+            ("東京", &["n", "news1"]),
+            ("京都", &["n", "news1"]),
+            ("都", &["n", "n-suf"]),
+            ("に", &["prt"]),
+            ("住む", &["v5m", "vi"]),
+            ("住", &["n"]),
+            ("で", &["prt"]),
+            ("い", &["n"]),
+            ("いる", &["v1", "vi", "ichi1"]),
+            ("猫", &["n", "ichi1"]),
+            ("が", &["prt"]),
+            ("魚", &["n", "ichi1"]),
+            ("を", &["prt"]),
+            ("食べる", &["v1", "vt", "ichi1"]),
+        ] {
+            dict_builder
+                .add(
+                    key,
+                    DictionaryEntry {
+                        readings: vec![],
+                        gloss: key.to_owned(),
+                        tags: tags.iter().map(|tag| (*tag).to_owned()).collect(),
+                        id: None,
+                    },
+                )
+                .unwrap();
+        }
+        let database = Database::<CDBDictionary>::new();
+        database.add_dictionary(dict_builder.build(Default::default()).unwrap());
+        let tokenizer = Tokenizer::new(&database);
+
+        let surfaces = |sentence: &str| {
+            tokenizer
+                .tokenize(sentence)
+                .into_iter()
+                .map(|token| token.surface)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            surfaces("猫が魚を食べさせられた。"),
+            ["猫", "が", "魚", "を", "食べさせられた", "。"]
+        );
+        assert_eq!(
+            surfaces("東京都に住んでいる"),
+            ["東京", "都", "に", "住んでいる"]
+        );
+        assert_eq!(surfaces("ミケが"), ["ミケ", "が"]);
+        assert!(surfaces("").is_empty());
+
+        let tokens = tokenizer.tokenize("魚を食べました");
+        assert_eq!(tokens[2].start, "魚を".len());
+        assert_eq!(tokens[2].dictionary_form, "食べる");
+        assert_eq!(tokens[2].inflections, ["polite", "past"]);
+        assert_eq!(tokens[2].entries[0].1[0].gloss, "食べる");
+        assert!(tokens.iter().all(Token::is_known));
+    }
+}
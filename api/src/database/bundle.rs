@@ -0,0 +1,395 @@
+//! Single-file bundles of pre-built dictionaries, which can be installed without importing their sources.
+//!
+//! A bundle is a tar archive starting with `manifest.json`, a [`BundleManifest`], followed by the files of every
+//! dictionary as `dictionaries/<dictionary index>/<shard index>`. Dictionaries are installed into a
+//! [`DictionaryStorage`] only once all of their files were extracted and match their checksums.
+
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    dictionary::{
+        any::AnyDictionary,
+        cdb::{self, CDBDictionary},
+        Dictionary, DictionaryMetadata, ENTRY_ENCODING_VERSION,
+    },
+    storage::{self, DictionaryStorage},
+    Database, DictionarySlot,
+};
+
+/// Version of the bundle layout, raised whenever it changes.
+pub const BUNDLE_VERSION: u32 = 1;
+
+const MANIFEST_PATH: &str = "manifest.json";
+
+/// Describes the dictionaries of a bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    bundle_version: u32,
+    /// [`ENTRY_ENCODING_VERSION`] of the bundled dictionary files.
+    entry_encoding_version: u32,
+    dictionaries: Vec<BundledDictionary>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BundledDictionary {
+    /// Metadata of the dictionary, including its tag descriptions and the checksum of its files.
+    metadata: DictionaryMetadata,
+    /// Name of the first file, which the dictionary is installed as unless the storage already has one, see [`DictionaryStorage::file_path`].
+    file_name: String,
+    /// Amount of files, see [`CDBDictionary::shard_paths`].
+    file_count: usize,
+}
+
+impl BundleManifest {
+    pub fn entry_encoding_version(&self) -> u32 {
+        self.entry_encoding_version
+    }
+
+    /// Metadata of the bundled dictionaries, in order.
+    pub fn dictionaries(&self) -> impl Iterator<Item = &DictionaryMetadata> {
+        self.dictionaries
+            .iter()
+            .map(|dictionary| &dictionary.metadata)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BundleError {
+    #[error("bundle file io error")]
+    FileIo(#[from] io::Error),
+    #[error("invalid bundle manifest: {}", .0)]
+    Manifest(#[from] serde_json::Error),
+    #[error("bundle doesn't start with a manifest")]
+    MissingManifest,
+    #[error("bundle version {} is newer than the supported version {}", .0, BUNDLE_VERSION)]
+    UnsupportedBundleVersion(u32),
+    #[error("entry encoding version {} is newer than the supported version {}", .0, ENTRY_ENCODING_VERSION)]
+    UnsupportedEntryEncoding(u32),
+    #[error("unexpected file {:?} in bundle", .0)]
+    UnexpectedFile(PathBuf),
+    #[error("invalid dictionary file name {:?} in bundle manifest", .0)]
+    InvalidFileName(String),
+    #[error("files of {:?} are missing or damaged", .0)]
+    ChecksumMismatch(String),
+}
+
+/// Writes every available imported dictionary of `database` into a bundle.
+///
+/// User dictionaries are left out, since they are stored in the configuration rather than in files.
+pub fn export_bundle<W: Write>(
+    database: &Database<AnyDictionary>,
+    writer: W,
+) -> Result<BundleManifest, BundleError> {
+    let dictionaries = database.dictionaries();
+    let dictionaries = dictionaries
+        .iter()
+        .filter_map(DictionarySlot::available)
+        .filter_map(|dictionary| dictionary.as_cdb())
+        .collect::<Vec<_>>();
+
+    let mut bundled = Vec::new();
+    for dictionary in &dictionaries {
+        let mut metadata = dictionary.get_metadata().clone();
+        // Dictionaries imported before checksums were stored get one here, so that installing can check it.
+        let checksum = storage::checksum_files(&dictionary.shard_paths())?;
+        if metadata
+            .checksum()
+            .is_some_and(|expected| expected != checksum)
+        {
+            return Err(BundleError::ChecksumMismatch(
+                metadata.display_name().to_owned(),
+            ));
+        }
+        metadata.set_checksum(Some(checksum));
+
+        bundled.push(BundledDictionary {
+            metadata,
+            file_name: dictionary
+                .path()
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("dictionary.cdb")
+                .to_owned(),
+            file_count: dictionary.shard_paths().len(),
+        });
+    }
+    let manifest = BundleManifest {
+        bundle_version: BUNDLE_VERSION,
+        entry_encoding_version: ENTRY_ENCODING_VERSION,
+        dictionaries: bundled,
+    };
+
+    let mut builder = tar::Builder::new(writer);
+    let manifest_data = serde_json::to_vec_pretty(&manifest)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(manifest_data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, MANIFEST_PATH, &manifest_data[..])?;
+    for (index, dictionary) in dictionaries.iter().enumerate() {
+        for (shard, path) in dictionary.shard_paths().iter().enumerate() {
+            builder.append_path_with_name(path, format!("dictionaries/{index}/{shard}"))?;
+        }
+    }
+    builder.into_inner()?.flush()?;
+
+    Ok(manifest)
+}
+
+/// Installs every dictionary of a bundle into `storage`, and adds them to `database`.
+///
+/// Nothing is added if any dictionary fails to install, and the files extracted so far are removed.
+pub fn install_bundle<R: Read>(
+    reader: R,
+    storage: &DictionaryStorage,
+    database: &Database<AnyDictionary>,
+) -> Result<BundleManifest, BundleError> {
+    let mut extracted = Vec::new();
+    let result = extract_bundle(reader, storage, &mut extracted);
+    let installed = result.and_then(|(manifest, paths)| {
+        let dictionaries = manifest
+            .dictionaries
+            .iter()
+            .zip(paths)
+            .map(|(bundled, path)| {
+                let dictionary = CDBDictionary::open(path, bundled.metadata.clone())?;
+                let checksum = storage::checksum_files(&dictionary.shard_paths())?;
+                if bundled.metadata.checksum() != Some(checksum.as_str()) {
+                    return Err(BundleError::ChecksumMismatch(
+                        bundled.metadata.display_name().to_owned(),
+                    ));
+                }
+                Ok(dictionary)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((manifest, dictionaries))
+    });
+
+    match installed {
+        Ok((manifest, dictionaries)) => {
+            for dictionary in dictionaries {
+                database.add_dictionary(dictionary.into());
+            }
+            Ok(manifest)
+        }
+        Err(error) => {
+            for path in extracted {
+                let _ = std::fs::remove_file(path);
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Extracts the files of a bundle into `storage`, returning the manifest and the path of every dictionary.
+///
+/// Every file created is pushed to `extracted`, even if extracting fails afterwards.
+fn extract_bundle<R: Read>(
+    reader: R,
+    storage: &DictionaryStorage,
+    extracted: &mut Vec<PathBuf>,
+) -> Result<(BundleManifest, Vec<PathBuf>), BundleError> {
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = archive.entries()?;
+
+    let mut manifest_entry = entries.next().ok_or(BundleError::MissingManifest)??;
+    if manifest_entry.path()? != Path::new(MANIFEST_PATH) {
+        return Err(BundleError::MissingManifest);
+    }
+    let mut manifest_data = Vec::new();
+    manifest_entry.read_to_end(&mut manifest_data)?;
+    let manifest: BundleManifest = serde_json::from_slice(&manifest_data)?;
+    if manifest.bundle_version > BUNDLE_VERSION {
+        return Err(BundleError::UnsupportedBundleVersion(
+            manifest.bundle_version,
+        ));
+    }
+    // Older encodings can still be decoded, but not newer ones.
+    if manifest.entry_encoding_version > ENTRY_ENCODING_VERSION {
+        return Err(BundleError::UnsupportedEntryEncoding(
+            manifest.entry_encoding_version,
+        ));
+    }
+
+    // File names come from the bundle, so anything but a plain name could point outside of the storage.
+    if let Some(dictionary) = manifest
+        .dictionaries
+        .iter()
+        .find(|dictionary| !is_plain_file_name(&dictionary.file_name))
+    {
+        return Err(BundleError::InvalidFileName(dictionary.file_name.clone()));
+    }
+    let mut paths = Vec::new();
+    for dictionary in &manifest.dictionaries {
+        let path = storage.file_path(&dictionary.file_name)?;
+        extracted.push(path.clone());
+        paths.push(path);
+    }
+    for entry in entries {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.into_owned();
+        // Only paths of the expected form are extracted, which keeps files from being written elsewhere.
+        let target = parse_file_path(&entry_path)
+            .filter(|(index, shard)| {
+                manifest
+                    .dictionaries
+                    .get(*index)
+                    .is_some_and(|dictionary| *shard < dictionary.file_count)
+            })
+            .ok_or_else(|| BundleError::UnexpectedFile(entry_path.clone()))?;
+        // The other files are named after the first one's build, so it has to come first, as it does when exported.
+        let target = match target {
+            (index, 0) => paths[index].clone(),
+            (index, shard) => cdb::read_shard_paths(&paths[index])?
+                .get(shard)
+                .cloned()
+                .ok_or(BundleError::UnexpectedFile(entry_path))?,
+        };
+
+        // The first file of each dictionary was already reserved, and is only overwritten here.
+        let mut file = File::create(&target)?;
+        if !extracted.contains(&target) {
+            extracted.push(target);
+        }
+        io::copy(&mut entry, &mut file)?;
+    }
+
+    Ok((manifest, paths))
+}
+
+/// Parses the dictionary and shard index of a path like `dictionaries/0/1`.
+fn parse_file_path(path: &Path) -> Option<(usize, usize)> {
+    let mut components = path.to_str()?.split('/');
+    if components.next()? != "dictionaries" {
+        return None;
+    }
+    let index = components.next()?.parse().ok()?;
+    let shard = components.next()?.parse().ok()?;
+    components.next().is_none().then_some((index, shard))
+}
+
+/// Whether `file_name` is a single normal path component, without any directory, `..` or root.
+fn is_plain_file_name(file_name: &str) -> bool {
+    let mut components = Path::new(file_name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::dictionary::{
+        cdb::CDBDictionaryBuilder, user::UserDictionaryBuilder, DictionaryBuilder,
+    };
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let storage = DictionaryStorage::new(temp_dir.path().join("exporter"));
+
+        let entry = |gloss: &str| crate::database::dictionary::DictionaryEntry {
+            readings: vec![],
+            gloss: gloss.to_owned(),
+            tags: vec!["n".to_owned()],
+            id: None,
+        };
+        let database = Database::new();
+        for (name, compression) in [("plain", None), ("compressed", Some(3))] {
+            let mut dict_builder =
+                CDBDictionaryBuilder::new(storage.file_path(&format!("{name}.cdb")).unwrap())
+                    .unwrap();
+            if let Some(level) = compression {
+                dict_builder = dict_builder.with_compression(level);
+            }
+            dict_builder.add("test", entry(name)).unwrap();
+            let metadata = DictionaryMetadata::builder(name)
+                .tags([("n".to_owned(), "noun".to_owned())].into())
+                .build();
+            database.add_dictionary(dict_builder.build(metadata).unwrap().into());
+        }
+        database.add_dictionary(
+            UserDictionaryBuilder::new()
+                .build(Default::default())
+                .unwrap()
+                .into(),
+        );
+
+        let mut bundle = Vec::new();
+        export_bundle(&database, &mut bundle).unwrap();
+
+        // The new storage already has a dictionary with the same file name.
+        let new_storage = DictionaryStorage::new(temp_dir.path().join("installer"));
+        std::fs::write(new_storage.file_path("plain.cdb").unwrap(), b"").unwrap();
+        let new_database = Database::new();
+        let manifest = install_bundle(&bundle[..], &new_storage, &new_database).unwrap();
+
+        assert_eq!(manifest.dictionaries().count(), 2);
+        let dictionaries = new_database.dictionaries();
+        let plain = dictionaries[0].available().unwrap().as_cdb().unwrap();
+        assert_eq!(plain.path(), new_storage.root().join("plain-1.cdb"));
+        assert_eq!(plain.get_metadata().tags()["n"], "noun");
+        assert_eq!(
+            new_database
+                .get("test")
+                .into_iter()
+                .map(|(_, entries)| entries)
+                .collect::<Vec<_>>(),
+            [vec![entry("plain")], vec![entry("compressed")]]
+        );
+
+        // A bundle whose files don't match the manifest installs nothing, and leaves no files behind.
+        let checksum = manifest.dictionaries().last().unwrap().checksum().unwrap();
+        let position = bundle
+            .windows(checksum.len())
+            .position(|window| window == checksum.as_bytes())
+            .unwrap();
+        let mut damaged = bundle.clone();
+        damaged[position] = if checksum.starts_with('0') {
+            b'1'
+        } else {
+            b'0'
+        };
+        let damaged_storage = DictionaryStorage::new(temp_dir.path().join("damaged"));
+        let damaged_database = Database::new();
+        assert!(install_bundle(&damaged[..], &damaged_storage, &damaged_database).is_err());
+        assert!(damaged_database.dictionaries().is_empty());
+        assert_eq!(
+            std::fs::read_dir(damaged_storage.root()).unwrap().count(),
+            0
+        );
+
+        // A manifest can't name files outside of the storage.
+        for file_name in [
+            "../escaped.cdb",
+            "/tmp/escaped.cdb",
+            "nested/escaped.cdb",
+            "..",
+        ] {
+            let mut manifest = manifest.clone();
+            manifest.dictionaries[0].file_name = file_name.to_owned();
+            let manifest_data = serde_json::to_vec(&manifest).unwrap();
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(manifest_data.len() as u64);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, MANIFEST_PATH, &manifest_data[..])
+                .unwrap();
+            let malicious = builder.into_inner().unwrap();
+
+            let result = install_bundle(&malicious[..], &damaged_storage, &damaged_database);
+            assert!(matches!(result, Err(BundleError::InvalidFileName(_))));
+        }
+        assert!(!temp_dir.path().join("escaped.cdb").exists());
+    }
+}
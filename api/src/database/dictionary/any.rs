@@ -0,0 +1,160 @@
+//! Dictionaries of any kind the app keeps in its database, imported or made by the user.
+
+use std::path::PathBuf;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::database::storage::{IntegrityError, StoredDictionary};
+
+use super::{
+    cdb::CDBDictionary, user::UserDictionary, Dictionary, DictionaryEntry, DictionaryMetadata,
+};
+
+/// Either an imported dictionary or one made by the user, so that both are looked up together.
+///
+/// Serialized as the dictionary it holds, which keeps databases of [`CDBDictionary`] loading as they are.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum AnyDictionary {
+    Cdb(CDBDictionary),
+    User(UserDictionary),
+}
+
+impl<'de> Deserialize<'de> for AnyDictionary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Told apart by their fields rather than by trying each, which would hide why a CDB dictionary failed to load.
+        let serialized = serde_json::Value::deserialize(deserializer)?;
+        if serialized.get("entries").is_some() {
+            UserDictionary::deserialize(serialized)
+                .map(Self::User)
+                .map_err(de::Error::custom)
+        } else {
+            CDBDictionary::deserialize(serialized)
+                .map(Self::Cdb)
+                .map_err(de::Error::custom)
+        }
+    }
+}
+
+impl AnyDictionary {
+    pub fn as_cdb(&self) -> Option<&CDBDictionary> {
+        match self {
+            Self::Cdb(dictionary) => Some(dictionary),
+            Self::User(_) => None,
+        }
+    }
+
+    pub fn as_user(&self) -> Option<&UserDictionary> {
+        match self {
+            Self::User(dictionary) => Some(dictionary),
+            Self::Cdb(_) => None,
+        }
+    }
+}
+
+impl From<CDBDictionary> for AnyDictionary {
+    fn from(dictionary: CDBDictionary) -> Self {
+        Self::Cdb(dictionary)
+    }
+}
+
+impl From<UserDictionary> for AnyDictionary {
+    fn from(dictionary: UserDictionary) -> Self {
+        Self::User(dictionary)
+    }
+}
+
+impl Dictionary for AnyDictionary {
+    fn get(&self, key: &str) -> Vec<DictionaryEntry> {
+        match self {
+            Self::Cdb(dictionary) => dictionary.get(key),
+            Self::User(dictionary) => dictionary.get(key),
+        }
+    }
+
+    fn get_metadata(&self) -> &DictionaryMetadata {
+        match self {
+            Self::Cdb(dictionary) => dictionary.get_metadata(),
+            Self::User(dictionary) => dictionary.get_metadata(),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_> {
+        match self {
+            Self::Cdb(dictionary) => dictionary.iter(),
+            Self::User(dictionary) => dictionary.iter(),
+        }
+    }
+
+    fn get_by_tags(&self, tags: &[&str]) -> Vec<(String, DictionaryEntry)> {
+        match self {
+            Self::Cdb(dictionary) => dictionary.get_by_tags(tags),
+            Self::User(dictionary) => dictionary.get_by_tags(tags),
+        }
+    }
+}
+
+impl StoredDictionary for AnyDictionary {
+    /// Paths of the files of an imported dictionary, while user dictionaries are stored inline and have none.
+    fn files(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Cdb(dictionary) => dictionary.files(),
+            Self::User(_) => Vec::new(),
+        }
+    }
+
+    fn serialized_files(serialized: &serde_json::Value) -> Vec<PathBuf> {
+        if serialized.get("entries").is_some() {
+            Vec::new()
+        } else {
+            CDBDictionary::serialized_files(serialized)
+        }
+    }
+
+    fn verify(&self) -> Result<(), IntegrityError> {
+        match self {
+            Self::Cdb(dictionary) => dictionary.verify(),
+            Self::User(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::dictionary::{
+        cdb::CDBDictionaryBuilder, user::UserDictionaryBuilder, DictionaryBuilder,
+        MutableDictionary,
+    };
+
+    use super::*;
+
+    #[test]
+    fn serialization() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let entry = DictionaryEntry {
+            readings: vec!["ゆうしゃ".to_owned()],
+            gloss: "hero".to_owned(),
+            tags: vec![],
+            id: None,
+        };
+
+        let mut cdb_builder = CDBDictionaryBuilder::new(temp_dir.path().join("any")).unwrap();
+        cdb_builder.add("勇者", entry.clone()).unwrap();
+        let cdb = AnyDictionary::from(cdb_builder.build(Default::default()).unwrap());
+        let mut user = UserDictionaryBuilder::new()
+            .build(Default::default())
+            .unwrap();
+        user.insert("勇者", entry.clone()).unwrap();
+        let user = AnyDictionary::from(user);
+
+        for dictionary in [cdb, user] {
+            let serialized = serde_json::to_string(&dictionary).unwrap();
+            let deserialized = serde_json::from_str::<AnyDictionary>(&serialized).unwrap();
+            assert_eq!(
+                deserialized.as_user().is_some(),
+                dictionary.as_user().is_some()
+            );
+            assert_eq!(deserialized.get("勇者"), vec![entry.clone()]);
+        }
+    }
+}
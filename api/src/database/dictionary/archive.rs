@@ -0,0 +1,258 @@
+//! Dictionaries stored as a memory-mapped archive, whose entries are read in place without decoding.
+//!
+//! The file is an rkyv archive of every entry grouped by key, so a lookup is a hash table probe into the
+//! mapped file, and [`ArchiveDictionary::get_views`] hands out [`EntryView`]s borrowing from it. Entries are
+//! only copied into owned [`DictionaryEntry`]s through [`EntryView::to_entry`], which [`Dictionary::get`] does.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use rkyv::Infallible;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use super::{
+    ArchivedDictionaryEntry, Dictionary, DictionaryBuilder, DictionaryEntry, DictionaryMetadata,
+};
+use crate::database::storage::{self, StoredDictionary};
+
+/// Scratch space used while serializing, before falling back to the heap.
+const SCRATCH_SPACE: usize = 4096;
+
+/// Root of an archive file.
+#[derive(rkyv::Archive, rkyv::Serialize)]
+#[archive(check_bytes)]
+struct EntryArchive {
+    /// [`DictionaryMetadata`] as JSON, since it is only read once when opening.
+    metadata: String,
+    entries: HashMap<String, Vec<DictionaryEntry>>,
+}
+
+/// Collects entries in memory, and writes the whole archive at once when built.
+pub struct ArchiveDictionaryBuilder {
+    entries: HashMap<String, Vec<DictionaryEntry>>,
+    path: PathBuf,
+}
+
+impl ArchiveDictionaryBuilder {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            entries: HashMap::new(),
+            path: path.into(),
+        }
+    }
+}
+
+impl DictionaryBuilder for ArchiveDictionaryBuilder {
+    type Dictionary = ArchiveDictionary;
+    type Error = Error;
+
+    fn add(&mut self, key: &str, entry: DictionaryEntry) -> Result<(), Self::Error> {
+        self.entries.entry(key.to_owned()).or_default().push(entry);
+        Ok(())
+    }
+
+    fn build(self, metadata: DictionaryMetadata) -> Result<Self::Dictionary, Self::Error> {
+        let archive = EntryArchive {
+            metadata: serde_json::to_string(&metadata)?,
+            entries: self.entries,
+        };
+        let bytes = rkyv::to_bytes::<_, SCRATCH_SPACE>(&archive)
+            .map_err(|e| Error::Serialize(e.to_string()))?;
+
+        // Written next to the final path first, so that a dictionary in use is never left half written.
+        let (mut file, tmp_path) = storage::create_temp_file(&self.path)?;
+        file.write_all(&bytes)?;
+        drop(file);
+        storage::persist_temp_file(tmp_path, &self.path)?;
+
+        ArchiveDictionary::open(self.path)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("archive file io error")]
+    FileIo(#[from] std::io::Error),
+    #[error("could not serialize archive: {}", .0)]
+    Serialize(String),
+    #[error("invalid archive: {}", .0)]
+    Invalid(String),
+    #[error("invalid archive metadata")]
+    Metadata(#[from] serde_json::Error),
+}
+
+/// A dictionary in a memory-mapped archive file, serialized as the path of that file.
+pub struct ArchiveDictionary {
+    mmap: Mmap,
+    path: PathBuf,
+    metadata: DictionaryMetadata,
+}
+
+impl ArchiveDictionary {
+    /// Maps an archive built by [`ArchiveDictionaryBuilder`], checking that it is valid.
+    ///
+    /// The whole archive is validated here once, so that lookups don't need to.
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        // SAFETY: the archive is never written in place, only replaced by renaming a new file over it.
+        let mmap = unsafe { Mmap::map(&file)? };
+        let archive = rkyv::check_archived_root::<EntryArchive>(&mmap)
+            .map_err(|e| Error::Invalid(e.to_string()))?;
+        let metadata = serde_json::from_str(&archive.metadata)?;
+
+        Ok(Self {
+            mmap,
+            path,
+            metadata,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the entries of `key` without copying them out of the archive.
+    pub fn get_views(&self, key: &str) -> impl ExactSizeIterator<Item = EntryView<'_>> {
+        self.archive()
+            .entries
+            .get(key)
+            .map_or(&[][..], |entries| entries.as_slice())
+            .iter()
+            .map(EntryView)
+    }
+
+    fn archive(&self) -> &ArchivedEntryArchive {
+        // SAFETY: the archive was validated when opened, and the mapping is never changed afterwards.
+        unsafe { rkyv::archived_root::<EntryArchive>(&self.mmap) }
+    }
+}
+
+impl Dictionary for ArchiveDictionary {
+    fn get(&self, key: &str) -> Vec<DictionaryEntry> {
+        self.get_views(key).map(|view| view.to_entry()).collect()
+    }
+
+    fn get_metadata(&self) -> &DictionaryMetadata {
+        &self.metadata
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_> {
+        Box::new(self.archive().entries.iter().flat_map(|(key, entries)| {
+            entries
+                .iter()
+                .map(move |entry| (key.to_string(), EntryView(entry).to_entry()))
+        }))
+    }
+}
+
+impl Serialize for ArchiveDictionary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        storage::serialize_path(&self.path, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ArchiveDictionary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = storage::deserialize_path(deserializer)?;
+        Self::open(path).map_err(de::Error::custom)
+    }
+}
+
+/// A [`DictionaryEntry`] borrowed from an [`ArchiveDictionary`].
+#[derive(Debug, Clone, Copy)]
+pub struct EntryView<'a>(&'a ArchivedDictionaryEntry);
+
+impl<'a> EntryView<'a> {
+    pub fn readings(&self) -> impl ExactSizeIterator<Item = &'a str> {
+        self.0.readings.iter().map(|reading| reading.as_str())
+    }
+
+    pub fn gloss(&self) -> &'a str {
+        &self.0.gloss
+    }
+
+    pub fn tags(&self) -> impl ExactSizeIterator<Item = &'a str> {
+        self.0.tags.iter().map(|tag| tag.as_str())
+    }
+
+    pub fn id(&self) -> Option<&'a str> {
+        self.0.id.as_ref().map(|id| id.as_str())
+    }
+
+    /// Copies the entry out of the archive.
+    pub fn to_entry(&self) -> DictionaryEntry {
+        // Deserializing into owned values can't fail.
+        rkyv::Deserialize::deserialize(self.0, &mut Infallible).unwrap()
+    }
+}
+
+impl StoredDictionary for ArchiveDictionary {
+    fn files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+
+    fn serialized_files(serialized: &serde_json::Value) -> Vec<PathBuf> {
+        storage::deserialize_path(serialized).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = temp_dir.path().join("archive-test-basic");
+
+        let test1 = DictionaryEntry {
+            readings: vec!["abc".to_owned(), "def".to_owned()],
+            gloss: "ghij".to_owned(),
+            tags: vec!["kl".to_owned()],
+            id: None,
+        };
+        let test2 = DictionaryEntry {
+            readings: vec!["mno".to_owned()],
+            gloss: "pqrs".to_owned(),
+            tags: vec![],
+            id: Some("2".to_owned()),
+        };
+
+        let mut dict_builder = ArchiveDictionaryBuilder::new(&path);
+        dict_builder.add("test", test1.clone()).unwrap();
+        dict_builder.add("test", test2.clone()).unwrap();
+        dict_builder.add("test2", test2.clone()).unwrap();
+        let metadata = DictionaryMetadata::builder("archive test basic").build();
+        let dict = dict_builder.build(metadata.clone()).unwrap();
+
+        let views = dict.get_views("test").collect::<Vec<_>>();
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[0].readings().collect::<Vec<_>>(), ["abc", "def"]);
+        assert_eq!(views[0].gloss(), "ghij");
+        assert_eq!(views[0].tags().collect::<Vec<_>>(), ["kl"]);
+        assert_eq!(views[0].id(), None);
+        assert_eq!(views[1].id(), Some("2"));
+        assert_eq!(dict.get("test"), vec![test1, test2.clone()]);
+        assert_eq!(dict.get_views("test3").len(), 0);
+        assert_eq!(dict.iter().count(), 3);
+
+        let serialized = serde_json::to_string(&dict).unwrap();
+        drop(dict);
+        let dict = serde_json::from_str::<ArchiveDictionary>(&serialized).unwrap();
+        assert_eq!(dict.get_metadata(), &metadata);
+        assert_eq!(dict.get("test2"), vec![test2]);
+
+        std::fs::write(&path, b"not an archive").unwrap();
+        assert!(matches!(
+            ArchiveDictionary::open(&path),
+            Err(Error::Invalid(_))
+        ));
+    }
+}
@@ -1,26 +1,370 @@
-use std::path::PathBuf;
-
-use cdb::{CDBWriter, CDB};
-use serde::{
-    de::{self, Visitor},
-    ser, Deserialize, Deserializer, Serialize, Serializer,
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
 };
+
+use cdb::{CDBMake, CDB};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tempfile::TempPath;
 use thiserror::Error;
+use zstd::{bulk::Compressor, dict::DecoderDictionary};
+
+use super::{
+    Dictionary, DictionaryBuilder, DictionaryEntry, DictionaryMetadata, EncodedEntry, EntryEncoder,
+};
+use crate::database::storage::{self, StoredDictionary};
 
-use super::{Dictionary, DictionaryBuilder, DictionaryEntry, DictionaryMetadata};
+/// Key of the zstd dictionary of compressed CDBs. Keys starting with `\0` are reserved and never hold entries.
+const ZSTD_DICTIONARY_KEY: &[u8] = b"\0zstd-dictionary";
+/// Amount of record data sampled to train the zstd dictionary.
+const TRAINING_SAMPLES_LEN: usize = 8 * 1024 * 1024;
+/// Largest size of the trained zstd dictionary.
+const ZSTD_DICTIONARY_LEN: usize = 112 * 1024;
+/// Key of the names of every tag, which records refer to by index.
+const TAG_TABLE_KEY: &[u8] = b"\0tags";
+/// Prefix of the keys listing the keys of the entries with a tag, followed by the tag's index as u16 little endian.
+const TAG_INDEX_KEY_PREFIX: &[u8] = b"\0tag-index:";
+/// Key of the amount of shards, in the first shard of dictionaries split into several.
+const SHARD_COUNT_KEY: &[u8] = b"\0shards";
+/// Key of the name of the build which made the other shards, in the first shard of dictionaries split into several.
+const SHARD_BUILD_KEY: &[u8] = b"\0shard-build";
+/// Largest size of a shard. CDB offsets are 32 bits, and some room is left for the shard count.
+const SHARD_LEN_LIMIT: u64 = u32::MAX as u64 - 1024;
+/// Amount of shards past which a dictionary isn't split further, since records must be sharing a key.
+const MAX_SHARDS: usize = 1024;
+/// Size of the table of hash table offsets at the start of a CDB.
+const CDB_HEADER_LEN: u64 = 2048;
 
+/// Builds a [`CDBDictionary`] in temporary files next to its path, which replace any previous files only once
+/// everything is written and flushed to disk.
+///
+/// The temporary files are removed if the builder is dropped or fails, so an interrupted import never leaves a
+/// partial dictionary behind, nor damages the one it was going to replace.
 pub struct CDBDictionaryBuilder {
-    cdb_writer: CDBWriter,
+    shards: Vec<ShardBuilder>,
     path: PathBuf,
+    compression: Compression,
+    tags: TagTableBuilder,
+    /// Compression level and trained zstd dictionary, shared with the encoders once records are compressed.
+    zstd_dictionary: Arc<OnceLock<(i32, Vec<u8>)>>,
+    shard_len_limit: u64,
+}
+
+/// Interns the tags of the entries added so far, and remembers which keys have entries with each tag.
+#[derive(Default)]
+struct TagTableBuilder {
+    /// Shared with the encoders, which only read it.
+    ids: Arc<RwLock<HashMap<String, u16>>>,
+    names: Vec<String>,
+    keys: Vec<BTreeSet<String>>,
+}
+
+impl TagTableBuilder {
+    /// Encodes `entry` with its tags replaced by their index in the table.
+    fn encode(&mut self, key: &str, entry: DictionaryEntry) -> io::Result<Vec<u8>> {
+        let tags = entry
+            .tags
+            .iter()
+            .map(|tag| self.intern(key, tag))
+            .collect::<io::Result<_>>()?;
+
+        Ok(CompactEntry::encode(entry, tags))
+    }
+
+    fn intern(&mut self, key: &str, tag: &str) -> io::Result<u16> {
+        let id = self.ids.read().unwrap().get(tag).copied();
+        let id = match id {
+            Some(id) => id,
+            None => {
+                let id = u16::try_from(self.names.len()).map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "too many distinct tags")
+                })?;
+                self.ids.write().unwrap().insert(tag.to_owned(), id);
+                self.names.push(tag.to_owned());
+                self.keys.push(BTreeSet::new());
+                id
+            }
+        };
+        self.index(key, id);
+        Ok(id)
+    }
+
+    /// Remembers that `key` has an entry with the tag `id`.
+    fn index(&mut self, key: &str, id: u16) {
+        if !self.keys[id as usize].contains(key) {
+            self.keys[id as usize].insert(key.to_owned());
+        }
+    }
+}
+
+/// Encoding of entries in a dictionary with a tag table, whose tags are indices into the table.
+#[derive(bitcode::Encode, bitcode::Decode)]
+struct CompactEntry {
+    readings: Vec<String>,
+    gloss: String,
+    tags: Vec<u16>,
+    id: Option<String>,
+}
+
+impl CompactEntry {
+    /// Encodes `entry` with `tags` as the indices of its tags.
+    fn encode(entry: DictionaryEntry, tags: Vec<u16>) -> Vec<u8> {
+        let entry = Self {
+            readings: entry.readings,
+            gloss: entry.gloss,
+            tags,
+            id: entry.id,
+        };
+        bitcode::encode(&entry).unwrap()
+    }
+}
+
+/// An entry encoded by the encoder of a [`CDBDictionaryBuilder`], see [`DictionaryBuilder::encoder`].
+pub struct EncodedRecord {
+    data: Vec<u8>,
+    /// Indices of the entry's tags, for the builder's tag index.
+    tags: Vec<u16>,
+    /// Whether `data` is already compressed with the builder's zstd dictionary.
+    compressed: bool,
+}
+
+/// Encodes and compresses entries for a [`CDBDictionaryBuilder`] with the tags and zstd dictionary it has so far.
+///
+/// Only the builder adds tags, in the order entries are added, so records are the same whichever thread encoded
+/// them. Entries with a tag it hasn't seen yet are left for it to encode, as are the records written before the
+/// zstd dictionary is trained left for it to compress.
+struct CDBEntryEncoder {
+    tag_ids: Arc<RwLock<HashMap<String, u16>>>,
+    zstd_dictionary: Arc<OnceLock<(i32, Vec<u8>)>>,
+    /// Compressors not in use by any thread, since each holds its own copy of the zstd dictionary.
+    compressors: Mutex<Vec<Compressor<'static>>>,
+}
+
+impl CDBEntryEncoder {
+    fn compress(&self, level: i32, dictionary: &[u8], data: &[u8]) -> io::Result<Vec<u8>> {
+        let compressor = self.compressors.lock().unwrap().pop();
+        let mut compressor = match compressor {
+            Some(compressor) => compressor,
+            None => Compressor::with_dictionary(level, dictionary)?,
+        };
+        let data = compressor.compress(data)?;
+        self.compressors.lock().unwrap().push(compressor);
+        Ok(data)
+    }
+}
+
+impl EntryEncoder for CDBEntryEncoder {
+    fn encode(&self, entry: DictionaryEntry) -> EncodedEntry {
+        let tags = {
+            let tag_ids = self.tag_ids.read().unwrap();
+            entry
+                .tags
+                .iter()
+                .map(|tag| tag_ids.get(tag).copied())
+                .collect::<Option<Vec<_>>>()
+        };
+        let Some(tags) = tags else {
+            return EncodedEntry::Entry(entry);
+        };
+
+        let mut record = EncodedRecord {
+            data: CompactEntry::encode(entry, tags.clone()),
+            tags,
+            compressed: false,
+        };
+        if let Some((level, dictionary)) = self.zstd_dictionary.get() {
+            // On failure the builder compresses the record itself, and reports the error.
+            if let Ok(data) = self.compress(*level, dictionary, &record.data) {
+                record.data = data;
+                record.compressed = true;
+            }
+        }
+        EncodedEntry::Record(record)
+    }
+}
+
+/// One of the CDB files a dictionary is split into once it outgrows a single one.
+struct ShardBuilder {
+    cdb_make: CDBMake,
+    // Entries are written here first, and only moved to the shard's path once the dictionary is built.
+    tmp_path: TempPath,
+    /// Size the shard will have once finished.
+    len: u64,
+}
+
+impl ShardBuilder {
+    /// Creates a shard in a temporary file, uniquely named so that builds for the same path can't clash.
+    fn create(path: &Path) -> io::Result<Self> {
+        let (file, tmp_path) = storage::create_temp_file(path)?;
+
+        Ok(Self {
+            cdb_make: CDBMake::new(file)?,
+            tmp_path,
+            len: CDB_HEADER_LEN,
+        })
+    }
+
+    /// Writes the shard's hash tables and flushes it to disk, returning the temporary file to move into place.
+    fn finish(self) -> io::Result<TempPath> {
+        self.cdb_make.finish()?;
+        File::open(&self.tmp_path)?.sync_all()?;
+        Ok(self.tmp_path)
+    }
+}
+
+enum Compression {
+    None,
+    /// Records are held back until there are enough of them to train a dictionary on.
+    Training {
+        level: i32,
+        pending: Vec<(String, Vec<u8>)>,
+        pending_len: usize,
+    },
+    Compressing(Compressor<'static>),
 }
 
 impl CDBDictionaryBuilder {
-    pub fn new<P: Into<PathBuf> + ToString>(path: P) -> Result<Self, std::io::Error> {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self, std::io::Error> {
+        let path = path.into();
+
         Ok(Self {
-            cdb_writer: CDBWriter::create(path.to_string())?,
-            path: path.into(),
+            shards: vec![ShardBuilder::create(&path)?],
+            path,
+            compression: Compression::None,
+            tags: TagTableBuilder::default(),
+            zstd_dictionary: Arc::default(),
+            shard_len_limit: SHARD_LEN_LIMIT,
         })
     }
+
+    /// Compresses every record with zstd at `level`, using a dictionary trained on the first records.
+    ///
+    /// Glosses and tags repeat a lot between entries, which a shared dictionary captures even though
+    /// each record is compressed on its own. Reading compressed dictionaries needs no configuration.
+    pub fn with_compression(mut self, level: i32) -> Self {
+        self.compression = Compression::Training {
+            level,
+            pending: Vec::new(),
+            pending_len: 0,
+        };
+        self
+    }
+
+    /// Splits the dictionary into shards of at most `limit` bytes, so that tests can shard small dictionaries.
+    #[cfg(test)]
+    pub(crate) fn with_shard_len_limit(mut self, limit: u64) -> Self {
+        self.shard_len_limit = limit;
+        self
+    }
+
+    fn write(&mut self, key: &str, data: &[u8]) -> Result<(), CDBDictionaryBuilderError> {
+        match &mut self.compression {
+            Compression::None => {}
+            Compression::Training {
+                pending,
+                pending_len,
+                ..
+            } => {
+                pending.push((key.to_owned(), data.to_vec()));
+                *pending_len += data.len();
+                if *pending_len >= TRAINING_SAMPLES_LEN {
+                    self.train()?;
+                }
+                return Ok(());
+            }
+            Compression::Compressing(compressor) => {
+                let data = compressor.compress(data)?;
+                return Ok(self.add_record(key.as_bytes(), &data)?);
+            }
+        }
+
+        Ok(self.add_record(key.as_bytes(), data)?)
+    }
+
+    /// Trains the zstd dictionary on the records held back so far, and writes them compressed.
+    fn train(&mut self) -> Result<(), CDBDictionaryBuilderError> {
+        let Compression::Training { level, pending, .. } =
+            std::mem::replace(&mut self.compression, Compression::None)
+        else {
+            return Ok(());
+        };
+
+        // Training fails with too few samples, in which case records are compressed without a dictionary.
+        let samples = pending.iter().map(|(_, data)| data).collect::<Vec<_>>();
+        let dictionary =
+            zstd::dict::from_samples(&samples, ZSTD_DICTIONARY_LEN).unwrap_or_default();
+        self.add_record(ZSTD_DICTIONARY_KEY, &dictionary)?;
+
+        let mut compressor = Compressor::with_dictionary(level, &dictionary)?;
+        for (key, data) in pending {
+            self.add_record(key.as_bytes(), &compressor.compress(&data)?)?;
+        }
+        self.compression = Compression::Compressing(compressor);
+        let _ = self.zstd_dictionary.set((level, dictionary));
+
+        Ok(())
+    }
+
+    /// Adds a record to the shard of its key, first splitting the dictionary into more shards if that one is full.
+    fn add_record(&mut self, key: &[u8], data: &[u8]) -> io::Result<()> {
+        let len = record_len(key, data);
+        if len > self.shard_len_limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "record is too large for a CDB",
+            ));
+        }
+
+        let mut index = shard_index(key, self.shards.len());
+        while self.shards[index].len + len > self.shard_len_limit {
+            self.reshard()?;
+            index = shard_index(key, self.shards.len());
+        }
+        let shard = &mut self.shards[index];
+        shard.cdb_make.add(key, data)?;
+        shard.len += len;
+
+        Ok(())
+    }
+
+    /// Doubles the amount of shards, moving every record written so far to its new shard.
+    ///
+    /// Every key of a new shard comes from the same old shard, so the new shards can't exceed the limit.
+    /// Records keep their order, which keeps the entries of a key in the order they were added.
+    fn reshard(&mut self) -> io::Result<()> {
+        if self.shards.len() >= MAX_SHARDS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too many records share the same CDB shard",
+            ));
+        }
+
+        let mut old_paths = Vec::new();
+        for shard in std::mem::take(&mut self.shards) {
+            shard.cdb_make.finish()?;
+            old_paths.push(shard.tmp_path);
+        }
+
+        let count = old_paths.len() * 2;
+        self.shards = (0..count)
+            .map(|index| ShardBuilder::create(&shard_path(&self.path, None, index)))
+            .collect::<io::Result<_>>()?;
+        for old_path in old_paths {
+            for record in CDB::open(&old_path)?.iter() {
+                let (key, data) = record?;
+                let shard = &mut self.shards[shard_index(&key, count)];
+                shard.cdb_make.add(&key, &data)?;
+                shard.len += record_len(&key, &data);
+            }
+            old_path.close()?;
+        }
+
+        Ok(())
+    }
 }
 
 impl DictionaryBuilder for CDBDictionaryBuilder {
@@ -28,17 +372,92 @@ impl DictionaryBuilder for CDBDictionaryBuilder {
     type Error = CDBDictionaryBuilderError;
 
     fn add(&mut self, key: &str, entry: DictionaryEntry) -> Result<(), Self::Error> {
-        self.cdb_writer
-            .add(key.as_bytes(), &entry.serialize_fast())
-            .map_err(Self::Error::CDBFileIo)
+        let data = self.tags.encode(key, entry)?;
+        self.write(key, &data)
     }
 
-    fn build(self, metadata: DictionaryMetadata) -> Result<Self::Dictionary, Self::Error> {
-        self.cdb_writer.finish()?;
-        Ok(Self::Dictionary {
-            cdb_pathbuf: (CDB::open(&self.path)?, self.path),
-            metadata,
-        })
+    fn encoder(&self) -> Option<Arc<dyn EntryEncoder>> {
+        Some(Arc::new(CDBEntryEncoder {
+            tag_ids: self.tags.ids.clone(),
+            zstd_dictionary: self.zstd_dictionary.clone(),
+            compressors: Mutex::default(),
+        }))
+    }
+
+    fn add_encoded(&mut self, key: &str, entry: EncodedEntry) -> Result<(), Self::Error> {
+        let record = match entry {
+            EncodedEntry::Entry(entry) => return self.add(key, entry),
+            EncodedEntry::Record(record) => record,
+        };
+        for id in record.tags {
+            self.tags.index(key, id);
+        }
+        if record.compressed {
+            Ok(self.add_record(key.as_bytes(), &record.data)?)
+        } else {
+            self.write(key, &record.data)
+        }
+    }
+
+    fn build(mut self, mut metadata: DictionaryMetadata) -> Result<Self::Dictionary, Self::Error> {
+        self.train()?;
+        // Written even without tags, since its presence tells that records refer to it.
+        let tags = std::mem::take(&mut self.tags);
+        self.add_record(TAG_TABLE_KEY, &bitcode::encode(&tags.names).unwrap())?;
+        for (id, keys) in tags.keys.into_iter().enumerate() {
+            let keys = keys.into_iter().collect::<Vec<_>>();
+            let data = bitcode::encode(&keys).unwrap();
+            self.add_record(&tag_index_key(id as u16), &data)?;
+        }
+        let count = self.shards.len();
+        let mut shards = self.shards.into_iter();
+        let mut first = shards.next().expect("a dictionary has at least one shard");
+        // The other shards go under a build name of their own, so that they can't replace any file of the
+        // dictionary at the path until the first shard does, which tells readers where they are.
+        let others = shards
+            .map(ShardBuilder::finish)
+            .collect::<io::Result<Vec<_>>>()?;
+        let (build, mut paths) = persist_shards(&self.path, others)?;
+        let switched = (|| {
+            if let Some(build) = &build {
+                // Added last, once the amount of shards can't change anymore. The limit leaves room for them.
+                first
+                    .cdb_make
+                    .add(SHARD_COUNT_KEY, &(count as u32).to_le_bytes())?;
+                first.cdb_make.add(SHARD_BUILD_KEY, build.as_bytes())?;
+            }
+            let first = first.finish()?;
+            let stale = read_shard_paths(&self.path).unwrap_or_default();
+            first.persist(&self.path).map_err(|error| error.error)?;
+            storage::sync_parent_dir(&self.path)?;
+            Ok::<_, io::Error>(stale)
+        })();
+        let stale = match switched {
+            Ok(stale) => stale,
+            Err(error) => {
+                for path in paths {
+                    let _ = std::fs::remove_file(path);
+                }
+                return Err(error.into());
+            }
+        };
+        paths.insert(0, self.path.clone());
+        // The shards of the replaced dictionary, which a crash before here leaves to the storage's cleanup.
+        for path in stale.iter().skip(1).filter(|path| !paths.contains(path)) {
+            let _ = std::fs::remove_file(path);
+        }
+        // The metadata is stored apart from the files, so it can tell whether they were damaged.
+        metadata.set_checksum(Some(storage::checksum_files(&paths)?));
+
+        Ok(CDBDictionary::open(self.path, metadata)?)
+    }
+
+    fn discard(self) -> Result<(), Self::Error> {
+        for shard in self.shards {
+            drop(shard.cdb_make);
+            shard.tmp_path.close()?;
+        }
+        Ok(())
     }
 }
 
@@ -48,65 +467,386 @@ pub enum CDBDictionaryBuilderError {
     CDBFileIo(#[from] std::io::Error),
 }
 
+/// A dictionary in one or more CDB files, serialized as the path of the first one.
+///
+/// Lookups only read the files, so a dictionary can be shared between threads and queried from all of them at once.
 #[derive(Serialize, Deserialize)]
 pub struct CDBDictionary {
-    #[serde(serialize_with = "serialize_cdb", deserialize_with = "deserialize_cdb")]
-    cdb_pathbuf: (CDB, PathBuf),
+    #[serde(
+        rename = "cdb_pathbuf",
+        serialize_with = "serialize_shards",
+        deserialize_with = "deserialize_shards"
+    )]
+    shards_paths: (Vec<CDB>, Vec<PathBuf>),
     metadata: DictionaryMetadata,
+    /// Dictionary which records were compressed with, or `None` if they are not compressed.
+    #[serde(skip)]
+    zstd_dictionary: OnceLock<Option<DecoderDictionary<'static>>>,
+    /// Names of the tags which records refer to, or `None` for older dictionaries whose records hold the names.
+    #[serde(skip)]
+    tag_table: OnceLock<Option<Vec<String>>>,
+}
+
+// Databases are shared by every window and server of the app.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CDBDictionary>();
+};
+
+impl CDBDictionary {
+    /// Opens a dictionary built by [`CDBDictionaryBuilder`] at `path`, whose metadata was kept apart from it.
+    pub fn open<P: Into<PathBuf>>(path: P, metadata: DictionaryMetadata) -> io::Result<Self> {
+        let path = path.into();
+        Ok(Self {
+            shards_paths: open_shards(&path)?,
+            metadata,
+            zstd_dictionary: OnceLock::new(),
+            tag_table: OnceLock::new(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.shards_paths.1[0]
+    }
+
+    /// Paths of every file the dictionary is stored in, starting with [`CDBDictionary::path`].
+    pub fn shard_paths(&self) -> Vec<PathBuf> {
+        self.shards_paths.1.clone()
+    }
+
+    fn decode(&self, data: &[u8]) -> Option<DictionaryEntry> {
+        let zstd_dictionary = self.zstd_dictionary.get_or_init(|| {
+            let dictionary = self.shards_paths.0[0]
+                .find(ZSTD_DICTIONARY_KEY)
+                .next()?
+                .ok()?;
+            Some(DecoderDictionary::copy(&dictionary))
+        });
+
+        let data = match zstd_dictionary {
+            Some(zstd_dictionary) => {
+                let mut decompressed = Vec::new();
+                zstd::stream::Decoder::with_prepared_dictionary(data, zstd_dictionary)
+                    .ok()?
+                    .read_to_end(&mut decompressed)
+                    .ok()?;
+                Cow::Owned(decompressed)
+            }
+            None => Cow::Borrowed(data),
+        };
+
+        match self.tag_table() {
+            Some(tag_table) => {
+                let entry: CompactEntry = bitcode::decode(&data).ok()?;
+                Some(DictionaryEntry {
+                    readings: entry.readings,
+                    gloss: entry.gloss,
+                    tags: entry
+                        .tags
+                        .into_iter()
+                        .map(|id| tag_table.get(id as usize).cloned())
+                        .collect::<Option<_>>()?,
+                    id: entry.id,
+                })
+            }
+            None => Some(DictionaryEntry::deserialize_fast(&data)),
+        }
+    }
+
+    fn tag_table(&self) -> Option<&[String]> {
+        self.tag_table
+            .get_or_init(|| {
+                let tag_table = self.shards_paths.0[0].find(TAG_TABLE_KEY).next()?.ok()?;
+                bitcode::decode(&tag_table).ok()
+            })
+            .as_deref()
+    }
+
+    /// Keys of the entries with the tag at `id` in the tag table.
+    fn tagged_keys(&self, id: u16) -> Vec<String> {
+        self.shards_paths.0[0]
+            .find(&tag_index_key(id))
+            .next()
+            .and_then(Result::ok)
+            .and_then(|keys| bitcode::decode(&keys).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Path of the shard at `index` made by `build`. The first shard is at the dictionary's own path, like an
+/// unsharded dictionary, and the others are named after their build, or only their index for older dictionaries.
+fn shard_path(path: &Path, build: Option<&str>, index: usize) -> PathBuf {
+    if index == 0 {
+        return path.to_owned();
+    }
+    let mut shard_path = path.as_os_str().to_owned();
+    match build {
+        Some(build) => shard_path.push(format!(".{build}.{index}")),
+        None => shard_path.push(format!(".{index}")),
+    }
+    shard_path.into()
+}
+
+/// Path of the first shard, and the files next to it which are named like its other shards.
+///
+/// Only the first shard tells which the others are, so this is for when it's missing or damaged.
+fn sibling_shard_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![path.to_owned()];
+    let (Some(dir), Some(name)) = (
+        path.parent(),
+        path.file_name().and_then(|name| name.to_str()),
+    ) else {
+        return paths;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return paths;
+    };
+
+    let is_shard_suffix = |suffix: &str| {
+        let parts = suffix.split('.').collect::<Vec<_>>();
+        let is_index = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        match parts[..] {
+            [index] => is_index(index),
+            [build, index] => {
+                !build.is_empty()
+                    && build.bytes().all(|b| b.is_ascii_alphanumeric())
+                    && is_index(index)
+            }
+            _ => false,
+        }
+    };
+    for entry in entries.flatten() {
+        let file_name = entry.file_name();
+        let is_shard = file_name
+            .to_str()
+            .and_then(|file_name| file_name.strip_prefix(name)?.strip_prefix('.'))
+            .is_some_and(is_shard_suffix);
+        if is_shard {
+            paths.push(entry.path());
+        }
+    }
+    paths[1..].sort();
+    paths
+}
+
+/// Moves the shards after the first into place, under the first build name which no file uses yet.
+///
+/// Returns the build name and the paths of the shards, or `None` and no paths if there are none.
+fn persist_shards(
+    path: &Path,
+    tmp_paths: Vec<TempPath>,
+) -> io::Result<(Option<String>, Vec<PathBuf>)> {
+    let mut tmp_paths = tmp_paths.into_iter();
+    let Some(mut second) = tmp_paths.next() else {
+        return Ok((None, Vec::new()));
+    };
+
+    // Claiming the second shard's name reserves the build name, even against concurrent builds.
+    let mut build = 1u32;
+    let build = loop {
+        let name = build.to_string();
+        match second.persist_noclobber(shard_path(path, Some(&name), 1)) {
+            Ok(()) => break name,
+            Err(error) if error.error.kind() == io::ErrorKind::AlreadyExists => {
+                second = error.path;
+                build += 1;
+            }
+            Err(error) => return Err(error.error),
+        }
+    };
+
+    let mut paths = vec![shard_path(path, Some(&build), 1)];
+    for (index, tmp_path) in (2..).zip(tmp_paths) {
+        let shard_path = shard_path(path, Some(&build), index);
+        if let Err(error) = tmp_path.persist(&shard_path) {
+            for path in paths {
+                let _ = std::fs::remove_file(path);
+            }
+            return Err(error.error);
+        }
+        paths.push(shard_path);
+    }
+    Ok((Some(build), paths))
 }
 
-fn serialize_cdb<S>(cdb_pathbuf: &(CDB, PathBuf), serializer: S) -> Result<S::Ok, S::Error>
+/// Paths of every shard of the dictionary at `path`, read from its first shard.
+pub(crate) fn read_shard_paths(path: &Path) -> io::Result<Vec<PathBuf>> {
+    shard_paths(path, &CDB::open(path)?)
+}
+
+/// Paths of every shard of the dictionary at `path`, whose first shard is `first`.
+fn shard_paths(path: &Path, first: &CDB) -> io::Result<Vec<PathBuf>> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+    let count = match first.find(SHARD_COUNT_KEY).next() {
+        Some(count) => u32::from_le_bytes(
+            count?
+                .try_into()
+                .map_err(|_| invalid("invalid CDB shard count"))?,
+        ) as usize,
+        None => 1,
+    };
+    let build = match first.find(SHARD_BUILD_KEY).next() {
+        // Bundles come from elsewhere, and the name must not lead out of the dictionary's folder.
+        Some(build) => Some(
+            String::from_utf8(build?)
+                .ok()
+                .filter(|build| build.bytes().all(|byte| byte.is_ascii_alphanumeric()))
+                .ok_or_else(|| invalid("invalid CDB shard build"))?,
+        ),
+        None => None,
+    };
+
+    Ok((0..count)
+        .map(|index| shard_path(path, build.as_deref(), index))
+        .collect())
+}
+
+fn tag_index_key(id: u16) -> Vec<u8> {
+    [TAG_INDEX_KEY_PREFIX, &id.to_le_bytes()].concat()
+}
+
+/// Index of the shard holding `key`. Reserved keys are always in the first shard.
+fn shard_index(key: &[u8], shards: usize) -> usize {
+    if key.first() == Some(&0) {
+        return 0;
+    }
+    // FNV-1a, which unlike the standard library's hasher is guaranteed to stay the same between builds.
+    let hash = key.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % shards as u64) as usize
+}
+
+/// Space a record takes in a CDB: its header, key and data, and its two hash table slots.
+fn record_len(key: &[u8], data: &[u8]) -> u64 {
+    24 + key.len() as u64 + data.len() as u64
+}
+
+fn open_shards(path: &Path) -> io::Result<(Vec<CDB>, Vec<PathBuf>)> {
+    let first = CDB::open(path)?;
+    let paths = shard_paths(path, &first)?;
+
+    let mut shards = vec![first];
+    for path in &paths[1..] {
+        shards.push(CDB::open(path)?);
+    }
+    Ok((shards, paths))
+}
+
+fn serialize_shards<S>(
+    shards_paths: &(Vec<CDB>, Vec<PathBuf>),
+    serializer: S,
+) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    serializer.serialize_str(
-        cdb_pathbuf
-            .1
-            .to_str()
-            .ok_or(ser::Error::custom("path contains invalid UTF-8 characters"))?,
-    )
+    storage::serialize_path(&shards_paths.1[0], serializer)
 }
 
-fn deserialize_cdb<'de, D>(deserializer: D) -> Result<(CDB, PathBuf), D::Error>
+fn deserialize_shards<'de, D>(deserializer: D) -> Result<(Vec<CDB>, Vec<PathBuf>), D::Error>
 where
     D: Deserializer<'de>,
 {
-    struct CDBPathBufVisitor;
-
-    impl<'de> Visitor<'de> for CDBPathBufVisitor {
-        type Value = (CDB, PathBuf);
-
-        fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-            formatter.write_str("a path to a CDB")
-        }
+    let pathbuf = storage::deserialize_path(deserializer)?;
+    open_shards(&pathbuf).map_err(de::Error::custom)
+}
 
-        fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
-        where
-            E: de::Error,
-        {
-            let pathbuf = PathBuf::from(s);
-            let cdb = CDB::open(&pathbuf).map_err(de::Error::custom)?;
+pub(crate) fn serialize_cdb<S>(
+    cdb_pathbuf: &(CDB, PathBuf),
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    storage::serialize_path(&cdb_pathbuf.1, serializer)
+}
 
-            Ok((cdb, pathbuf))
-        }
-    }
+pub(crate) fn deserialize_cdb<'de, D>(deserializer: D) -> Result<(CDB, PathBuf), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pathbuf = storage::deserialize_path(deserializer)?;
+    let cdb = CDB::open(&pathbuf).map_err(de::Error::custom)?;
 
-    deserializer.deserialize_str(CDBPathBufVisitor)
+    Ok((cdb, pathbuf))
 }
 
 impl Dictionary for CDBDictionary {
     fn get(&self, key: &str) -> Vec<DictionaryEntry> {
-        self.cdb_pathbuf
-            .0
+        let shards = &self.shards_paths.0;
+        shards[shard_index(key.as_bytes(), shards.len())]
             .find(key.as_bytes())
             .filter_map(Result::ok)
-            .map(|v| DictionaryEntry::deserialize_fast(&v))
+            .filter_map(|v| self.decode(&v))
             .collect()
     }
 
     fn get_metadata(&self) -> &DictionaryMetadata {
         &self.metadata
     }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_> {
+        Box::new(
+            self.shards_paths
+                .0
+                .iter()
+                .flat_map(CDB::iter)
+                .filter_map(Result::ok)
+                .filter(|(key, _)| key.first() != Some(&0))
+                .filter_map(|(key, value)| {
+                    let key = String::from_utf8(key).ok()?;
+                    Some((key, self.decode(&value)?))
+                }),
+        )
+    }
+
+    fn get_by_tags(&self, tags: &[&str]) -> Vec<(String, DictionaryEntry)> {
+        let Some(tag_table) = self.tag_table().filter(|_| !tags.is_empty()) else {
+            return self
+                .iter()
+                .filter(|(_, entry)| entry.has_tags(tags))
+                .collect();
+        };
+
+        // Only the keys of the rarest tag need to be looked up, and their entries checked for the other tags.
+        let mut tagged_keys = Vec::new();
+        for tag in tags {
+            let Some(id) = tag_table.iter().position(|name| name == tag) else {
+                return Vec::new();
+            };
+            tagged_keys.push(self.tagged_keys(id as u16));
+        }
+
+        tagged_keys
+            .into_iter()
+            .min_by_key(Vec::len)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|key| {
+                self.get(&key)
+                    .into_iter()
+                    .filter(|entry| entry.has_tags(tags))
+                    .map(move |entry| (key.clone(), entry))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
+
+impl StoredDictionary for CDBDictionary {
+    fn files(&self) -> Vec<PathBuf> {
+        self.shard_paths()
+    }
+
+    fn serialized_files(serialized: &serde_json::Value) -> Vec<PathBuf> {
+        let Some(path) = serialized
+            .get("cdb_pathbuf")
+            .and_then(|path| storage::deserialize_path(path).ok())
+        else {
+            return Vec::new();
+        };
+        read_shard_paths(&path).unwrap_or_else(|_| sibling_shard_paths(&path))
+    }
 }
 
 #[cfg(test)]
@@ -133,30 +873,200 @@ mod tests {
             readings: vec!["abc".to_owned()],
             gloss: "defg".to_owned(),
             tags: vec!["hi".to_owned(), "jk".to_owned()],
+            id: None,
         };
 
         let test2 = DictionaryEntry {
             readings: vec!["lmn".to_owned()],
             gloss: "opqr".to_owned(),
             tags: vec!["st".to_owned(), "uv".to_owned()],
+            id: Some("2".to_owned()),
         };
 
         cdb_dict_builder.add("test1", test1.clone()).unwrap();
         cdb_dict_builder.add("test2", test2.clone()).unwrap();
 
-        let metadata = DictionaryMetadata {
-            name: "cdb test basic".to_owned(),
-            author: "tomokao".to_owned(),
-            version: Version::new(1, 2, 3),
-            homepage_url: Some(Url::parse("https://github.com/tomokao/yomisama").unwrap()),
-            update_url: None,
-            notes: "this is a test dictionary".to_owned(),
-        };
+        let metadata = DictionaryMetadata::builder("cdb test basic")
+            .author("tomokao")
+            .version(Version::new(1, 2, 3))
+            .homepage_url(Some(
+                Url::parse("https://github.com/tomokao/yomisama").unwrap(),
+            ))
+            .notes("this is a test dictionary")
+            .build();
         let cdb_dict = cdb_dict_builder.build(metadata.clone()).unwrap();
+        let mut metadata = metadata;
+        metadata.set_checksum(Some(
+            storage::checksum_files(&cdb_dict.shard_paths()).unwrap(),
+        ));
 
         assert_eq!(*cdb_dict.get("test1").first().unwrap(), test1);
         assert_eq!(*cdb_dict.get("test2").first().unwrap(), test2);
         assert!(cdb_dict.get("test3").is_empty());
         assert_eq!(cdb_dict.get_metadata(), &metadata);
     }
+
+    #[test]
+    fn compressed() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let entry = |index: usize| DictionaryEntry {
+            readings: vec![format!("reading {index}")],
+            gloss: format!("a gloss repeated in many entries, number {index}. ").repeat(3),
+            tags: vec!["n".to_owned(), "common".to_owned()],
+            id: Some(index.to_string()),
+        };
+        let build = |name: &str, compression: Option<i32>| {
+            let mut builder = CDBDictionaryBuilder::new(temp_dir.path().join(name)).unwrap();
+            if let Some(level) = compression {
+                builder = builder.with_compression(level);
+            }
+            for index in 0..2000 {
+                builder.add(&index.to_string(), entry(index)).unwrap();
+            }
+            builder.build(Default::default()).unwrap()
+        };
+        let plain = build("plain", None);
+        let compressed = build("compressed", Some(3));
+
+        assert_eq!(compressed.get("1234"), vec![entry(1234)]);
+        assert!(compressed.get("2000").is_empty());
+        assert_eq!(compressed.iter().count(), 2000);
+        let file_len = |dict: &CDBDictionary| std::fs::metadata(dict.path()).unwrap().len();
+        assert!(file_len(&compressed) < file_len(&plain));
+
+        let serialized = serde_json::to_string(&compressed).unwrap();
+        let compressed = serde_json::from_str::<CDBDictionary>(&serialized).unwrap();
+        assert_eq!(compressed.get("42"), vec![entry(42)]);
+    }
+
+    #[test]
+    fn sharded() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let entry = |index: usize| DictionaryEntry {
+            readings: vec![format!("reading {index}")],
+            gloss: format!("gloss {index}"),
+            tags: vec![],
+            id: Some(index.to_string()),
+        };
+        let build = |count: usize, shard_len_limit: u64| {
+            let mut builder = CDBDictionaryBuilder::new(temp_dir.path().join("sharded")).unwrap();
+            builder.shard_len_limit = shard_len_limit;
+            for index in 0..count {
+                builder
+                    .add(&(index % 1000).to_string(), entry(index))
+                    .unwrap();
+            }
+            builder.build(Default::default()).unwrap()
+        };
+        let dict = build(2000, 16 * 1024);
+
+        assert!(dict.shard_paths().len() > 1);
+        assert!(dict.shard_paths().iter().all(|path| path.exists()));
+        assert_eq!(dict.get("123"), vec![entry(123), entry(1123)]);
+        assert_eq!(dict.iter().count(), 2000);
+
+        let serialized = serde_json::to_string(&dict).unwrap();
+        let dict = serde_json::from_str::<CDBDictionary>(&serialized).unwrap();
+        assert_eq!(dict.get("999"), vec![entry(999), entry(1999)]);
+
+        // Rebuilding in place writes the other shards under new names, and removes those of the previous build.
+        let old_paths = dict.shard_paths();
+        let dict = build(2000, 16 * 1024);
+        let paths = dict.shard_paths();
+        assert!(paths[1..].iter().all(|path| !old_paths.contains(path)));
+        assert!(old_paths[1..].iter().all(|path| !path.exists()));
+        assert_eq!(
+            std::fs::read_dir(temp_dir.path()).unwrap().count(),
+            paths.len()
+        );
+        assert_eq!(dict.get("999"), vec![entry(999), entry(1999)]);
+
+        let dict = build(10, SHARD_LEN_LIMIT);
+        assert_eq!(dict.shard_paths().len(), 1);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        assert_eq!(dict.get("9"), vec![entry(9)]);
+    }
+
+    #[test]
+    fn atomic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = temp_dir.path().join("atomic");
+
+        let entry = |gloss: &str| DictionaryEntry {
+            readings: vec![],
+            gloss: gloss.to_owned(),
+            tags: vec![],
+            id: None,
+        };
+        let build = |gloss: &str| {
+            let mut builder = CDBDictionaryBuilder::new(&path).unwrap();
+            builder.add("test", entry(gloss)).unwrap();
+            builder
+        };
+        let dict = build("old").build(Default::default()).unwrap();
+
+        // Re-importing in place doesn't touch the working copy until it's done, and leaves nothing behind if it isn't.
+        let builder = build("new");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+        drop(builder);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        assert_eq!(dict.get("test"), vec![entry("old")]);
+        build("discarded").discard().unwrap();
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+
+        let dict = build("new").build(Default::default()).unwrap();
+        assert_eq!(dict.get("test"), vec![entry("new")]);
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn tags() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let entry = |gloss: &str, tags: &[&str]| DictionaryEntry {
+            readings: vec![],
+            gloss: gloss.to_owned(),
+            tags: tags.iter().map(|tag| (*tag).to_owned()).collect(),
+            id: None,
+        };
+        let mut builder = CDBDictionaryBuilder::new(temp_dir.path().join("tags"))
+            .unwrap()
+            .with_compression(3);
+        builder
+            .add("食べる", entry("to eat", &["v1", "vt", "common"]))
+            .unwrap();
+        builder
+            .add("見る", entry("to see", &["v1", "vt", "common"]))
+            .unwrap();
+        builder
+            .add("見る", entry("to look after", &["v1"]))
+            .unwrap();
+        builder
+            .add("書く", entry("to write", &["v5k", "common"]))
+            .unwrap();
+        let dict = builder.build(Default::default()).unwrap();
+
+        assert_eq!(dict.tag_table().unwrap(), ["v1", "vt", "common", "v5k"]);
+        assert_eq!(
+            dict.get("書く"),
+            vec![entry("to write", &["v5k", "common"])]
+        );
+        let mut common_v1 = dict.get_by_tags(&["common", "v1"]);
+        common_v1.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            common_v1,
+            [
+                ("見る".to_owned(), entry("to see", &["v1", "vt", "common"])),
+                (
+                    "食べる".to_owned(),
+                    entry("to eat", &["v1", "vt", "common"])
+                ),
+            ]
+        );
+        assert_eq!(dict.get_by_tags(&["v1"]).len(), 3);
+        assert!(dict.get_by_tags(&["adj-i"]).is_empty());
+        assert_eq!(dict.get_by_tags(&[]).len(), 4);
+    }
 }
@@ -0,0 +1,113 @@
+//! Differences between two revisions of a dictionary, by entry id.
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+};
+
+use semver::Version;
+use serde::Serialize;
+use thiserror::Error;
+
+use super::{Dictionary, DictionaryMetadata};
+
+#[derive(Debug, Error)]
+pub enum UpdateError {
+    #[error("no dictionary at index {}", .0)]
+    NoSuchDictionary(usize),
+    #[error("{:?} is not a revision of {:?}", .new, .old)]
+    DifferentDictionary { old: String, new: String },
+    #[error("revision {} is older than the current revision {}", .new, .old)]
+    OlderRevision { old: Version, new: Version },
+}
+
+/// Ids of the words which were added, removed or changed between two revisions of a dictionary.
+///
+/// Entries without an id, such as those of dictionaries imported before entries had ids, can't be matched across
+/// revisions and are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DictionaryChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl DictionaryChanges {
+    /// Compares every entry of both dictionaries, grouped by id.
+    pub fn between(old: &impl Dictionary, new: &impl Dictionary) -> Self {
+        let old = fingerprints(old);
+        let mut new = fingerprints(new);
+        let mut changes = Self::default();
+
+        for (id, old_fingerprint) in old {
+            match new.remove(&id) {
+                Some(new_fingerprint) if new_fingerprint != old_fingerprint => {
+                    changes.changed.push(id)
+                }
+                Some(_) => {}
+                None => changes.removed.push(id),
+            }
+        }
+        changes.added = new.into_keys().collect();
+
+        changes
+    }
+
+    /// Reports every entry of `new` as added, for when there is no previous revision to compare with.
+    pub fn added(new: &impl Dictionary) -> Self {
+        Self {
+            added: fingerprints(new).into_keys().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Checks that `new` can replace `old`, being the same dictionary in the same or a later revision.
+pub(crate) fn check_update(
+    old: &DictionaryMetadata,
+    new: &DictionaryMetadata,
+) -> Result<(), UpdateError> {
+    if old.name() != new.name() {
+        return Err(UpdateError::DifferentDictionary {
+            old: old.name().to_owned(),
+            new: new.name().to_owned(),
+        });
+    }
+    if new.version() < old.version() {
+        return Err(UpdateError::OlderRevision {
+            old: old.version().clone(),
+            new: new.version().clone(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Hashes the entries of every id, independently of the order they are stored in.
+///
+/// Keeping only hashes avoids holding two whole dictionaries in memory.
+fn fingerprints(dictionary: &impl Dictionary) -> BTreeMap<String, u64> {
+    let mut entry_hashes: BTreeMap<String, Vec<u64>> = BTreeMap::new();
+    for (key, entry) in dictionary.iter() {
+        let Some(id) = entry.id.clone() else {
+            continue;
+        };
+        let mut hasher = DefaultHasher::new();
+        (key, entry).hash(&mut hasher);
+        entry_hashes.entry(id).or_default().push(hasher.finish());
+    }
+
+    entry_hashes
+        .into_iter()
+        .map(|(id, mut hashes)| {
+            hashes.sort_unstable();
+            let mut hasher = DefaultHasher::new();
+            hashes.hash(&mut hasher);
+            (id, hasher.finish())
+        })
+        .collect()
+}
@@ -55,4 +55,12 @@ impl Dictionary for HashMapDictionary {
     fn get_metadata(&self) -> &DictionaryMetadata {
         &self.metadata
     }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_> {
+        Box::new(
+            self.hashmap
+                .iter()
+                .map(|(key, entry)| (key.clone(), entry.clone())),
+        )
+    }
 }
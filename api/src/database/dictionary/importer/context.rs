@@ -0,0 +1,351 @@
+//! Progress reporting and cancellation for imports.
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::database::storage;
+
+/// Amount of processed words between two progress reports.
+const REPORT_INTERVAL: u64 = 1000;
+
+/// Current phase of an import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ImportPhase {
+    /// Reading and converting the source into dictionary entries.
+    Reading,
+    /// Finishing the dictionary after every entry was added.
+    Building,
+    /// The import has finished.
+    Done,
+}
+
+/// A snapshot of the progress of an import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ImportProgress {
+    pub phase: ImportPhase,
+    /// Bytes read from the source file, before decompression.
+    pub bytes_read: u64,
+    /// Size of the source file, if known.
+    pub total_bytes: Option<u64>,
+    pub words_processed: u64,
+}
+
+/// A problem which was skipped over during a tolerant import.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportWarning {
+    /// Where the problem is in the source, like `$.words[12]` for JSON or `line 12` for line-based formats.
+    pub location: String,
+    pub message: String,
+}
+
+/// Summary of an import, see [`ImportContext::with_tolerance`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub words_processed: u64,
+    pub words_skipped: u64,
+    pub warnings: Vec<ImportWarning>,
+}
+
+/// Receives progress reports during an import.
+pub trait ProgressSink: Send + Sync {
+    fn report(&self, progress: ImportProgress);
+}
+
+impl<F> ProgressSink for F
+where
+    F: Fn(ImportProgress) + Send + Sync,
+{
+    fn report(&self, progress: ImportProgress) {
+        self(progress)
+    }
+}
+
+/// A cloneable handle for cancelling an import from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The import was cancelled through its [`CancellationToken`].
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("import cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// State shared between an importer and its caller for the duration of one import.
+pub struct ImportContext {
+    sink: Option<Box<dyn ProgressSink>>,
+    cancellation: CancellationToken,
+    phase: Mutex<ImportPhase>,
+    bytes_read: AtomicU64,
+    total_bytes: OnceLock<u64>,
+    words_processed: AtomicU64,
+    tolerant: bool,
+    words_skipped: AtomicU64,
+    warnings: Mutex<Vec<ImportWarning>>,
+    display_name: Option<String>,
+    source_hash: OnceLock<String>,
+}
+
+impl Default for ImportContext {
+    fn default() -> Self {
+        Self {
+            sink: None,
+            cancellation: Default::default(),
+            phase: Mutex::new(ImportPhase::Reading),
+            bytes_read: Default::default(),
+            total_bytes: Default::default(),
+            words_processed: Default::default(),
+            tolerant: false,
+            words_skipped: Default::default(),
+            warnings: Default::default(),
+            display_name: None,
+            source_hash: Default::default(),
+        }
+    }
+}
+
+impl ImportContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_progress(mut self, sink: impl ProgressSink + 'static) -> Self {
+        self.sink = Some(Box::new(sink));
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Makes importers skip unknown fields and malformed words instead of failing, recording a warning for each.
+    ///
+    /// The warnings are available from [`ImportContext::report`] once the import is done.
+    pub fn with_tolerance(mut self, tolerant: bool) -> Self {
+        self.tolerant = tolerant;
+        self
+    }
+
+    pub fn is_tolerant(&self) -> bool {
+        self.tolerant
+    }
+
+    /// Sets the name shown for the imported dictionary, instead of the one given by its source.
+    pub fn with_display_name(mut self, display_name: impl Into<String>) -> Self {
+        self.display_name = Some(display_name.into());
+        self
+    }
+
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    /// Hex encoded SHA-256 of the source file, once an import from a path read all of it.
+    pub fn source_hash(&self) -> Option<&str> {
+        self.source_hash.get().map(String::as_str)
+    }
+
+    pub fn progress(&self) -> ImportProgress {
+        ImportProgress {
+            phase: *self.phase.lock().unwrap(),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            total_bytes: self.total_bytes.get().copied(),
+            words_processed: self.words_processed.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    pub fn report(&self) -> ImportReport {
+        ImportReport {
+            words_processed: self.words_processed.load(Ordering::Relaxed),
+            words_skipped: self.words_skipped.load(Ordering::Relaxed),
+            warnings: self.warnings.lock().unwrap().clone(),
+        }
+    }
+
+    /// Wraps the raw source so that bytes read from it are counted as progress.
+    pub fn track_reader<R: Read>(
+        &self,
+        reader: R,
+        total_bytes: Option<u64>,
+    ) -> ProgressReader<'_, R> {
+        if let Some(total_bytes) = total_bytes {
+            let _ = self.total_bytes.set(total_bytes);
+        }
+
+        ProgressReader {
+            reader,
+            context: self,
+            hasher: None,
+        }
+    }
+
+    /// Wraps a source file like [`ImportContext::track_reader`], also hashing it for [`ImportContext::source_hash`].
+    ///
+    /// The hash is only recorded by [`ProgressReader::finish_source`], once the whole file was read.
+    pub(crate) fn track_source(&self, file: File) -> ProgressReader<'_, File> {
+        let total_bytes = file.metadata().ok().map(|metadata| metadata.len());
+        ProgressReader {
+            hasher: Some(Sha256::new()),
+            ..self.track_reader(file, total_bytes)
+        }
+    }
+
+    pub(crate) fn set_source_hash(&self, source_hash: String) {
+        let _ = self.source_hash.set(source_hash);
+    }
+
+    pub(crate) fn set_phase(&self, phase: ImportPhase) {
+        *self.phase.lock().unwrap() = phase;
+        self.report_progress();
+    }
+
+    /// Counts one processed word, reporting progress periodically.
+    ///
+    /// Fails if the import was cancelled, so importers can bail out at a safe point.
+    pub(crate) fn word_processed(&self) -> Result<(), Cancelled> {
+        let words_processed = self.words_processed.fetch_add(1, Ordering::Relaxed) + 1;
+        // `is_multiple_of` would need Rust 1.87.
+        #[allow(clippy::manual_is_multiple_of)]
+        if words_processed % REPORT_INTERVAL == 0 {
+            self.report_progress();
+            self.check_cancelled()?;
+        }
+        Ok(())
+    }
+
+    /// Records a problem which was skipped over in tolerant mode.
+    pub(crate) fn warn(&self, location: impl Into<String>, message: impl ToString) {
+        self.warnings.lock().unwrap().push(ImportWarning {
+            location: location.into(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Records a word which was skipped in tolerant mode because it is malformed.
+    pub(crate) fn word_skipped(&self, location: impl Into<String>, message: impl ToString) {
+        self.words_skipped.fetch_add(1, Ordering::Relaxed);
+        self.warn(location, message);
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn report_progress(&self) {
+        if let Some(sink) = &self.sink {
+            sink.report(self.progress());
+        }
+    }
+}
+
+/// A reader which counts the bytes read through it into an [`ImportContext`].
+pub struct ProgressReader<'a, R> {
+    reader: R,
+    context: &'a ImportContext,
+    /// Hashes the bytes read, for readers of a source file.
+    hasher: Option<Sha256>,
+}
+
+impl<R: Read> ProgressReader<'_, R> {
+    /// Reads the rest of a source, which the importer may not have needed, and records its hash in the context.
+    ///
+    /// The first hash recorded is kept, so an import nested in another one doesn't replace the outer source's.
+    pub(crate) fn finish_source(mut self) -> io::Result<()> {
+        io::copy(&mut self, &mut io::sink())?;
+        if let Some(hasher) = self.hasher.take() {
+            self.context
+                .set_source_hash(storage::to_hex(&hasher.finalize()));
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.reader.read(buf)?;
+        self.context
+            .bytes_read
+            .fetch_add(read as u64, Ordering::Relaxed);
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..read]);
+        }
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let cancellation = CancellationToken::new();
+        let context = ImportContext::new()
+            .with_progress({
+                let reports = reports.clone();
+                move |progress| reports.lock().unwrap().push(progress)
+            })
+            .with_cancellation(cancellation.clone());
+
+        let mut data = Vec::new();
+        context
+            .track_reader(&[0u8; 100][..], Some(100))
+            .read_to_end(&mut data)
+            .unwrap();
+
+        for _ in 0..REPORT_INTERVAL {
+            context.word_processed().unwrap();
+        }
+        assert_eq!(
+            reports.lock().unwrap().last(),
+            Some(&ImportProgress {
+                phase: ImportPhase::Reading,
+                bytes_read: 100,
+                total_bytes: Some(100),
+                words_processed: REPORT_INTERVAL,
+            })
+        );
+
+        cancellation.cancel();
+        let result = (0..REPORT_INTERVAL).try_for_each(|_| context.word_processed());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! Random access into dictzip files (`.dict.dz`).
+//!
+//! A dictzip file is a gzip file whose deflate stream is flushed every `chunk_len` uncompressed bytes,
+//! with the compressed size of each chunk listed in the `RA` extra field of the gzip header.
+//! Any chunk can then be inflated on its own, without decompressing everything before it.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use flate2::{Decompress, FlushDecompress};
+
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+pub struct DictZip<R> {
+    reader: R,
+    chunk_len: usize,
+    /// Offset of every chunk in the file, followed by the end offset of the last one.
+    chunk_offsets: Vec<u64>,
+    /// The last inflated chunk, since consecutive reads usually hit the same one.
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl DictZip<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> DictZip<R> {
+    /// Reads the gzip header, failing if it has no dictzip chunk table.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 10];
+        reader.read_exact(&mut header)?;
+        if header[..3] != [0x1f, 0x8b, 8] {
+            return Err(invalid_data("not a gzip file"));
+        }
+        let flags = header[3];
+        if flags & FLAG_EXTRA == 0 {
+            return Err(invalid_data("gzip file has no dictzip chunk table"));
+        }
+
+        let extra_len = read_u16(&mut reader)?;
+        let mut extra = vec![0; extra_len as usize];
+        reader.read_exact(&mut extra)?;
+        let (chunk_len, chunk_sizes) = parse_chunk_table(&extra)
+            .ok_or_else(|| invalid_data("gzip file has no dictzip chunk table"))?;
+        if chunk_len == 0 || chunk_sizes.is_empty() {
+            return Err(invalid_data("dictzip chunk table is empty"));
+        }
+
+        if flags & FLAG_NAME != 0 {
+            skip_zero_terminated(&mut reader)?;
+        }
+        if flags & FLAG_COMMENT != 0 {
+            skip_zero_terminated(&mut reader)?;
+        }
+        if flags & FLAG_HCRC != 0 {
+            read_u16(&mut reader)?;
+        }
+
+        let mut offset = reader.stream_position()?;
+        let mut chunk_offsets = Vec::with_capacity(chunk_sizes.len() + 1);
+        chunk_offsets.push(offset);
+        for size in chunk_sizes {
+            offset += size as u64;
+            chunk_offsets.push(offset);
+        }
+
+        Ok(Self {
+            reader,
+            chunk_len,
+            chunk_offsets,
+            cache: None,
+        })
+    }
+
+    /// Reads `len` uncompressed bytes starting at `offset`.
+    pub fn read_at(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(len);
+        let mut chunk = (offset / self.chunk_len as u64) as usize;
+        let mut chunk_offset = (offset % self.chunk_len as u64) as usize;
+
+        while data.len() < len {
+            let chunk_data = self.chunk(chunk)?;
+            let available = chunk_data
+                .get(chunk_offset..)
+                .ok_or_else(|| invalid_data("read past the end of a dictzip chunk"))?;
+            if available.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let wanted = available.len().min(len - data.len());
+            data.extend_from_slice(&available[..wanted]);
+
+            chunk += 1;
+            chunk_offset = 0;
+        }
+
+        Ok(data)
+    }
+
+    fn chunk(&mut self, index: usize) -> io::Result<&[u8]> {
+        if self.cache.as_ref().map(|(cached, _)| *cached) != Some(index) {
+            let (Some(start), Some(end)) = (
+                self.chunk_offsets.get(index),
+                self.chunk_offsets.get(index + 1),
+            ) else {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            };
+
+            let mut compressed = vec![0; (end - start) as usize];
+            self.reader.seek(SeekFrom::Start(*start))?;
+            self.reader.read_exact(&mut compressed)?;
+
+            // Chunks end with a full flush, so each one starts a fresh raw deflate stream.
+            let mut chunk = Vec::with_capacity(self.chunk_len);
+            Decompress::new(false)
+                .decompress_vec(&compressed, &mut chunk, FlushDecompress::Sync)
+                .map_err(|error| invalid_data(&error.to_string()))?;
+            self.cache = Some((index, chunk));
+        }
+
+        Ok(&self.cache.as_ref().unwrap().1)
+    }
+}
+
+/// Finds the `RA` subfield in a gzip extra field, returning the chunk length and compressed chunk sizes.
+fn parse_chunk_table(mut extra: &[u8]) -> Option<(usize, Vec<u16>)> {
+    while extra.len() >= 4 {
+        let id = &extra[..2];
+        let len = u16::from_le_bytes([extra[2], extra[3]]) as usize;
+        let data = extra.get(4..4 + len)?;
+
+        if id == b"RA" {
+            let field = |index: usize| {
+                data.get(index * 2..index * 2 + 2)
+                    .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            };
+            let (_version, chunk_len, chunk_count) = (field(0)?, field(1)?, field(2)?);
+            let sizes = (0..chunk_count as usize)
+                .map(|index| field(3 + index))
+                .collect::<Option<Vec<u16>>>()?;
+            return Some((chunk_len as usize, sizes));
+        }
+
+        extra = &extra[4 + len..];
+    }
+
+    None
+}
+
+fn read_u16(reader: &mut impl Read) -> io::Result<u16> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn skip_zero_terminated(reader: &mut impl Read) -> io::Result<()> {
+    let mut byte = [0];
+    loop {
+        reader.read_exact(&mut byte)?;
+        if byte[0] == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Cursor;
+
+    use flate2::{Compress, Compression, FlushCompress};
+
+    use super::*;
+
+    /// Compresses `data` into a dictzip file with tiny chunks.
+    pub(crate) fn dictzip(data: &[u8], chunk_len: usize) -> Vec<u8> {
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut chunks = Vec::new();
+        for chunk in data.chunks(chunk_len) {
+            let mut compressed = Vec::with_capacity(chunk.len() * 2 + 64);
+            compress
+                .compress_vec(chunk, &mut compressed, FlushCompress::Full)
+                .unwrap();
+            chunks.push(compressed);
+        }
+
+        let mut extra = b"RA".to_vec();
+        extra.extend_from_slice(&((6 + chunks.len() * 2) as u16).to_le_bytes());
+        extra.extend_from_slice(&1u16.to_le_bytes());
+        extra.extend_from_slice(&(chunk_len as u16).to_le_bytes());
+        extra.extend_from_slice(&(chunks.len() as u16).to_le_bytes());
+        for chunk in chunks.iter() {
+            extra.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        }
+
+        let mut file = vec![0x1f, 0x8b, 8, FLAG_EXTRA, 0, 0, 0, 0, 0, 3];
+        file.extend_from_slice(&(extra.len() as u16).to_le_bytes());
+        file.extend_from_slice(&extra);
+        for chunk in chunks {
+            file.extend_from_slice(&chunk);
+        }
+        file
+    }
+
+    #[test]
+    fn random_access() {
+        let data = "猫は可愛い動物です。".repeat(10);
+        let mut dictzip = DictZip::new(Cursor::new(dictzip(data.as_bytes(), 16))).unwrap();
+
+        assert_eq!(dictzip.read_at(30, 40).unwrap(), &data.as_bytes()[30..70]);
+        assert_eq!(dictzip.read_at(0, 3).unwrap(), &data.as_bytes()[..3]);
+        assert_eq!(dictzip.read_at(0, data.len()).unwrap(), data.as_bytes());
+        assert!(dictzip.read_at(data.len() as u64, 1).is_err());
+
+        // The chunk length follows the gzip header, the extra field length, the `RA` id and length, and the version.
+        let mut zero_chunk_len = self::dictzip(data.as_bytes(), 16);
+        zero_chunk_len[18..20].copy_from_slice(&0u16.to_le_bytes());
+        assert!(DictZip::new(Cursor::new(zero_chunk_len)).is_err());
+        assert!(DictZip::new(Cursor::new(self::dictzip(b"", 16))).is_err());
+    }
+}
@@ -0,0 +1,593 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::BufRead,
+};
+
+use quick_xml::{events::Event, Reader};
+use semver::Version;
+use thiserror::Error;
+use url::Url;
+
+use crate::database::dictionary::{
+    importer, DictionaryBuilder, DictionaryEntry, DictionaryMetadata,
+};
+
+use super::{
+    context::ImportContext,
+    parse_version,
+    pipeline::{with_pipeline, Pipeline},
+    Importer,
+};
+
+/// Importer for the original JMdict XML files (`JMdict`, `JMdict_e`, optionally gzipped) released by the EDRDG.
+#[derive(Debug)]
+pub struct JMDictImporter {}
+
+impl Importer for JMDictImporter {
+    type Error = Error;
+
+    const NAME: &'static str = "JMdict XML";
+    const EXTENSIONS: &'static [&'static str] = &["xml"];
+
+    fn detect(head: &[u8]) -> bool {
+        let head = String::from_utf8_lossy(head);
+        head.contains("<!DOCTYPE JMdict") || head.contains("<JMdict>")
+    }
+
+    fn import_entries<R, DB>(
+        reader: R,
+        dict_builder: &mut DB,
+        context: &ImportContext,
+    ) -> Result<DictionaryMetadata, importer::Error<Self::Error, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder,
+    {
+        with_pipeline(entry_entries, dict_builder.encoder(), |pipeline| {
+            import_xml(reader, pipeline, dict_builder, context)
+        })
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("JMDict XML parsing error")]
+    Xml(#[from] quick_xml::Error),
+    #[error("JMDict XML escape error")]
+    Escape(#[from] quick_xml::escape::EscapeError),
+    #[error("JMDict XML is missing the DTD entity declarations")]
+    MissingDoctype,
+}
+
+/// Descriptions of the priority markers found in `ke_pri` and `re_pri` elements.
+///
+/// These are not declared as entities in the DTD, so they are described here instead.
+const PRIORITY_TAGS: &[(&str, &str)] = &[
+    (
+        "news1",
+        "appears in the first 12,000 words of the \"wordfreq\" newspaper list",
+    ),
+    (
+        "news2",
+        "appears in the second 12,000 words of the \"wordfreq\" newspaper list",
+    ),
+    ("ichi1", "appears in the \"Ichimango goi bunruishuu\""),
+    (
+        "ichi2",
+        "appears in the \"Ichimango goi bunruishuu\", but demoted due to low frequency",
+    ),
+    ("spec1", "common word not included in other lists"),
+    (
+        "spec2",
+        "common word not included in other lists, of lower priority",
+    ),
+    ("gai1", "common loanword, based on the \"wordfreq\" file"),
+    (
+        "gai2",
+        "loanword of lower frequency, based on the \"wordfreq\" file",
+    ),
+];
+
+#[derive(Default)]
+struct JMDictEntry {
+    id: String,
+    kanji: Vec<JMDictKanji>,
+    kana: Vec<JMDictKana>,
+    sense: Vec<JMDictSense>,
+}
+
+#[derive(Default)]
+struct JMDictKanji {
+    text: String,
+    info: Vec<String>,
+    priority: Vec<String>,
+}
+
+#[derive(Default)]
+struct JMDictKana {
+    text: String,
+    no_kanji: bool,
+    applies_to_kanji: Vec<String>,
+    info: Vec<String>,
+    priority: Vec<String>,
+}
+
+#[derive(Default, Clone)]
+struct JMDictSense {
+    applies_to_kanji: Vec<String>,
+    applies_to_kana: Vec<String>,
+    part_of_speech: Vec<String>,
+    field: Vec<String>,
+    misc: Vec<String>,
+    dialect: Vec<String>,
+    gloss: Vec<String>,
+}
+
+fn import_xml<R, DB>(
+    reader: R,
+    pipeline: &mut Pipeline<JMDictEntry>,
+    dict_builder: &mut DB,
+    context: &ImportContext,
+) -> Result<DictionaryMetadata, importer::Error<Error, DB::Error>>
+where
+    R: BufRead,
+    DB: DictionaryBuilder,
+{
+    let mut reader = Reader::from_reader(reader);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut entities: Option<HashMap<String, String>> = None;
+    let mut revisions = Vec::new();
+    let mut created = None;
+    let mut used_tags = BTreeMap::new();
+    let mut entry = JMDictEntry::default();
+    let mut path: Vec<Vec<u8>> = Vec::new();
+    let mut inherited_part_of_speech = false;
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(Error::Xml)
+            .map_err(importer::Error::ImporterSpecific)?;
+
+        match event {
+            Event::DocType(doctype) => {
+                let doctype = String::from_utf8_lossy(&doctype);
+                entities = Some(parse_entities(&doctype));
+                revisions = parse_revisions(&doctype);
+            }
+            Event::Comment(comment) => {
+                let comment = String::from_utf8_lossy(&comment);
+                if let Some(date) = comment.trim().strip_prefix("JMdict created:") {
+                    created = Some(date.trim().to_owned());
+                }
+            }
+            Event::Start(start) => {
+                let name = start.name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"entry" => entry = JMDictEntry::default(),
+                    b"k_ele" => entry.kanji.push(Default::default()),
+                    b"r_ele" => entry.kana.push(Default::default()),
+                    b"sense" => {
+                        // Per the DTD, a sense without part of speech inherits the previous one's.
+                        let part_of_speech = entry
+                            .sense
+                            .last()
+                            .map(|sense| sense.part_of_speech.clone())
+                            .unwrap_or_default();
+                        entry.sense.push(JMDictSense {
+                            part_of_speech,
+                            ..Default::default()
+                        });
+                        inherited_part_of_speech = true;
+                    }
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::Empty(empty) if empty.name().as_ref() == b"re_nokanji" => {
+                if let Some(kana) = entry.kana.last_mut() {
+                    kana.no_kanji = true;
+                }
+            }
+            Event::Text(text) => {
+                let entities = entities
+                    .as_ref()
+                    .ok_or(Error::MissingDoctype)
+                    .map_err(importer::Error::ImporterSpecific)?;
+                let raw = String::from_utf8_lossy(&text);
+                let element = path.last().map(Vec::as_slice).unwrap_or_default();
+
+                match element {
+                    b"keb" | b"reb" | b"stagk" | b"stagr" | b"re_restr" | b"gloss" => {
+                        let text = quick_xml::escape::unescape_with(&raw, |name| {
+                            entities.get(name).map(String::as_str)
+                        })
+                        .map_err(Error::Escape)
+                        .map_err(importer::Error::ImporterSpecific)?
+                        .into_owned();
+
+                        match element {
+                            b"keb" => {
+                                if let Some(kanji) = entry.kanji.last_mut() {
+                                    kanji.text = text;
+                                }
+                            }
+                            b"reb" => {
+                                if let Some(kana) = entry.kana.last_mut() {
+                                    kana.text = text;
+                                }
+                            }
+                            b"re_restr" => {
+                                if let Some(kana) = entry.kana.last_mut() {
+                                    kana.applies_to_kanji.push(text);
+                                }
+                            }
+                            b"stagk" => {
+                                if let Some(sense) = entry.sense.last_mut() {
+                                    sense.applies_to_kanji.push(text);
+                                }
+                            }
+                            b"stagr" => {
+                                if let Some(sense) = entry.sense.last_mut() {
+                                    sense.applies_to_kana.push(text);
+                                }
+                            }
+                            _ => {
+                                if let Some(sense) = entry.sense.last_mut() {
+                                    sense.gloss.push(text);
+                                }
+                            }
+                        }
+                    }
+                    b"pos" | b"field" | b"misc" | b"dial" => {
+                        let tag = resolve_tag(&raw, entities, &mut used_tags);
+                        if let Some(sense) = entry.sense.last_mut() {
+                            match element {
+                                b"pos" => {
+                                    if inherited_part_of_speech {
+                                        sense.part_of_speech.clear();
+                                        inherited_part_of_speech = false;
+                                    }
+                                    sense.part_of_speech.push(tag);
+                                }
+                                b"field" => sense.field.push(tag),
+                                b"misc" => sense.misc.push(tag),
+                                _ => sense.dialect.push(tag),
+                            }
+                        }
+                    }
+                    b"ke_inf" | b"re_inf" => {
+                        let tag = resolve_tag(&raw, entities, &mut used_tags);
+                        if element == b"ke_inf" {
+                            if let Some(kanji) = entry.kanji.last_mut() {
+                                kanji.info.push(tag);
+                            }
+                        } else if let Some(kana) = entry.kana.last_mut() {
+                            kana.info.push(tag);
+                        }
+                    }
+                    b"ent_seq" => entry.id = raw.trim().to_owned(),
+                    b"ke_pri" | b"re_pri" => {
+                        let tag = raw.trim().to_owned();
+                        used_tags
+                            .entry(tag.clone())
+                            .or_insert_with(|| describe_priority(&tag));
+
+                        if element == b"ke_pri" {
+                            if let Some(kanji) = entry.kanji.last_mut() {
+                                kanji.priority.push(tag);
+                            }
+                        } else if let Some(kana) = entry.kana.last_mut() {
+                            kana.priority.push(tag);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(end) => {
+                if end.name().as_ref() == b"entry" {
+                    pipeline
+                        .push(std::mem::take(&mut entry), dict_builder)
+                        .map_err(importer::Error::DictBuilder)?;
+                    context
+                        .word_processed()
+                        .map_err(|_| importer::Error::Cancelled)?;
+                }
+                path.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+
+        buf.clear();
+    }
+
+    pipeline
+        .finish(dict_builder)
+        .map_err(importer::Error::DictBuilder)?;
+
+    let version = revisions
+        .into_iter()
+        .filter_map(|revision| parse_version(&revision).ok())
+        .max()
+        .unwrap_or(Version::new(0, 0, 0));
+
+    Ok(DictionaryMetadata::builder("JMDict")
+        .author("Electronic Dictionary Research and Development Group (http://www.edrdg.org/edrdg/licence.html)")
+        .version(version)
+        .homepage_url(Some(Url::parse("https://www.edrdg.org/jmdict/j_jmdict.html").unwrap()))
+        .tags(used_tags)
+        .source_date(created)
+        .build())
+}
+
+/// Turns an entry into one dictionary entry per sense for each of its kanji and kana.
+fn entry_entries(entry: JMDictEntry) -> Vec<(String, DictionaryEntry)> {
+    let mut entries = Vec::new();
+
+    for sense in entry.sense {
+        let mut tags = Vec::with_capacity(
+            sense.dialect.len() + sense.field.len() + sense.misc.len() + sense.part_of_speech.len(),
+        );
+        tags.extend(sense.dialect);
+        tags.extend(sense.field);
+        tags.extend(sense.misc);
+        tags.extend(sense.part_of_speech);
+
+        let gloss = sense.gloss.join("\n");
+
+        for kana in entry.kana.iter() {
+            if !sense.applies_to_kana.is_empty() && !sense.applies_to_kana.contains(&kana.text) {
+                continue;
+            }
+
+            let entry = DictionaryEntry {
+                readings: vec![kana.text.clone()],
+                gloss: gloss.clone(),
+                tags: tags
+                    .iter()
+                    .chain(kana.info.iter())
+                    .chain(kana.priority.iter())
+                    .cloned()
+                    .collect(),
+                id: Some(entry.id.clone()),
+            };
+
+            entries.push((kana.text.clone(), entry));
+        }
+
+        for kanji in entry.kanji.iter() {
+            if !sense.applies_to_kanji.is_empty() && !sense.applies_to_kanji.contains(&kanji.text) {
+                continue;
+            }
+
+            let readings = entry
+                .kana
+                .iter()
+                .filter(|kana| {
+                    !kana.no_kanji
+                        && (kana.applies_to_kanji.is_empty()
+                            || kana.applies_to_kanji.contains(&kanji.text))
+                })
+                .map(|kana| kana.text.clone())
+                .collect();
+
+            let entry = DictionaryEntry {
+                readings,
+                gloss: gloss.clone(),
+                tags: tags
+                    .iter()
+                    .chain(kanji.info.iter())
+                    .chain(kanji.priority.iter())
+                    .cloned()
+                    .collect(),
+                id: Some(entry.id.clone()),
+            };
+
+            entries.push((kanji.text.clone(), entry));
+        }
+    }
+
+    entries
+}
+
+/// Resolves the content of a tag element (such as `&v5k;`) into the entity name, recording its description.
+fn resolve_tag(
+    raw: &str,
+    entities: &HashMap<String, String>,
+    used_tags: &mut BTreeMap<String, String>,
+) -> String {
+    let raw = raw.trim();
+
+    match raw
+        .strip_prefix('&')
+        .and_then(|name| name.strip_suffix(';'))
+    {
+        Some(name) => {
+            if let Some(description) = entities.get(name) {
+                used_tags
+                    .entry(name.to_owned())
+                    .or_insert_with(|| description.clone());
+            }
+            name.to_owned()
+        }
+        None => raw.to_owned(),
+    }
+}
+
+fn describe_priority(tag: &str) -> String {
+    if let Some(rank) = tag.strip_prefix("nf") {
+        return format!("appears in the word frequency list, in the {rank} group of 500 words");
+    }
+
+    PRIORITY_TAGS
+        .iter()
+        .find(|(name, _)| *name == tag)
+        .map(|(_, description)| (*description).to_owned())
+        .unwrap_or_default()
+}
+
+/// Parses `<!ENTITY name "value">` declarations from the DTD internal subset.
+fn parse_entities(doctype: &str) -> HashMap<String, String> {
+    let mut entities = HashMap::new();
+    let mut rest = doctype;
+
+    while let Some(start) = rest.find("<!ENTITY") {
+        rest = &rest[start + "<!ENTITY".len()..];
+
+        let Some((name, after_name)) = rest.trim_start().split_once(char::is_whitespace) else {
+            break;
+        };
+        let after_name = after_name.trim_start();
+        let Some(quote) = after_name
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+        else {
+            continue;
+        };
+        let Some((value, after_value)) = after_name[1..].split_once(quote) else {
+            break;
+        };
+
+        entities.insert(name.to_owned(), value.to_owned());
+        rest = after_value;
+    }
+
+    entities
+}
+
+/// Parses the `Rev x.yy` revision markers from the DTD comments.
+fn parse_revisions(doctype: &str) -> Vec<String> {
+    doctype
+        .match_indices("Rev ")
+        .filter_map(|(index, _)| {
+            doctype[index + "Rev ".len()..]
+                .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .next()
+                .filter(|revision| !revision.is_empty())
+                .map(|revision| revision.trim_end_matches('.').to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use crate::database::{
+        dictionary::{cdb::CDBDictionaryBuilder, Dictionary},
+        storage,
+    };
+
+    use super::*;
+
+    const TEXT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE JMdict [
+<!ELEMENT JMdict (entry*)>
+<!-- Rev 1.09
+	Added the "gloss" g_type attribute.
+	Rev 1.08 -->
+<!ENTITY v5k "Godan verb with 'ku' ending">
+<!ENTITY vt "transitive verb">
+<!ENTITY n "noun (common) (futsuumeishi)">
+<!ENTITY uk "word usually written using kana alone">
+<!ENTITY rK "rarely used kanji form">
+]>
+<!-- JMdict created: 2023-12-04 -->
+<JMdict>
+<entry>
+<ent_seq>1000320</ent_seq>
+<k_ele>
+<keb>彼処</keb>
+<ke_inf>&rK;</ke_inf>
+</k_ele>
+<r_ele>
+<reb>あそこ</reb>
+<re_pri>ichi1</re_pri>
+<re_pri>nf12</re_pri>
+</r_ele>
+<r_ele>
+<reb>アソコ</reb>
+<re_nokanji/>
+</r_ele>
+<sense>
+<pos>&n;</pos>
+<misc>&uk;</misc>
+<gloss>there</gloss>
+<gloss>over there</gloss>
+</sense>
+<sense>
+<stagr>あそこ</stagr>
+<gloss>that far</gloss>
+</sense>
+</entry>
+<entry>
+<ent_seq>1216280</ent_seq>
+<k_ele>
+<keb>聞く</keb>
+<ke_pri>news1</ke_pri>
+</k_ele>
+<r_ele>
+<reb>きく</reb>
+</r_ele>
+<sense>
+<pos>&v5k;</pos>
+<pos>&vt;</pos>
+<gloss>to hear</gloss>
+</sense>
+</entry>
+</JMdict>
+"#;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let dict_path = temp_dir.path().join("JMdict_e.gz");
+        let mut encoder = GzEncoder::new(
+            fs::File::create(&dict_path).expect("could not create dictionary file in temp dir"),
+            Compression::default(),
+        );
+        encoder
+            .write_all(TEXT.as_bytes())
+            .expect("could not write dictionary file to temp dir");
+        encoder.finish().unwrap();
+
+        let path = temp_dir.path().join("jmdict-test-basic");
+        let dict_builder =
+            CDBDictionaryBuilder::new(path.to_str().expect("cdb database path is not valid utf-8"))
+                .unwrap();
+        let jmdict = JMDictImporter::import_path(&dict_path, dict_builder, &Default::default())
+            .expect("error while importing dictionary file");
+
+        let expected = r#"[DictionaryEntry { readings: ["あそこ"], gloss: "there\nover there", tags: ["uk", "n", "rK"], id: Some("1000320") }, DictionaryEntry { readings: ["あそこ"], gloss: "that far", tags: ["n", "rK"], id: Some("1000320") }]"#;
+        assert_eq!(format!("{:?}", jmdict.get("彼処")), expected);
+
+        let expected = r#"[DictionaryEntry { readings: ["あそこ"], gloss: "there\nover there", tags: ["uk", "n", "ichi1", "nf12"], id: Some("1000320") }, DictionaryEntry { readings: ["あそこ"], gloss: "that far", tags: ["n", "ichi1", "nf12"], id: Some("1000320") }]"#;
+        assert_eq!(format!("{:?}", jmdict.get("あそこ")), expected);
+
+        assert_eq!(jmdict.get("アソコ").len(), 1);
+        assert_eq!(
+            format!("{:?}", jmdict.get("聞く")),
+            r#"[DictionaryEntry { readings: ["きく"], gloss: "to hear", tags: ["v5k", "vt", "news1"], id: Some("1216280") }]"#
+        );
+
+        let metadata = jmdict.get_metadata();
+        assert_eq!(metadata.version(), &Version::new(1, 9, 0));
+        assert_eq!(metadata.source_date(), Some("2023-12-04"));
+        assert_eq!(metadata.tags()["v5k"], "Godan verb with 'ku' ending");
+        assert!(metadata.tags()["nf12"].contains("12"));
+        assert_eq!(metadata.tags()["rK"], "rarely used kanji form");
+        let import_info = metadata.import_info().unwrap();
+        assert_eq!(import_info.importer, JMDictImporter::NAME);
+        assert_eq!(
+            import_info.source_hash,
+            Some(storage::checksum_files(&[dict_path]).unwrap())
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs::File, io::BufReader, path::Path};
+use std::{collections::HashMap, io::BufRead};
 
 use semver::Version;
 use serde::{
@@ -12,7 +12,7 @@ use crate::database::dictionary::{
     importer, DictionaryBuilder, DictionaryEntry, DictionaryMetadata,
 };
 
-use super::Importer;
+use super::{context::ImportContext, parse_version, pipeline::with_pipeline, Importer};
 
 #[derive(Debug)]
 pub struct JMDictSimplifiedImporter {}
@@ -20,81 +20,184 @@ pub struct JMDictSimplifiedImporter {}
 impl Importer for JMDictSimplifiedImporter {
     type Error = Error;
 
-    fn import<DB>(
-        path: impl AsRef<Path>,
-        dict_builder: DB,
-    ) -> Result<DB::Dictionary, importer::Error<Self::Error, DB::Error>>
+    const NAME: &'static str = "JMdict Simplified JSON";
+    const EXTENSIONS: &'static [&'static str] = &["json"];
+
+    fn detect(head: &[u8]) -> bool {
+        let head = String::from_utf8_lossy(head);
+        head.trim_start_matches('\u{feff}')
+            .trim_start()
+            .starts_with('{')
+            && head.contains("\"dictRevisions\"")
+            && head.contains("\"commonOnly\"")
+    }
+
+    fn import_entries<R, DB>(
+        reader: R,
+        dict_builder: &mut DB,
+        context: &ImportContext,
+    ) -> Result<DictionaryMetadata, importer::Error<Self::Error, DB::Error>>
     where
+        R: BufRead,
         DB: DictionaryBuilder,
     {
-        let reader = BufReader::new(File::open(path).map_err(importer::Error::DictFileIo)?);
         let mut deserializer = serde_json::Deserializer::from_reader(reader);
-        let jmdict_deserializer = JMDictDeserializer { dict_builder };
+        let jmdict_deserializer = JMDictDeserializer {
+            dict_builder,
+            context,
+        };
         let jmdict = jmdict_deserializer
             .deserialize(&mut deserializer)
             .map_err(Error::Deserialization)
             .map_err(importer::Error::ImporterSpecific)?;
 
-        Ok(jmdict.dict_builder.build(DictionaryMetadata {
-            name: "JMDict".to_owned(),
-            author: "Electronic Dictionary Research and Development Group (http://www.edrdg.org/edrdg/licence.html)".to_owned(),
-            version: parse_version(jmdict.dict_revisions.first().expect("JMDict dict_revisions should not be empty")).expect("invalid JMDict dict_revisions version"),
-            homepage_url: Some(Url::parse("https://github.com/scriptin/jmdict-simplified").unwrap()),
-            update_url: None,
-            notes: "".to_owned(),
-        }).map_err(importer::Error::DictBuilder)?)
-    }
-}
+        let version = match jmdict.dict_revisions.first() {
+            Some(revision) => {
+                parse_version(revision).map_err(|_| Error::InvalidRevision(revision.clone()))
+            }
+            None => Err(Error::MissingRevision),
+        };
+        let version = match version {
+            Ok(version) => version,
+            Err(error) if context.is_tolerant() => {
+                context.warn("$.dictRevisions", &error);
+                Version::new(0, 0, 0)
+            }
+            Err(error) => return Err(importer::Error::ImporterSpecific(error)),
+        };
 
-fn parse_version(s: &str) -> Result<Version, Box<dyn std::error::Error>> {
-    Ok(Version::parse(
-        &s.split('.')
-            .map(str::parse::<u32>)
-            .collect::<Result<Vec<u32>, _>>()?
-            .into_iter()
-            .chain(vec![0].into_iter())
-            .map(|a| a.to_string())
-            .collect::<Vec<String>>()
-            .join("."),
-    )?)
+        // Releases differ by languages and by whether they only have common words, so the name tells them apart.
+        let mut variant = jmdict.languages.clone();
+        if jmdict.common_only {
+            variant.push("common words only".to_owned());
+        }
+        let name = if variant.is_empty() {
+            "JMDict".to_owned()
+        } else {
+            format!("JMDict ({})", variant.join(", "))
+        };
+
+        Ok(DictionaryMetadata::builder(name)
+            .author("Electronic Dictionary Research and Development Group (http://www.edrdg.org/edrdg/licence.html)")
+            .version(version)
+            .homepage_url(Some(Url::parse("https://github.com/scriptin/jmdict-simplified").unwrap()))
+            .update_url(Some(Url::parse("https://github.com/scriptin/jmdict-simplified/releases/latest").unwrap()))
+            .tags(jmdict.tags.into_iter().collect())
+            .source_date(Some(jmdict.dict_date).filter(|date| !date.is_empty()))
+            .languages(jmdict.languages)
+            .format_version(Some(jmdict.version).filter(|version| !version.is_empty()))
+            .build())
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("JMDict Simplified JSON deserialization error")]
     Deserialization(#[from] serde_json::Error),
+    #[error("JMDict Simplified file has no dictRevisions")]
+    MissingRevision,
+    #[error("invalid JMDict Simplified revision {:?}", .0)]
+    InvalidRevision(String),
 }
 
-struct JMDict<DB>
-where
-    DB: DictionaryBuilder,
-{
-    #[allow(dead_code)]
+/// JSON location of a word, with its id when it has one.
+fn word_location(index: usize, word: &serde_json::Value) -> String {
+    match word.get("id").and_then(serde_json::Value::as_str) {
+        Some(id) => format!("$.words[{index}] (id {id})"),
+        None => format!("$.words[{index}]"),
+    }
+}
+
+/// Turns a word into one entry per sense for each of its kanji and kana.
+fn word_entries(word: JMDictWord) -> Vec<(String, DictionaryEntry)> {
+    let mut entries = Vec::new();
+
+    for sense in word.sense {
+        let mut tags = Vec::with_capacity(
+            sense.dialect.len() + sense.field.len() + sense.misc.len() + sense.part_of_speech.len(),
+        );
+        tags.extend(sense.dialect);
+        tags.extend(sense.field);
+        tags.extend(sense.misc);
+        tags.extend(sense.part_of_speech);
+
+        let applies_to_kanji = match sense.applies_to_kanji.first() {
+            Some(s) if s == "*" => word.kanji.iter().map(|k| k.text.clone()).collect(),
+            _ => sense.applies_to_kanji,
+        };
+
+        let applies_to_kana = match sense.applies_to_kana.first() {
+            Some(s) if s == "*" => word.kana.iter().map(|k| k.text.clone()).collect(),
+            _ => sense.applies_to_kana,
+        };
+
+        let gloss = sense
+            .gloss
+            .iter()
+            .map(|gloss| gloss.text.as_ref())
+            .collect::<Vec<&str>>()
+            .join("\n");
+
+        for applies_to in applies_to_kana.iter() {
+            let entry = DictionaryEntry {
+                readings: vec![applies_to.clone()],
+                gloss: gloss.clone(),
+                tags: tags.clone(),
+                id: Some(word.id.clone()),
+            };
+
+            entries.push((applies_to.clone(), entry));
+        }
+
+        for applies_to in applies_to_kanji.iter() {
+            let readings = word
+                .kana
+                .iter()
+                .filter_map(|kana| {
+                    if kana.applies_to_kanji.first().map(|s| s as &str) == Some("*")
+                        || kana.applies_to_kanji.contains(applies_to)
+                    {
+                        Some(kana.text.clone())
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            let entry = DictionaryEntry {
+                readings,
+                gloss: gloss.clone(),
+                tags: tags.clone(),
+                id: Some(word.id.clone()),
+            };
+
+            entries.push((applies_to.clone(), entry));
+        }
+    }
+
+    entries
+}
+
+struct JMDict {
     common_only: bool,
-    #[allow(dead_code)]
     dict_date: String,
     dict_revisions: Vec<String>,
-    #[allow(dead_code)]
     languages: Vec<String>,
-    #[allow(dead_code)]
     tags: HashMap<String, String>,
-    #[allow(dead_code)]
     version: String,
-    dict_builder: DB,
 }
 
-struct JMDictDeserializer<DB>
+struct JMDictDeserializer<'a, DB>
 where
     DB: DictionaryBuilder,
 {
-    dict_builder: DB,
+    dict_builder: &'a mut DB,
+    context: &'a ImportContext,
 }
 
 #[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct JMDictWord {
-    #[serde(skip)]
-    #[allow(dead_code)]
     id: String,
     kana: Vec<JMDictKana>,
     kanji: Vec<JMDictKanji>,
@@ -164,41 +267,43 @@ struct JMDictGloss {
     type_: Option<String>,
 }
 
-impl<'de, DB> DeserializeSeed<'de> for JMDictDeserializer<DB>
+impl<'de, 'a, DB> DeserializeSeed<'de> for JMDictDeserializer<'a, DB>
 where
-    DB: DictionaryBuilder + 'de,
+    DB: DictionaryBuilder,
 {
-    type Value = JMDict<DB>;
+    type Value = JMDict;
 
     fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: Deserializer<'de>,
     {
-        struct JMDictVisitor<DB: DictionaryBuilder> {
-            dict_builder: DB,
+        struct JMDictVisitor<'a, DB: DictionaryBuilder> {
+            dict_builder: &'a mut DB,
+            context: &'a ImportContext,
         }
 
-        struct JMDictWordsSeed<DB: DictionaryBuilder> {
-            dict_builder: DB,
+        struct JMDictWordsSeed<'a, DB: DictionaryBuilder> {
+            dict_builder: &'a mut DB,
+            context: &'a ImportContext,
         }
 
-        impl<'de, DB> DeserializeSeed<'de> for JMDictWordsSeed<DB>
+        impl<'de, 'a, DB> DeserializeSeed<'de> for JMDictWordsSeed<'a, DB>
         where
             DB: DictionaryBuilder,
         {
-            type Value = DB;
+            type Value = ();
 
             fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
             where
                 D: Deserializer<'de>,
             {
-                struct JMDictWordsVisitor<DB: DictionaryBuilder>(JMDictWordsSeed<DB>);
+                struct JMDictWordsVisitor<'a, DB: DictionaryBuilder>(JMDictWordsSeed<'a, DB>);
 
-                impl<'de, DB> Visitor<'de> for JMDictWordsVisitor<DB>
+                impl<'de, 'a, DB> Visitor<'de> for JMDictWordsVisitor<'a, DB>
                 where
                     DB: DictionaryBuilder,
                 {
-                    type Value = DB;
+                    type Value = ();
 
                     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
                         formatter.write_str("array of JMDictWord")
@@ -208,84 +313,43 @@ where
                     where
                         A: SeqAccess<'de>,
                     {
-                        let mut dict_builder = self.0.dict_builder;
-
-                        while let Some(word) = seq.next_element::<JMDictWord>()? {
-                            for sense in word.sense {
-                                let mut tags = Vec::with_capacity(
-                                    sense.dialect.len()
-                                        + sense.field.len()
-                                        + sense.misc.len()
-                                        + sense.part_of_speech.len(),
-                                );
-                                tags.extend(sense.dialect);
-                                tags.extend(sense.field);
-                                tags.extend(sense.misc);
-                                tags.extend(sense.part_of_speech);
-
-                                let applies_to_kanji = match sense.applies_to_kanji.first() {
-                                    Some(s) if s == "*" => {
-                                        word.kanji.iter().map(|k| k.text.clone()).collect()
-                                    }
-                                    _ => sense.applies_to_kanji,
-                                };
-
-                                let applies_to_kana = match sense.applies_to_kana.first() {
-                                    Some(s) if s == "*" => {
-                                        word.kana.iter().map(|k| k.text.clone()).collect()
-                                    }
-                                    _ => sense.applies_to_kana,
-                                };
-
-                                let gloss = sense
-                                    .gloss
-                                    .iter()
-                                    .map(|gloss| gloss.text.as_ref())
-                                    .collect::<Vec<&str>>()
-                                    .join("\n");
-
-                                for applies_to in applies_to_kana.iter() {
-                                    let entry = DictionaryEntry {
-                                        readings: vec![applies_to.clone()],
-                                        gloss: gloss.clone(),
-                                        tags: tags.clone(),
+                        let dict_builder = self.0.dict_builder;
+                        let context = self.0.context;
+
+                        with_pipeline(word_entries, dict_builder.encoder(), |pipeline| {
+                            let mut index = 0;
+                            loop {
+                                let word = if context.is_tolerant() {
+                                    // Parsing into a value first leaves the sequence usable when a word does not match the schema.
+                                    let Some(value) = seq.next_element::<serde_json::Value>()?
+                                    else {
+                                        break;
                                     };
-
-                                    dict_builder
-                                        .add(applies_to, entry)
-                                        .map_err(A::Error::custom)?;
-                                }
-
-                                for applies_to in applies_to_kanji.iter() {
-                                    let readings = word
-                                        .kana
-                                        .iter()
-                                        .filter_map(|kana| {
-                                            if kana.applies_to_kanji.first().map(|s| s as &str)
-                                                == Some("*")
-                                                || kana.applies_to_kanji.contains(applies_to)
-                                            {
-                                                Some(kana.text.clone())
-                                            } else {
-                                                None
-                                            }
-                                        })
-                                        .collect();
-
-                                    let entry = DictionaryEntry {
-                                        readings,
-                                        gloss: gloss.clone(),
-                                        tags: tags.clone(),
+                                    match JMDictWord::deserialize(&value) {
+                                        Ok(word) => word,
+                                        Err(error) => {
+                                            context
+                                                .word_skipped(word_location(index, &value), error);
+                                            index += 1;
+                                            continue;
+                                        }
+                                    }
+                                } else {
+                                    let Some(word) = seq.next_element::<JMDictWord>()? else {
+                                        break;
                                     };
+                                    word
+                                };
+                                index += 1;
 
-                                    dict_builder
-                                        .add(applies_to, entry)
-                                        .map_err(A::Error::custom)?;
-                                }
+                                pipeline
+                                    .push(word, dict_builder)
+                                    .map_err(A::Error::custom)?;
+                                context.word_processed().map_err(A::Error::custom)?;
                             }
-                        }
 
-                        Ok(dict_builder)
+                            pipeline.finish(dict_builder).map_err(A::Error::custom)
+                        })
                     }
                 }
 
@@ -293,17 +357,17 @@ where
             }
         }
 
-        impl<'de, DB> Visitor<'de> for JMDictVisitor<DB>
+        impl<'de, 'a, DB> Visitor<'de> for JMDictVisitor<'a, DB>
         where
-            DB: DictionaryBuilder + 'de,
+            DB: DictionaryBuilder,
         {
-            type Value = JMDict<DB>;
+            type Value = JMDict;
 
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
                 formatter.write_str("JMDict struct")
             }
 
-            fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
             where
                 A: serde::de::MapAccess<'de>,
             {
@@ -345,10 +409,16 @@ where
                             version = map.next_value()?;
                         }
                         "words" => {
-                            self.dict_builder = map.next_value_seed(JMDictWordsSeed {
-                                dict_builder: self.dict_builder,
+                            map.next_value_seed(JMDictWordsSeed {
+                                dict_builder: &mut *self.dict_builder,
+                                context: self.context,
                             })?;
                         }
+                        unknown if self.context.is_tolerant() => {
+                            map.next_value::<de::IgnoredAny>()?;
+                            self.context
+                                .warn(format!("$.{unknown}"), "unknown field was skipped");
+                        }
                         unknown => {
                             return Err(de::Error::unknown_field(unknown, FIELDS));
                         }
@@ -362,13 +432,13 @@ where
                     languages,
                     tags,
                     version,
-                    dict_builder: self.dict_builder,
                 })
             }
         }
 
         deserializer.deserialize_map(JMDictVisitor {
             dict_builder: self.dict_builder,
+            context: self.context,
         })
     }
 }
@@ -377,7 +447,9 @@ where
 mod tests {
     use std::fs;
 
-    use crate::database::dictionary::{cdb::CDBDictionaryBuilder, Dictionary};
+    use crate::database::dictionary::{
+        cdb::CDBDictionaryBuilder, importer::context::CancellationToken, Dictionary,
+    };
 
     use super::*;
 
@@ -410,10 +482,89 @@ mod tests {
         let dict_builder =
             CDBDictionaryBuilder::new(path.to_str().expect("cdb database path is not valid utf-8"))
                 .unwrap();
-        let jmdict = JMDictSimplifiedImporter::import(&dict_path, dict_builder)
-            .expect("error while importing dictionary file");
+        let jmdict =
+            JMDictSimplifiedImporter::import_path(&dict_path, dict_builder, &Default::default())
+                .expect("error while importing dictionary file");
 
-        let expected = r#"[DictionaryEntry { readings: ["あそこ", "あすこ", "かしこ", "あしこ", "あこ"], gloss: "there\nover there\nthat place\nyonder\nyou-know-where", tags: ["uk", "pn"] }, DictionaryEntry { readings: ["あそこ", "あすこ", "かしこ", "あしこ", "あこ"], gloss: "genitals\nprivate parts\nnether regions", tags: ["col", "uk", "euph", "n"] }, DictionaryEntry { readings: ["あそこ", "あすこ", "かしこ", "あしこ", "あこ"], gloss: "that far\nthat much\nthat point", tags: ["uk", "n"] }]"#;
+        let expected = r#"[DictionaryEntry { readings: ["あそこ", "あすこ", "かしこ", "あしこ", "あこ"], gloss: "there\nover there\nthat place\nyonder\nyou-know-where", tags: ["uk", "pn"], id: Some("1000320") }, DictionaryEntry { readings: ["あそこ", "あすこ", "かしこ", "あしこ", "あこ"], gloss: "genitals\nprivate parts\nnether regions", tags: ["col", "uk", "euph", "n"], id: Some("1000320") }, DictionaryEntry { readings: ["あそこ", "あすこ", "かしこ", "あしこ", "あこ"], gloss: "that far\nthat much\nthat point", tags: ["uk", "n"], id: Some("1000320") }]"#;
         assert_eq!(format!("{:?}", jmdict.get("彼処")), expected);
+
+        let metadata = jmdict.get_metadata();
+        assert_eq!(metadata.name(), "JMDict (eng)");
+        assert_eq!(metadata.version(), &Version::new(1, 9, 0));
+        assert_eq!(metadata.source_date(), Some("2023-12-04"));
+        assert_eq!(metadata.languages(), ["eng"]);
+        assert_eq!(metadata.format_version(), Some("3.5.0"));
+    }
+
+    #[test]
+    fn tolerant() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let text = r#"{
+"version": "3.6.0",
+"languages": ["eng"],
+"commonOnly": false,
+"dictDate": "2024-01-01",
+"dictRevisions": [],
+"newField": {"nested": [1, 2, 3]},
+"tags": {},
+"words": [
+{"id":"1000360","kanji":[],"kana":[{"common":true,"text":"あっさり","tags":[],"appliesToKanji":["*"]}],"sense":[{"partOfSpeech":["adv"],"appliesToKanji":["*"],"appliesToKana":["*"],"related":[],"antonym":[],"field":[],"dialect":[],"misc":[],"info":[],"languageSource":[],"gloss":[{"lang":"eng","gender":null,"type":null,"text":"easily"}]}]},
+{"id":"1000390","kanji":[],"kana":"あっというまに","sense":[]},
+{"id":"1000400","kanji":[],"kana":[{"common":true,"text":"あっぱれ","tags":[],"appliesToKanji":["*"]}],"sense":[{"partOfSpeech":["adj-na"],"appliesToKanji":["*"],"appliesToKana":["*"],"related":[],"antonym":[],"field":[],"dialect":[],"misc":[],"info":[],"languageSource":[],"gloss":[{"lang":"eng","gender":null,"type":null,"text":"admirable"}]}]}
+]}
+"#;
+
+        let strict = JMDictSimplifiedImporter::import(
+            text.as_bytes(),
+            CDBDictionaryBuilder::new(temp_dir.path().join("strict")).unwrap(),
+            &Default::default(),
+        );
+        assert!(matches!(
+            strict,
+            Err(importer::Error::ImporterSpecific(Error::Deserialization(_)))
+        ));
+
+        let context = ImportContext::new().with_tolerance(true);
+        let jmdict = JMDictSimplifiedImporter::import(
+            text.as_bytes(),
+            CDBDictionaryBuilder::new(temp_dir.path().join("tolerant")).unwrap(),
+            &context,
+        )
+        .expect("error while importing dictionary file");
+
+        assert_eq!(jmdict.get("あっさり").len(), 1);
+        assert_eq!(jmdict.get("あっぱれ").len(), 1);
+        assert_eq!(jmdict.get_metadata().version(), &Version::new(0, 0, 0));
+
+        let report = context.report();
+        assert_eq!(report.words_processed, 2);
+        assert_eq!(report.words_skipped, 1);
+        let locations = report
+            .warnings
+            .iter()
+            .map(|warning| warning.location.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            locations,
+            vec!["$.newField", "$.words[1] (id 1000390)", "$.dictRevisions"]
+        );
+    }
+
+    #[test]
+    fn cancelled() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let path = temp_dir.path().join("jmdict-simplified-test-cancelled");
+        let dict_builder = CDBDictionaryBuilder::new(&path).unwrap();
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let context = ImportContext::new().with_cancellation(cancellation);
+
+        let result = JMDictSimplifiedImporter::import(&b"{}"[..], dict_builder, &context);
+
+        assert!(matches!(result, Err(importer::Error::Cancelled)));
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
     }
 }
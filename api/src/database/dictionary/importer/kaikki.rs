@@ -0,0 +1,315 @@
+use std::{collections::BTreeMap, io::BufRead};
+
+use serde::Deserialize;
+use thiserror::Error;
+use url::Url;
+
+use crate::database::dictionary::{
+    importer, DictionaryBuilder, DictionaryEntry, DictionaryMetadata,
+};
+
+use super::{context::ImportContext, pipeline::with_pipeline, Importer};
+
+/// Importer for the Wiktionary extracts of kaikki.org (JSON Lines, one word per line).
+///
+/// Only Japanese words are imported, so both the Japanese-only extract and the full dump can be used.
+/// Senses become entries, with their usage examples and the word's etymology appended to the gloss.
+/// Inflected forms from the inflection tables become additional keys, so conjugated words can be looked up directly.
+#[derive(Debug)]
+pub struct KaikkiImporter {}
+
+impl Importer for KaikkiImporter {
+    type Error = Error;
+
+    const NAME: &'static str = "Wiktionary (kaikki.org) JSONL";
+    const EXTENSIONS: &'static [&'static str] = &["jsonl"];
+
+    fn detect(head: &[u8]) -> bool {
+        let head = String::from_utf8_lossy(head);
+        head.starts_with('{') && head.contains("\"lang_code\"") && head.contains("\"pos\"")
+    }
+
+    fn import_entries<R, DB>(
+        reader: R,
+        dict_builder: &mut DB,
+        context: &ImportContext,
+    ) -> Result<DictionaryMetadata, importer::Error<Self::Error, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder,
+    {
+        let mut used_tags = BTreeMap::new();
+
+        with_pipeline(
+            word_entries,
+            dict_builder.encoder(),
+            |pipeline| -> Result<(), importer::Error<Error, DB::Error>> {
+                for (index, line) in reader.lines().enumerate() {
+                    let line = line.map_err(importer::Error::DictFileIo)?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    let word = match serde_json::from_str::<KaikkiWord>(&line) {
+                        Ok(word) => word,
+                        Err(error) if context.is_tolerant() => {
+                            context.word_skipped(format!("line {}", index + 1), error);
+                            continue;
+                        }
+                        Err(error) => {
+                            return Err(importer::Error::ImporterSpecific(Error::Deserialization {
+                                line: index + 1,
+                                error,
+                            }))
+                        }
+                    };
+                    if word.lang_code != "ja" {
+                        continue;
+                    }
+
+                    if let Some(description) = describe_part_of_speech(&word.pos) {
+                        used_tags.insert(word.pos.clone(), description.to_owned());
+                    }
+                    pipeline
+                        .push(word, dict_builder)
+                        .map_err(importer::Error::DictBuilder)?;
+                    context
+                        .word_processed()
+                        .map_err(|_| importer::Error::Cancelled)?;
+                }
+
+                pipeline
+                    .finish(dict_builder)
+                    .map_err(importer::Error::DictBuilder)
+            },
+        )?;
+
+        Ok(DictionaryMetadata::builder("Wiktionary")
+            .author("Wiktionary contributors, extracted by kaikki.org (https://kaikki.org/dictionary/rawdata.html)")
+            // The extracts are not versioned, only dated, and the date is not part of the data.
+            .homepage_url(Some(Url::parse("https://kaikki.org/dictionary/Japanese/").unwrap()))
+            .notes("Licensed under CC BY-SA 4.0 and GFDL, like Wiktionary.")
+            .tags(used_tags)
+            .languages(vec!["en".to_owned()])
+            .build())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("kaikki.org JSON deserialization error on line {}", .line)]
+    Deserialization {
+        line: usize,
+        #[source]
+        error: serde_json::Error,
+    },
+}
+
+/// Tags of `forms` which are not inflections or alternative spellings of the word.
+const NON_KEY_FORM_TAGS: &[&str] = &[
+    "canonical",
+    "romanization",
+    "table-tags",
+    "inflection-template",
+    "class",
+];
+
+/// Tags of `forms` which mark a reading of the word.
+const READING_FORM_TAGS: &[&str] = &["hiragana", "katakana"];
+
+/// Descriptions of the parts of speech used by Wiktionary.
+const PARTS_OF_SPEECH: &[(&str, &str)] = &[
+    ("adj", "adjective"),
+    ("adnominal", "adnominal (rentaishi)"),
+    ("adv", "adverb"),
+    ("affix", "affix"),
+    ("character", "character"),
+    ("conj", "conjunction"),
+    ("counter", "counter"),
+    ("intj", "interjection"),
+    ("name", "proper noun"),
+    ("noun", "noun"),
+    ("num", "numeral"),
+    ("particle", "particle"),
+    ("phrase", "phrase"),
+    ("prefix", "prefix"),
+    ("pron", "pronoun"),
+    ("proverb", "proverb"),
+    ("suffix", "suffix"),
+    ("verb", "verb"),
+];
+
+fn describe_part_of_speech(pos: &str) -> Option<&'static str> {
+    PARTS_OF_SPEECH
+        .iter()
+        .find(|(name, _)| *name == pos)
+        .map(|(_, description)| *description)
+}
+
+#[derive(Deserialize)]
+struct KaikkiWord {
+    word: String,
+    #[serde(default)]
+    lang_code: String,
+    #[serde(default)]
+    pos: String,
+    #[serde(default)]
+    etymology_text: Option<String>,
+    /// Tells apart words spelled the same but of different origins, which Wiktionary numbers from 1.
+    #[serde(default)]
+    etymology_number: Option<u32>,
+    #[serde(default)]
+    forms: Vec<KaikkiForm>,
+    #[serde(default)]
+    senses: Vec<KaikkiSense>,
+}
+
+#[derive(Deserialize)]
+struct KaikkiForm {
+    form: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct KaikkiSense {
+    #[serde(default)]
+    glosses: Vec<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    examples: Vec<KaikkiExample>,
+}
+
+#[derive(Deserialize)]
+struct KaikkiExample {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    english: Option<String>,
+}
+
+/// Turns a word into one entry per sense for each of its keys and readings.
+fn word_entries(word: KaikkiWord) -> Vec<(String, DictionaryEntry)> {
+    let mut entries = Vec::new();
+    let mut readings: Vec<String> = Vec::new();
+    let mut keys = vec![word.word.clone()];
+    for form in word.forms {
+        if form.form == word.word
+            || form.form.is_ascii()
+            || form
+                .tags
+                .iter()
+                .any(|tag| NON_KEY_FORM_TAGS.contains(&tag.as_str()))
+        {
+            continue;
+        }
+
+        // Inflected forms are tagged as hiragana too, but are keys rather than readings.
+        if !form.tags.is_empty()
+            && form
+                .tags
+                .iter()
+                .all(|tag| READING_FORM_TAGS.contains(&tag.as_str()))
+        {
+            if !readings.contains(&form.form) {
+                readings.push(form.form);
+            }
+        } else if !keys.contains(&form.form) {
+            keys.push(form.form);
+        }
+    }
+
+    // Wiktionary has no identifiers for words, but a page has one word per spelling, part of speech and etymology.
+    let id = match word.etymology_number {
+        Some(number) => format!("{}:{}:{number}", word.word, word.pos),
+        None => format!("{}:{}", word.word, word.pos),
+    };
+
+    for sense in word
+        .senses
+        .into_iter()
+        .filter(|sense| !sense.glosses.is_empty())
+    {
+        let mut gloss = sense.glosses.join("\n");
+        for example in sense
+            .examples
+            .iter()
+            .filter(|example| !example.text.is_empty())
+        {
+            gloss.push_str("\nExample: ");
+            gloss.push_str(&example.text);
+            if let Some(english) = &example.english {
+                gloss.push_str(" (");
+                gloss.push_str(english);
+                gloss.push(')');
+            }
+        }
+        // Shared by every sense, but each is its own entry and may be shown without the others.
+        if let Some(etymology) = &word.etymology_text {
+            gloss.push_str("\nEtymology: ");
+            gloss.push_str(etymology);
+        }
+
+        let mut tags = sense.tags;
+        tags.push(word.pos.clone());
+
+        let entry = DictionaryEntry {
+            readings: readings.clone(),
+            gloss,
+            tags,
+            id: Some(id.clone()),
+        };
+        for key in keys.iter().chain(readings.iter()) {
+            entries.push((key.clone(), entry.clone()));
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::database::dictionary::{cdb::CDBDictionaryBuilder, Dictionary};
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let jsonl = r#"{"word": "食べる", "lang_code": "ja", "lang": "Japanese", "pos": "verb", "etymology_text": "From Old Japanese.", "forms": [{"form": "食べる", "tags": ["canonical"]}, {"form": "たべる", "tags": ["hiragana"]}, {"form": "taberu", "tags": ["romanization"]}, {"form": "食べない", "tags": ["negative"], "source": "conjugation"}, {"form": "たべない", "tags": ["hiragana", "negative"], "source": "conjugation"}], "senses": [{"glosses": ["to eat"], "tags": ["transitive"], "examples": [{"text": "ご飯を食べる。", "english": "to eat a meal"}]}, {"glosses": ["to live on"]}]}
+{"word": "cat", "lang_code": "en", "pos": "noun", "senses": [{"glosses": ["a small feline"]}]}
+{"word": "ね", "lang_code": "ja", "pos": "particle", "etymology_number": 2, "senses": [{"glosses": ["sentence-ending particle seeking agreement"], "tags": ["colloquial"]}]}
+"#;
+        let path = temp_dir.path().join("kaikki.org-dictionary-Japanese.jsonl");
+        fs::write(&path, jsonl).expect("could not write dictionary file to temp dir");
+
+        let dict_builder =
+            CDBDictionaryBuilder::new(temp_dir.path().join("kaikki-test-basic")).unwrap();
+        let dict = KaikkiImporter::import_path(&path, dict_builder, &Default::default())
+            .expect("error while importing dictionary file");
+
+        let expected = r#"[DictionaryEntry { readings: ["たべる"], gloss: "to eat\nExample: ご飯を食べる。 (to eat a meal)\nEtymology: From Old Japanese.", tags: ["transitive", "verb"], id: Some("食べる:verb") }, DictionaryEntry { readings: ["たべる"], gloss: "to live on\nEtymology: From Old Japanese.", tags: ["verb"], id: Some("食べる:verb") }]"#;
+        assert_eq!(format!("{:?}", dict.get("食べる")), expected);
+        assert_eq!(format!("{:?}", dict.get("たべない")), expected);
+        assert_eq!(
+            dict.get("ね").first().unwrap().tags,
+            vec!["colloquial", "particle"]
+        );
+        assert_eq!(
+            dict.get("ね").first().unwrap().id.as_deref(),
+            Some("ね:particle:2")
+        );
+        assert!(dict.get("cat").is_empty());
+        assert_eq!(
+            dict.get_metadata()
+                .tags()
+                .get("particle")
+                .map(String::as_str),
+            Some("particle")
+        );
+    }
+}
@@ -1,20 +1,99 @@
-use std::path::Path;
+use std::{
+    fs::File,
+    io::BufRead,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use semver::Version;
 use thiserror::Error;
 
-use super::DictionaryBuilder;
+use self::context::{ImportContext, ImportPhase};
 
+use super::{DictionaryBuilder, DictionaryMetadata, ImportInfo};
+
+pub mod context;
+pub mod dictzip;
+pub mod jmdict;
 pub mod jmdict_simplified;
+pub mod kaikki;
+pub(crate) mod pipeline;
+pub mod registry;
+pub mod source;
+pub mod stardict;
 
 pub trait Importer: Sized {
     type Error: std::error::Error;
 
-    fn import<DB>(
+    /// Human-readable name of the format.
+    const NAME: &'static str;
+    /// File extensions of the format, without compression or archive extensions.
+    const EXTENSIONS: &'static [&'static str];
+
+    /// Checks whether the first (decompressed) bytes of a source look like this format.
+    fn detect(head: &[u8]) -> bool;
+
+    /// Adds every entry of an uncompressed source to `dict_builder`, returning the dictionary's metadata.
+    ///
+    /// Implementors should call [`ImportContext::word_processed`] for every word, and bail out when it fails.
+    fn import_entries<R, DB>(
+        reader: R,
+        dict_builder: &mut DB,
+        context: &ImportContext,
+    ) -> Result<DictionaryMetadata, Error<Self::Error, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder;
+
+    /// Imports a dictionary from an uncompressed source.
+    ///
+    /// Use [`source::decompress`] first if the source might be compressed.
+    /// If the import fails or is cancelled, anything written by `dict_builder` is discarded.
+    fn import<R, DB>(
+        reader: R,
+        mut dict_builder: DB,
+        context: &ImportContext,
+    ) -> Result<DB::Dictionary, Error<Self::Error, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder,
+    {
+        let result = context
+            .check_cancelled()
+            .map_err(|_| Error::Cancelled)
+            .and_then(|_| Self::import_entries(reader, &mut dict_builder, context));
+
+        finish_import(Self::NAME, result, dict_builder, context)
+    }
+
+    /// Imports a dictionary from a file, which may be compressed or inside an archive.
+    fn import_path<DB>(
         path: impl AsRef<Path>,
-        dict_builder: DB,
+        mut dict_builder: DB,
+        context: &ImportContext,
     ) -> Result<DB::Dictionary, Error<Self::Error, DB::Error>>
     where
-        DB: DictionaryBuilder;
+        DB: DictionaryBuilder,
+    {
+        let file = File::open(path).map_err(Error::DictFileIo)?;
+        let mut source = context.track_source(file);
+
+        let result = context
+            .check_cancelled()
+            .map_err(|_| Error::Cancelled)
+            .and_then(|_| {
+                source::decompress(&mut source, |reader| {
+                    Self::import_entries(reader, &mut dict_builder, context)
+                })
+                .map_err(Error::DictFileIo)?
+            })
+            .and_then(|metadata| {
+                source.finish_source().map_err(Error::DictFileIo)?;
+                Ok(metadata)
+            });
+
+        finish_import(Self::NAME, result, dict_builder, context)
+    }
 }
 
 #[derive(Debug, Error)]
@@ -25,4 +104,81 @@ pub enum Error<IE: std::error::Error, DBE: std::error::Error> {
     DictBuilder(DBE),
     #[error(transparent)]
     ImporterSpecific(IE),
+    #[error("import cancelled")]
+    Cancelled,
+}
+
+impl<IE: std::error::Error, DBE: std::error::Error> Error<IE, DBE> {
+    pub fn map_importer_specific<T: std::error::Error>(
+        self,
+        f: impl FnOnce(IE) -> T,
+    ) -> Error<T, DBE> {
+        match self {
+            Self::DictFileIo(error) => Error::DictFileIo(error),
+            Self::DictBuilder(error) => Error::DictBuilder(error),
+            Self::ImporterSpecific(error) => Error::ImporterSpecific(f(error)),
+            Self::Cancelled => Error::Cancelled,
+        }
+    }
+}
+
+/// Builds the dictionary once every entry was added, or discards it if adding them failed.
+///
+/// The metadata is completed with the [`ImportInfo`] and the display name of the context.
+/// Importers which override [`Importer::import_path`] use this to behave like [`Importer::import`].
+pub(crate) fn finish_import<IE, DB>(
+    importer: &str,
+    result: Result<DictionaryMetadata, Error<IE, DB::Error>>,
+    dict_builder: DB,
+    context: &ImportContext,
+) -> Result<DB::Dictionary, Error<IE, DB::Error>>
+where
+    IE: std::error::Error,
+    DB: DictionaryBuilder,
+{
+    let mut metadata = match result {
+        Ok(metadata) => metadata,
+        Err(error) => {
+            // The import error is more relevant to the caller than a failure to clean up.
+            let _ = dict_builder.discard();
+            return Err(if context.is_cancelled() {
+                Error::Cancelled
+            } else {
+                error
+            });
+        }
+    };
+
+    metadata.set_import_info(ImportInfo {
+        importer: importer.to_owned(),
+        importer_version: env!("CARGO_PKG_VERSION").to_owned(),
+        imported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default(),
+        source_hash: context.source_hash().map(str::to_owned),
+    });
+    if let Some(display_name) = context.display_name() {
+        metadata.set_display_name(Some(display_name.to_owned()));
+    }
+
+    context.set_phase(ImportPhase::Building);
+    let dictionary = dict_builder.build(metadata).map_err(Error::DictBuilder)?;
+    context.set_phase(ImportPhase::Done);
+
+    Ok(dictionary)
+}
+
+/// Parses a dictionary revision such as `1.09` into a [`Version`], padding missing components with zeroes.
+pub(crate) fn parse_version(s: &str) -> Result<Version, Box<dyn std::error::Error>> {
+    Ok(Version::parse(
+        &s.split('.')
+            .map(str::parse::<u32>)
+            .collect::<Result<Vec<u32>, _>>()?
+            .into_iter()
+            .chain(vec![0])
+            .map(|a| a.to_string())
+            .collect::<Vec<String>>()
+            .join("."),
+    )?)
 }
@@ -0,0 +1,230 @@
+//! Parallel construction and encoding of entries during an import.
+//!
+//! The importer's thread keeps parsing words and hands them to a pool of workers, which turn each word
+//! into its entries and encode them with the builder's [`EntryEncoder`]. The importer's thread is also the only
+//! writer: it adds the encoded entries to the [`DictionaryBuilder`] in the order the words were parsed, so the
+//! output is the same as with a single thread.
+
+use std::{
+    collections::BTreeMap,
+    num::NonZeroUsize,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::database::dictionary::{DictionaryBuilder, DictionaryEntry, EncodedEntry, EntryEncoder};
+
+/// Words queued per worker, bounding memory use when parsing is faster than the workers.
+const QUEUE_PER_WORKER: usize = 64;
+
+/// Entries of one word, keyed by the dictionary key they are added under.
+type WordEntries = Vec<(String, EncodedEntry)>;
+
+/// Sends parsed words to the workers and writes their entries back in order.
+pub(crate) struct Pipeline<W> {
+    words: SyncSender<(u64, W)>,
+    entries: Receiver<(u64, WordEntries)>,
+    /// Entries which are done but wait for those of earlier words.
+    pending: BTreeMap<u64, WordEntries>,
+    sent: u64,
+    written: u64,
+}
+
+/// Runs `f` with a pipeline whose workers turn each word into entries with `prepare`, and encode them with
+/// `encoder`, which should be the [`DictionaryBuilder::encoder`] of the builder the entries are written to.
+///
+/// `f` should call [`Pipeline::finish`] once every word was pushed, otherwise the remaining entries are dropped.
+pub(crate) fn with_pipeline<W, P, T>(
+    prepare: P,
+    encoder: Option<Arc<dyn EntryEncoder>>,
+    f: impl FnOnce(&mut Pipeline<W>) -> T,
+) -> T
+where
+    W: Send,
+    P: Fn(W) -> Vec<(String, DictionaryEntry)> + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        // The importer's thread is busy parsing and writing.
+        .saturating_sub(1)
+        .max(1);
+
+    let (word_sender, word_receiver) = mpsc::sync_channel(workers * QUEUE_PER_WORKER);
+    let (entry_sender, entry_receiver) = mpsc::channel();
+    let word_receiver = Mutex::new(word_receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let word_receiver = &word_receiver;
+            let prepare = &prepare;
+            let encoder = encoder.as_deref();
+            let entry_sender = entry_sender.clone();
+            scope.spawn(move || loop {
+                let Ok((index, word)) = word_receiver.lock().unwrap().recv() else {
+                    break;
+                };
+                let entries = prepare(word)
+                    .into_iter()
+                    .map(|(key, entry)| {
+                        let entry = match encoder {
+                            Some(encoder) => encoder.encode(entry),
+                            None => EncodedEntry::Entry(entry),
+                        };
+                        (key, entry)
+                    })
+                    .collect();
+                if entry_sender.send((index, entries)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(entry_sender);
+
+        let mut pipeline = Pipeline {
+            words: word_sender,
+            entries: entry_receiver,
+            pending: BTreeMap::new(),
+            sent: 0,
+            written: 0,
+        };
+        // Dropping the pipeline disconnects the workers, which lets the scope end.
+        f(&mut pipeline)
+    })
+}
+
+impl<W> Pipeline<W> {
+    /// Queues a word, and writes the entries of any earlier word which are done.
+    pub(crate) fn push<DB>(&mut self, word: W, dict_builder: &mut DB) -> Result<(), DB::Error>
+    where
+        DB: DictionaryBuilder,
+    {
+        // Sending only fails if a worker panicked, which the scope reports once it ends.
+        let _ = self.words.send((self.sent, word));
+        self.sent += 1;
+
+        while let Ok((index, entries)) = self.entries.try_recv() {
+            self.pending.insert(index, entries);
+        }
+        self.write_ready(dict_builder)
+    }
+
+    /// Waits for the workers to finish every queued word, and writes their entries.
+    pub(crate) fn finish<DB>(&mut self, dict_builder: &mut DB) -> Result<(), DB::Error>
+    where
+        DB: DictionaryBuilder,
+    {
+        self.write_ready(dict_builder)?;
+        while self.written < self.sent {
+            let Ok((index, entries)) = self.entries.recv() else {
+                break;
+            };
+            self.pending.insert(index, entries);
+            self.write_ready(dict_builder)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_ready<DB>(&mut self, dict_builder: &mut DB) -> Result<(), DB::Error>
+    where
+        DB: DictionaryBuilder,
+    {
+        while let Some(entries) = self.pending.remove(&self.written) {
+            for (key, entry) in entries {
+                dict_builder.add_encoded(&key, entry)?;
+            }
+            self.written += 1;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::dictionary::{cdb::CDBDictionaryBuilder, Dictionary};
+
+    use super::*;
+
+    fn entry(index: u32) -> DictionaryEntry {
+        DictionaryEntry {
+            readings: vec![],
+            gloss: index.to_string(),
+            // New tags keep showing up, so that some entries are left to the builder.
+            tags: vec![format!("tag{}", index % 10), format!("tag{}", index / 10)],
+            id: None,
+        }
+    }
+
+    fn word_entries(index: u32) -> Vec<(String, DictionaryEntry)> {
+        // Uneven work, so that words finish out of order.
+        thread::sleep(std::time::Duration::from_micros((index % 7) as u64 * 50));
+        vec![
+            ("key".to_owned(), entry(index)),
+            (index.to_string(), entry(index)),
+        ]
+    }
+
+    #[test]
+    fn ordered() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let mut dict_builder = CDBDictionaryBuilder::new(temp_dir.path().join("pipeline")).unwrap();
+
+        with_pipeline(word_entries, dict_builder.encoder(), |pipeline| {
+            for index in 0..1000 {
+                pipeline.push(index, &mut dict_builder)?;
+            }
+            pipeline.finish(&mut dict_builder)
+        })
+        .unwrap();
+        let dict = dict_builder.build(Default::default()).unwrap();
+
+        let glosses = dict
+            .get("key")
+            .into_iter()
+            .map(|entry| entry.gloss)
+            .collect::<Vec<String>>();
+        assert_eq!(
+            glosses,
+            (0..1000).map(|i| i.to_string()).collect::<Vec<_>>()
+        );
+        assert_eq!(dict.get("999"), vec![entry(999)]);
+        assert_eq!(dict.get_by_tags(&["tag99"]).len(), 20);
+    }
+
+    #[test]
+    fn deterministic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let mut dict_builder = CDBDictionaryBuilder::new(temp_dir.path().join("pipeline"))
+            .unwrap()
+            .with_compression(3);
+        with_pipeline(word_entries, dict_builder.encoder(), |pipeline| {
+            for index in 0..1000 {
+                pipeline.push(index, &mut dict_builder)?;
+            }
+            pipeline.finish(&mut dict_builder)
+        })
+        .unwrap();
+        dict_builder.build(Default::default()).unwrap();
+
+        let mut dict_builder = CDBDictionaryBuilder::new(temp_dir.path().join("single"))
+            .unwrap()
+            .with_compression(3);
+        for index in 0..1000 {
+            for (key, entry) in word_entries(index) {
+                dict_builder.add(&key, entry).unwrap();
+            }
+        }
+        dict_builder.build(Default::default()).unwrap();
+
+        assert_eq!(
+            std::fs::read(temp_dir.path().join("pipeline")).unwrap(),
+            std::fs::read(temp_dir.path().join("single")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Registry of the available importers, for picking one automatically from a dropped file or archive.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Cursor, Read},
+    path::Path,
+};
+
+use thiserror::Error;
+
+use crate::database::dictionary::{importer, DictionaryBuilder, DictionaryMetadata};
+
+use super::{
+    context::ImportContext,
+    finish_import,
+    jmdict::{self, JMDictImporter},
+    jmdict_simplified::{self, JMDictSimplifiedImporter},
+    kaikki::{self, KaikkiImporter},
+    source,
+    stardict::{self, StarDictImporter},
+    Importer,
+};
+
+/// Amount of decompressed bytes given to [`Importer::detect`].
+const HEAD_LEN: u64 = 4096;
+
+/// Extensions of compressed files and archives, which are skipped when matching [`Importer::EXTENSIONS`].
+pub const SOURCE_EXTENSIONS: &[&str] = &["gz", "tgz", "zst", "zip", "tar"];
+
+/// One of the available importers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImporterKind {
+    JMDict,
+    JMDictSimplified,
+    Kaikki,
+    StarDict,
+}
+
+impl ImporterKind {
+    /// Every available importer, in the order they are tried when detecting a format.
+    pub const ALL: &'static [Self] = &[
+        Self::JMDict,
+        Self::JMDictSimplified,
+        Self::Kaikki,
+        Self::StarDict,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::JMDict => JMDictImporter::NAME,
+            Self::JMDictSimplified => JMDictSimplifiedImporter::NAME,
+            Self::Kaikki => KaikkiImporter::NAME,
+            Self::StarDict => StarDictImporter::NAME,
+        }
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::JMDict => JMDictImporter::EXTENSIONS,
+            Self::JMDictSimplified => JMDictSimplifiedImporter::EXTENSIONS,
+            Self::Kaikki => KaikkiImporter::EXTENSIONS,
+            Self::StarDict => StarDictImporter::EXTENSIONS,
+        }
+    }
+
+    pub fn detect(self, head: &[u8]) -> bool {
+        match self {
+            Self::JMDict => JMDictImporter::detect(head),
+            Self::JMDictSimplified => JMDictSimplifiedImporter::detect(head),
+            Self::Kaikki => KaikkiImporter::detect(head),
+            Self::StarDict => StarDictImporter::detect(head),
+        }
+    }
+
+    /// Finds the importer for a source, by its content first and by the file name of `path` otherwise.
+    pub fn find(head: &[u8], path: Option<&Path>) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.detect(head))
+            .or_else(|| {
+                let extension = path.and_then(content_extension)?;
+                Self::ALL
+                    .iter()
+                    .copied()
+                    .find(|kind| kind.extensions().contains(&extension.as_str()))
+            })
+    }
+
+    pub fn import<R, DB>(
+        self,
+        reader: R,
+        mut dict_builder: DB,
+        context: &ImportContext,
+    ) -> Result<DB::Dictionary, importer::Error<AnyImporterError, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder,
+    {
+        let result = context
+            .check_cancelled()
+            .map_err(|_| importer::Error::Cancelled)
+            .and_then(|_| self.import_entries(reader, &mut dict_builder, context));
+
+        finish_import(self.name(), result, dict_builder, context)
+    }
+
+    /// Adds every entry of an uncompressed source with this importer, see [`Importer::import_entries`].
+    pub fn import_entries<R, DB>(
+        self,
+        reader: R,
+        dict_builder: &mut DB,
+        context: &ImportContext,
+    ) -> Result<DictionaryMetadata, importer::Error<AnyImporterError, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder,
+    {
+        match self {
+            Self::JMDict => JMDictImporter::import_entries(reader, dict_builder, context)
+                .map_err(|error| error.map_importer_specific(AnyImporterError::JMDict)),
+            Self::JMDictSimplified => {
+                JMDictSimplifiedImporter::import_entries(reader, dict_builder, context).map_err(
+                    |error| error.map_importer_specific(AnyImporterError::JMDictSimplified),
+                )
+            }
+            Self::Kaikki => KaikkiImporter::import_entries(reader, dict_builder, context)
+                .map_err(|error| error.map_importer_specific(AnyImporterError::Kaikki)),
+            Self::StarDict => StarDictImporter::import_entries(reader, dict_builder, context)
+                .map_err(|error| error.map_importer_specific(AnyImporterError::StarDict)),
+        }
+    }
+}
+
+/// An error from any of the importers in [`ImporterKind`].
+#[derive(Debug, Error)]
+pub enum AnyImporterError {
+    #[error("no importer recognizes this file format")]
+    UnknownFormat,
+    #[error("StarDict dictionaries must be extracted from their archive before importing")]
+    StarDictArchive,
+    #[error(transparent)]
+    JMDict(jmdict::Error),
+    #[error(transparent)]
+    JMDictSimplified(jmdict_simplified::Error),
+    #[error(transparent)]
+    Kaikki(kaikki::Error),
+    #[error(transparent)]
+    StarDict(stardict::Error),
+}
+
+/// Imports a dictionary from a file or archive of any supported format.
+pub fn import_path<DB>(
+    path: impl AsRef<Path>,
+    mut dict_builder: DB,
+    context: &ImportContext,
+) -> Result<DB::Dictionary, importer::Error<AnyImporterError, DB::Error>>
+where
+    DB: DictionaryBuilder,
+{
+    let path = path.as_ref();
+    // StarDict entries are spread over several files, which are found from the path rather than read as one stream.
+    if stardict::is_stardict_file(path) {
+        return StarDictImporter::import_path(path, dict_builder, context)
+            .map_err(|error| error.map_importer_specific(AnyImporterError::StarDict));
+    }
+    let file = File::open(path).map_err(importer::Error::DictFileIo)?;
+    let mut source = context.track_source(file);
+
+    let mut kind = None;
+    let result = source::decompress_member(&mut source, |reader, member| {
+        context
+            .check_cancelled()
+            .map_err(|_| importer::Error::Cancelled)?;
+        let mut head = Vec::new();
+        (&mut *reader)
+            .take(HEAD_LEN)
+            .read_to_end(&mut head)
+            .map_err(importer::Error::DictFileIo)?;
+
+        let found = ImporterKind::find(&head, Some(member.unwrap_or(path))).ok_or(
+            importer::Error::ImporterSpecific(AnyImporterError::UnknownFormat),
+        )?;
+        kind = Some(found);
+        if found == ImporterKind::StarDict {
+            return Err(importer::Error::ImporterSpecific(
+                AnyImporterError::StarDictArchive,
+            ));
+        }
+        found.import_entries(
+            BufReader::new(Cursor::new(head).chain(reader)),
+            &mut dict_builder,
+            context,
+        )
+    })
+    .map_err(importer::Error::DictFileIo)
+    .and_then(|result| result)
+    .and_then(|metadata| {
+        source
+            .finish_source()
+            .map(|_| metadata)
+            .map_err(importer::Error::DictFileIo)
+    });
+    let importer = kind.map_or("", ImporterKind::name);
+
+    finish_import(importer, result, dict_builder, context)
+}
+
+/// Gets the extension of a file name, skipping compression and archive extensions (`jmdict-eng.json.zip` gives `json`).
+fn content_extension(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+
+    let extensions = name.split('.').skip(1).collect::<Vec<&str>>();
+
+    extensions
+        .into_iter()
+        .rev()
+        .find(|extension| !SOURCE_EXTENSIONS.contains(extension))
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use crate::database::dictionary::{hashmap::HashMapDictionaryBuilder, Dictionary};
+
+    use super::*;
+
+    #[test]
+    fn detection() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE JMdict [
+<!ENTITY n "noun (common) (futsuumeishi)">
+]>
+<JMdict>
+<entry><ent_seq>1</ent_seq><r_ele><reb>テスト</reb></r_ele><sense><pos>&n;</pos><gloss>test</gloss></sense></entry>
+</JMdict>
+"#;
+        let json = r#"{"version":"3.5.0","languages":["eng"],"commonOnly":false,"dictDate":"2023-12-04","dictRevisions":["1.09"],"tags":{},"words":[
+{"id":"1","kanji":[],"kana":[{"common":true,"text":"テスト","tags":[],"appliesToKanji":["*"]}],"sense":[{"partOfSpeech":["n"],"appliesToKanji":["*"],"appliesToKana":["*"],"related":[],"antonym":[],"field":[],"dialect":[],"misc":[],"info":[],"languageSource":[],"gloss":[{"lang":"eng","gender":null,"type":null,"text":"test"}]}]}
+]}"#;
+
+        assert_eq!(
+            ImporterKind::find(xml.as_bytes(), None),
+            Some(ImporterKind::JMDict)
+        );
+        assert_eq!(
+            ImporterKind::find(json.as_bytes(), None),
+            Some(ImporterKind::JMDictSimplified)
+        );
+        assert_eq!(
+            ImporterKind::find(b"", Some(Path::new("jmdict-eng-3.5.0.json.zip"))),
+            Some(ImporterKind::JMDictSimplified)
+        );
+        assert_eq!(
+            ImporterKind::find(b"", Some(Path::new("JMdict_e.gz"))),
+            None
+        );
+
+        for (name, text) in [("JMdict_e", xml), ("jmdict-eng.json", json)] {
+            let path = temp_dir.path().join(name);
+            fs::write(&path, text).expect("could not write dictionary file to temp dir");
+
+            let dict = import_path(&path, HashMapDictionaryBuilder::new(), &Default::default())
+                .expect("error while importing dictionary file");
+            assert_eq!(dict.get("テスト").first().unwrap().gloss, "test");
+        }
+
+        let path = temp_dir.path().join("unknown.txt");
+        fs::write(&path, "hello").expect("could not write file to temp dir");
+        assert!(matches!(
+            import_path(&path, HashMapDictionaryBuilder::new(), &Default::default()),
+            Err(importer::Error::ImporterSpecific(
+                AnyImporterError::UnknownFormat
+            ))
+        ));
+    }
+
+    #[test]
+    fn stardict() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let ifo_path = stardict::tests::write_dictionary(temp_dir.path(), true);
+
+        let context = ImportContext::new();
+        let dict = import_path(&ifo_path, HashMapDictionaryBuilder::new(), &context)
+            .expect("error while importing dictionary files");
+        assert_eq!(dict.get("いぬ").first().unwrap().gloss, "イヌ科の哺乳類。");
+        // The files are only read once, by the StarDict importer.
+        let progress = context.progress();
+        assert!(progress
+            .total_bytes
+            .map_or(progress.bytes_read == 0, |total| progress.bytes_read
+                <= total));
+
+        // Whichever file of the set comes first in the archive.
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        stardict::tests::write_dictionary(temp_dir.path(), false);
+        for first in ["test.ifo", "test.dict", "test.idx"] {
+            let mut names = ["test.ifo", "test.idx", "test.syn", "test.dict"];
+            names.sort_by_key(|name| *name != first);
+
+            let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            for name in names {
+                writer.start_file(name, Default::default()).unwrap();
+                writer
+                    .write_all(&fs::read(temp_dir.path().join(name)).unwrap())
+                    .unwrap();
+            }
+            let archive_path = temp_dir.path().join("test.zip");
+            fs::write(&archive_path, writer.finish().unwrap().into_inner()).unwrap();
+
+            assert!(matches!(
+                import_path(
+                    &archive_path,
+                    HashMapDictionaryBuilder::new(),
+                    &Default::default()
+                ),
+                Err(importer::Error::ImporterSpecific(
+                    AnyImporterError::StarDictArchive
+                ))
+            ));
+        }
+    }
+}
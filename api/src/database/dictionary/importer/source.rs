@@ -0,0 +1,199 @@
+//! Transparent decompression of import sources.
+//!
+//! Dictionary releases are usually distributed compressed (`.gz`, `.zst`) or packed in an archive (`.zip`, `.tgz`).
+//! Sources are detected by their magic bytes and decompressed as a stream, without extracting anything to disk.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Chain, Cursor, Read},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+
+/// Maximum amount of nested containers, e.g. a `.tar.gz` inside a `.zip`.
+const MAX_DEPTH: u8 = 4;
+
+/// Amount of bytes needed to identify every supported format (the `ustar` magic ends at offset 262).
+const HEAD_LEN: usize = 512;
+
+/// A reader which yields the already read head of a source before the rest of it.
+type HeadReader<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Compression or container format of an import source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceFormat {
+    Plain,
+    Gzip,
+    Zstd,
+    Zip,
+    Tar,
+}
+
+impl SourceFormat {
+    /// Identifies a format from the first bytes of a source.
+    pub fn sniff(head: &[u8]) -> Self {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            Self::Gzip
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Self::Zstd
+        } else if head.starts_with(b"PK\x03\x04") {
+            Self::Zip
+        } else if head.get(257..262) == Some(&b"ustar"[..]) {
+            Self::Tar
+        } else {
+            Self::Plain
+        }
+    }
+}
+
+/// Opens the file at `path` and calls `f` with a reader over its decompressed contents.
+pub fn open<T>(path: impl AsRef<Path>, f: impl FnOnce(&mut dyn BufRead) -> T) -> io::Result<T> {
+    decompress(File::open(path)?, f)
+}
+
+/// Calls `f` with a reader over the decompressed contents of `reader`.
+///
+/// Compressed sources are decompressed, and archives are read up to their first regular file.
+/// Sources which are not compressed are passed through as-is.
+pub fn decompress<R: Read, T>(reader: R, f: impl FnOnce(&mut dyn BufRead) -> T) -> io::Result<T> {
+    decompress_member(reader, |reader, _| f(reader))
+}
+
+/// Like [`decompress`], also giving `f` the name of the archive member it reads, if the source is an archive.
+pub fn decompress_member<R: Read, T>(
+    mut reader: R,
+    f: impl FnOnce(&mut dyn BufRead, Option<&Path>) -> T,
+) -> io::Result<T> {
+    decompress_nested(&mut reader, 0, None, f)
+}
+
+fn decompress_nested<T>(
+    reader: &mut dyn Read,
+    depth: u8,
+    member: Option<PathBuf>,
+    f: impl FnOnce(&mut dyn BufRead, Option<&Path>) -> T,
+) -> io::Result<T> {
+    let (format, mut reader) = read_head(reader)?;
+
+    if depth >= MAX_DEPTH && format != SourceFormat::Plain {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "too many nested compressed containers",
+        ));
+    }
+
+    match format {
+        SourceFormat::Plain => Ok(f(&mut BufReader::new(reader), member.as_deref())),
+        SourceFormat::Gzip => decompress_nested(&mut GzDecoder::new(reader), depth + 1, member, f),
+        SourceFormat::Zstd => {
+            decompress_nested(&mut zstd::Decoder::new(reader)?, depth + 1, member, f)
+        }
+        SourceFormat::Zip => {
+            while let Some(mut file) =
+                zip::read::read_zipfile_from_stream(&mut reader).map_err(io::Error::from)?
+            {
+                if file.is_file() && !is_hidden(file.name()) {
+                    let name = PathBuf::from(file.name());
+                    return decompress_nested(&mut file, depth + 1, Some(name), f);
+                }
+            }
+            Err(no_file_error())
+        }
+        SourceFormat::Tar => {
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.into_owned();
+                if entry.header().entry_type().is_file() && !is_hidden(&name.to_string_lossy()) {
+                    return decompress_nested(&mut entry, depth + 1, Some(name), f);
+                }
+            }
+            Err(no_file_error())
+        }
+    }
+}
+
+/// Reads the first bytes of a source to identify it, returning a reader which still yields them.
+fn read_head<R: Read>(mut reader: R) -> io::Result<(SourceFormat, HeadReader<R>)> {
+    let mut head = Vec::with_capacity(HEAD_LEN);
+    (&mut reader).take(HEAD_LEN as u64).read_to_end(&mut head)?;
+
+    Ok((SourceFormat::sniff(&head), Cursor::new(head).chain(reader)))
+}
+
+/// Whether an archive member is metadata rather than content, like `__MACOSX/` or `.DS_Store`.
+fn is_hidden(name: &str) -> bool {
+    name.split('/')
+        .any(|component| component.starts_with('.') || component == "__MACOSX")
+}
+
+fn no_file_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "archive contains no files")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::GzEncoder, Compression};
+
+    use super::*;
+
+    const TEXT: &[u8] = b"{\"words\": []}";
+
+    fn read_all(data: &[u8]) -> Vec<u8> {
+        decompress(data, |reader| {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf).unwrap();
+            buf
+        })
+        .expect("could not decompress source")
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn tar(data: &[u8]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "jmdict-eng.json", data)
+            .unwrap();
+        builder.into_inner().unwrap()
+    }
+
+    fn zip(data: &[u8]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer
+            .add_directory("__MACOSX/", Default::default())
+            .unwrap();
+        writer
+            .start_file("jmdict-eng.json", Default::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(read_all(TEXT), TEXT);
+        assert_eq!(read_all(&gzip(TEXT)), TEXT);
+        assert_eq!(read_all(&zstd::encode_all(TEXT, 0).unwrap()), TEXT);
+        assert_eq!(read_all(&zip(TEXT)), TEXT);
+        assert_eq!(read_all(&gzip(&tar(TEXT))), TEXT);
+        assert_eq!(read_all(&zip(&gzip(TEXT))), TEXT);
+
+        let member = decompress_member(&gzip(&tar(TEXT))[..], |_, member| {
+            member.map(Path::to_owned)
+        })
+        .unwrap();
+        assert_eq!(member, Some(PathBuf::from("jmdict-eng.json")));
+    }
+}
@@ -0,0 +1,546 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+use thiserror::Error;
+use url::Url;
+
+use crate::database::{
+    dictionary::{importer, DictionaryBuilder, DictionaryEntry, DictionaryMetadata},
+    storage,
+};
+
+use super::{context::ImportContext, dictzip::DictZip, finish_import, Importer};
+
+/// First line of every `.ifo` file.
+const IFO_MAGIC: &str = "StarDict's dict ifo file";
+
+/// Endings of the names of the files of a StarDict dictionary.
+const FILE_SUFFIXES: &[&str] = &[".ifo", ".idx.gz", ".idx", ".dict.dz", ".dict", ".syn"];
+
+/// Whether `path` is named like a file of a StarDict dictionary, which must be imported with [`Importer::import_path`].
+pub fn is_stardict_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| FILE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)))
+}
+
+/// Importer for StarDict dictionaries, made of an `.ifo` file and its `.idx`, `.dict` (or `.dict.dz`) and optional `.syn` siblings.
+///
+/// Since the entries are spread over several files with random access into `.dict.dz`,
+/// only [`Importer::import_path`] is supported, with the path of any file of the set.
+/// The files must be extracted from their archive first.
+#[derive(Debug)]
+pub struct StarDictImporter {}
+
+impl Importer for StarDictImporter {
+    type Error = Error;
+
+    const NAME: &'static str = "StarDict";
+    const EXTENSIONS: &'static [&'static str] = &["ifo", "idx", "dict", "dz", "syn"];
+
+    fn detect(head: &[u8]) -> bool {
+        head.starts_with(IFO_MAGIC.as_bytes())
+    }
+
+    fn import_entries<R, DB>(
+        _reader: R,
+        _dict_builder: &mut DB,
+        _context: &ImportContext,
+    ) -> Result<DictionaryMetadata, importer::Error<Self::Error, DB::Error>>
+    where
+        R: BufRead,
+        DB: DictionaryBuilder,
+    {
+        Err(importer::Error::ImporterSpecific(Error::PathRequired))
+    }
+
+    fn import_path<DB>(
+        path: impl AsRef<Path>,
+        mut dict_builder: DB,
+        context: &ImportContext,
+    ) -> Result<DB::Dictionary, importer::Error<Self::Error, DB::Error>>
+    where
+        DB: DictionaryBuilder,
+    {
+        let result = context
+            .check_cancelled()
+            .map_err(|_| importer::Error::Cancelled)
+            .and_then(|_| StarDictFiles::find(path.as_ref()))
+            .and_then(|files| import_files(&files, &mut dict_builder, context));
+
+        finish_import(Self::NAME, result, dict_builder, context)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("StarDict dictionaries can only be imported from their files on disk")]
+    PathRequired,
+    #[error("StarDict dictionary is missing its {} file", .0)]
+    MissingFile(&'static str),
+    #[error("invalid StarDict .ifo file")]
+    InvalidIfo,
+    #[error("StarDict .idx file is truncated")]
+    TruncatedIdx,
+    #[error("StarDict .syn file is truncated")]
+    TruncatedSyn,
+}
+
+type ImportError<DBE> = importer::Error<Error, DBE>;
+
+/// Paths of the files of a StarDict dictionary.
+struct StarDictFiles {
+    ifo: PathBuf,
+    idx: PathBuf,
+    dict: PathBuf,
+    syn: Option<PathBuf>,
+}
+
+impl StarDictFiles {
+    /// Finds the files of the dictionary that the file at `path` belongs to.
+    fn find<DBE: std::error::Error>(path: &Path) -> Result<Self, ImportError<DBE>> {
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or(
+            importer::Error::ImporterSpecific(Error::MissingFile(".ifo")),
+        )?;
+        let stem = FILE_SUFFIXES
+            .iter()
+            .find_map(|extension| name.strip_suffix(extension))
+            .unwrap_or(name);
+        let sibling = |extension: &str| {
+            let sibling = path.with_file_name(format!("{stem}{extension}"));
+            sibling.is_file().then_some(sibling)
+        };
+        let missing = |extension| importer::Error::ImporterSpecific(Error::MissingFile(extension));
+
+        Ok(Self {
+            ifo: sibling(".ifo").ok_or(missing(".ifo"))?,
+            idx: sibling(".idx")
+                .or_else(|| sibling(".idx.gz"))
+                .ok_or(missing(".idx"))?,
+            dict: sibling(".dict.dz")
+                .or_else(|| sibling(".dict"))
+                .ok_or(missing(".dict"))?,
+            syn: sibling(".syn"),
+        })
+    }
+
+    /// Paths of every file of the dictionary, in a fixed order.
+    fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.ifo.clone(), self.idx.clone(), self.dict.clone()];
+        paths.extend(self.syn.clone());
+        paths
+    }
+}
+
+/// Options of the `.ifo` file.
+struct Ifo {
+    options: HashMap<String, String>,
+}
+
+impl Ifo {
+    fn parse(text: &str) -> Option<Self> {
+        let mut lines = text.lines();
+        if lines.next()?.trim() != IFO_MAGIC {
+            return None;
+        }
+
+        let options = lines
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+            .collect();
+        Some(Self { options })
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.options.get(key).map(String::as_str)
+    }
+
+    fn metadata(&self) -> DictionaryMetadata {
+        DictionaryMetadata::builder(self.get("bookname").unwrap_or_default())
+            .author(self.get("author").unwrap_or_default())
+            .homepage_url(self.get("website").and_then(|url| Url::parse(url).ok()))
+            .notes(
+                self.get("description")
+                    .map(strip_markup)
+                    .unwrap_or_default(),
+            )
+            .source_date(self.get("date").map(str::to_owned))
+            // `version` is the version of the StarDict format, not of the dictionary.
+            .format_version(self.get("version").map(str::to_owned))
+            .build()
+    }
+}
+
+/// The `.dict` file, which is either plain or dictzip compressed.
+enum DictData {
+    Plain(BufReader<File>),
+    DictZip(DictZip<BufReader<File>>),
+    /// A plain gzip file, without the dictzip chunk table, can't be read randomly and is decompressed in memory instead.
+    Decompressed(Vec<u8>),
+}
+
+impl DictData {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        if path.extension().is_some_and(|extension| extension == "dz") {
+            return match DictZip::open(path) {
+                Ok(dictzip) => Ok(Self::DictZip(dictzip)),
+                Err(error) if error.kind() == std::io::ErrorKind::InvalidData => {
+                    let mut data = Vec::new();
+                    GzDecoder::new(File::open(path)?).read_to_end(&mut data)?;
+                    Ok(Self::Decompressed(data))
+                }
+                Err(error) => Err(error),
+            };
+        }
+
+        Ok(Self::Plain(BufReader::new(File::open(path)?)))
+    }
+
+    fn read_at(&mut self, offset: u64, len: usize) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Plain(file) => {
+                let mut data = vec![0; len];
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut data)?;
+                Ok(data)
+            }
+            Self::DictZip(dictzip) => dictzip.read_at(offset, len),
+            Self::Decompressed(data) => usize::try_from(offset)
+                .ok()
+                .and_then(|offset| data.get(offset..offset.checked_add(len)?))
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| std::io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+}
+
+/// An entry of the `.idx` file.
+struct IdxEntry {
+    word: String,
+    offset: u64,
+    size: usize,
+}
+
+fn import_files<DB>(
+    files: &StarDictFiles,
+    dict_builder: &mut DB,
+    context: &ImportContext,
+) -> Result<DictionaryMetadata, ImportError<DB::Error>>
+where
+    DB: DictionaryBuilder,
+{
+    let ifo_text = fs::read_to_string(&files.ifo).map_err(importer::Error::DictFileIo)?;
+    let ifo = Ifo::parse(&ifo_text).ok_or(importer::Error::ImporterSpecific(Error::InvalidIfo))?;
+    // A release may change any file of the set, so all of them are hashed.
+    context.set_source_hash(
+        storage::checksum_files(&files.paths()).map_err(importer::Error::DictFileIo)?,
+    );
+    let offset_bits = ifo.get("idxoffsetbits").unwrap_or("32");
+    let same_type_sequence = ifo.get("sametypesequence");
+
+    let idx = read_file(&files.idx).map_err(importer::Error::DictFileIo)?;
+    let idx = parse_idx(&idx, offset_bits == "64")
+        .ok_or(importer::Error::ImporterSpecific(Error::TruncatedIdx))?;
+
+    let mut synonyms: HashMap<usize, Vec<String>> = HashMap::new();
+    if let Some(syn) = &files.syn {
+        let syn = read_file(syn).map_err(importer::Error::DictFileIo)?;
+        for (word, index) in
+            parse_syn(&syn).ok_or(importer::Error::ImporterSpecific(Error::TruncatedSyn))?
+        {
+            synonyms.entry(index).or_default().push(word);
+        }
+    }
+
+    let mut dict = DictData::open(&files.dict).map_err(importer::Error::DictFileIo)?;
+    for (index, idx_entry) in idx.iter().enumerate() {
+        let data = dict
+            .read_at(idx_entry.offset, idx_entry.size)
+            .map_err(importer::Error::DictFileIo)?;
+        let mut entry = parse_entry(&data, same_type_sequence);
+        // StarDict has no identifiers, but headwords stay the same across revisions, unlike offsets.
+        entry.id = Some(idx_entry.word.clone());
+
+        dict_builder
+            .add(&idx_entry.word, entry.clone())
+            .map_err(importer::Error::DictBuilder)?;
+        for synonym in synonyms.get(&index).into_iter().flatten() {
+            if *synonym != idx_entry.word {
+                dict_builder
+                    .add(synonym, entry.clone())
+                    .map_err(importer::Error::DictBuilder)?;
+            }
+        }
+
+        context
+            .word_processed()
+            .map_err(|_| importer::Error::Cancelled)?;
+    }
+
+    Ok(ifo.metadata())
+}
+
+/// Reads a whole file, decompressing it if it is gzipped (like `.idx.gz`).
+fn read_file(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        GzDecoder::new(File::open(path)?).read_to_end(&mut data)?;
+    } else {
+        File::open(path)?.read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
+
+/// Splits a zero-terminated UTF-8 string from the start of `data`.
+fn split_word(data: &[u8]) -> Option<(String, &[u8])> {
+    let end = data.iter().position(|byte| *byte == 0)?;
+    Some((
+        String::from_utf8_lossy(&data[..end]).into_owned(),
+        &data[end + 1..],
+    ))
+}
+
+fn split_u32(data: &[u8]) -> Option<(u32, &[u8])> {
+    let bytes = data.get(..4)?;
+    Some((u32::from_be_bytes(bytes.try_into().ok()?), &data[4..]))
+}
+
+fn split_u64(data: &[u8]) -> Option<(u64, &[u8])> {
+    let bytes = data.get(..8)?;
+    Some((u64::from_be_bytes(bytes.try_into().ok()?), &data[8..]))
+}
+
+fn parse_idx(mut data: &[u8], offsets_64: bool) -> Option<Vec<IdxEntry>> {
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let (word, rest) = split_word(data)?;
+        let (offset, rest) = if offsets_64 {
+            split_u64(rest)?
+        } else {
+            split_u32(rest).map(|(offset, rest)| (offset as u64, rest))?
+        };
+        let (size, rest) = split_u32(rest)?;
+
+        entries.push(IdxEntry {
+            word,
+            offset,
+            size: size as usize,
+        });
+        data = rest;
+    }
+    Some(entries)
+}
+
+/// Parses the `.syn` file into synonyms and the index of the `.idx` entry they refer to.
+fn parse_syn(mut data: &[u8]) -> Option<Vec<(String, usize)>> {
+    let mut synonyms = Vec::new();
+    while !data.is_empty() {
+        let (word, rest) = split_word(data)?;
+        let (index, rest) = split_u32(rest)?;
+        synonyms.push((word, index as usize));
+        data = rest;
+    }
+    Some(synonyms)
+}
+
+/// Converts the data of a `.dict` entry into a dictionary entry.
+///
+/// With `sametypesequence`, the field types are omitted from the data and the last field has no terminator or size.
+/// Text fields become the gloss, and phonetic (`t`) or kana (`y`) fields become readings.
+/// Binary fields such as sounds and pictures are skipped.
+fn parse_entry(mut data: &[u8], same_type_sequence: Option<&str>) -> DictionaryEntry {
+    let mut entry = DictionaryEntry {
+        readings: Vec::new(),
+        gloss: String::new(),
+        tags: Vec::new(),
+        id: None,
+    };
+    let mut types = same_type_sequence.map(|sequence| sequence.chars().peekable());
+
+    while !data.is_empty() {
+        let (field_type, is_last) = match types.as_mut() {
+            Some(types) => match types.next() {
+                Some(field_type) => (field_type, types.peek().is_none()),
+                None => break,
+            },
+            None => {
+                let field_type = data[0] as char;
+                data = &data[1..];
+                (field_type, false)
+            }
+        };
+
+        let field;
+        if field_type.is_ascii_lowercase() {
+            let end = if is_last {
+                data.len()
+            } else {
+                data.iter()
+                    .position(|byte| *byte == 0)
+                    .unwrap_or(data.len())
+            };
+            field = &data[..end];
+            data = data.get(end + 1..).unwrap_or_default();
+        } else {
+            let (size, rest) = if is_last {
+                (data.len() as u32, data)
+            } else {
+                match split_u32(data) {
+                    Some(split) => split,
+                    None => break,
+                }
+            };
+            let size = (size as usize).min(rest.len());
+            field = &rest[..size];
+            data = &rest[size..];
+        }
+
+        let text = String::from_utf8_lossy(field);
+        match field_type {
+            't' | 'y' => entry.readings.push(text.trim().to_owned()),
+            'm' | 'l' | 'k' | 'w' => push_line(&mut entry.gloss, text.trim()),
+            'g' | 'x' | 'h' => push_line(&mut entry.gloss, strip_markup(&text).trim()),
+            _ => (),
+        }
+    }
+
+    entry
+}
+
+fn push_line(gloss: &mut String, line: &str) {
+    if line.is_empty() {
+        return;
+    }
+    if !gloss.is_empty() {
+        gloss.push('\n');
+    }
+    gloss.push_str(line);
+}
+
+/// Converts HTML, Pango or XDXF markup into plain text, keeping line breaks.
+fn strip_markup(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut rest = markup;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = rest[start + 1..start + end]
+            .trim_start_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if matches!(tag.as_str(), "br" | "br/" | "p" | "div" | "li") && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::database::dictionary::{
+        cdb::CDBDictionaryBuilder, importer::dictzip::tests::dictzip, Dictionary,
+    };
+
+    use super::*;
+
+    /// Writes a small dictionary into `dir`, returning the path of its `.ifo` file.
+    pub(crate) fn write_dictionary(dir: &Path, compressed: bool) -> PathBuf {
+        let definitions = [
+            (
+                "ねこ",
+                "猫\0小型の食肉目の哺乳類。<br>ペットとして飼われる。",
+            ),
+            ("いぬ", "犬\0イヌ科の哺乳類。"),
+        ];
+
+        let mut dict = Vec::new();
+        let mut idx = Vec::new();
+        for (word, definition) in definitions {
+            idx.extend_from_slice(word.as_bytes());
+            idx.push(0);
+            idx.extend_from_slice(&(dict.len() as u32).to_be_bytes());
+            idx.extend_from_slice(&(definition.len() as u32).to_be_bytes());
+            dict.extend_from_slice(definition.as_bytes());
+        }
+        let mut syn = "ネコ".as_bytes().to_vec();
+        syn.push(0);
+        syn.extend_from_slice(&0u32.to_be_bytes());
+
+        let ifo = format!(
+            "{IFO_MAGIC}\nversion=2.4.2\nwordcount=2\nsynwordcount=1\nidxfilesize={}\nbookname=テスト辞典\nsametypesequence=yh\ndescription=A <b>test</b> dictionary.\n",
+            idx.len()
+        );
+        fs::write(dir.join("test.ifo"), ifo).unwrap();
+        fs::write(dir.join("test.idx"), idx).unwrap();
+        fs::write(dir.join("test.syn"), syn).unwrap();
+        if compressed {
+            fs::write(dir.join("test.dict.dz"), dictzip(&dict, 32)).unwrap();
+        } else {
+            fs::write(dir.join("test.dict"), dict).unwrap();
+        }
+
+        dir.join("test.ifo")
+    }
+
+    #[test]
+    fn basic() {
+        for compressed in [false, true] {
+            let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+            let ifo_path = write_dictionary(temp_dir.path(), compressed);
+
+            let dict_builder =
+                CDBDictionaryBuilder::new(temp_dir.path().join("stardict-test-basic")).unwrap();
+            let context = ImportContext::new().with_display_name("猫と犬");
+            let dict = StarDictImporter::import_path(&ifo_path, dict_builder, &context)
+                .expect("error while importing dictionary files");
+
+            let expected = r#"[DictionaryEntry { readings: ["猫"], gloss: "小型の食肉目の哺乳類。\nペットとして飼われる。", tags: [], id: Some("ねこ") }]"#;
+            assert_eq!(format!("{:?}", dict.get("ねこ")), expected);
+            assert_eq!(format!("{:?}", dict.get("ネコ")), expected);
+            assert_eq!(dict.get("いぬ").first().unwrap().gloss, "イヌ科の哺乳類。");
+            let metadata = dict.get_metadata();
+            assert_eq!(metadata.name(), "テスト辞典");
+            assert_eq!(metadata.display_name(), "猫と犬");
+            assert_eq!(metadata.notes(), "A test dictionary.");
+            assert_eq!(metadata.format_version(), Some("2.4.2"));
+
+            let source_hash = metadata.import_info().unwrap().source_hash.clone();
+            let files = StarDictFiles::find::<std::io::Error>(&ifo_path).unwrap();
+            assert_eq!(
+                source_hash,
+                Some(storage::checksum_files(&files.paths()).unwrap())
+            );
+
+            // A release which only changes the synonyms is still a different source.
+            fs::write(temp_dir.path().join("test.syn"), b"").unwrap();
+            let dict_builder =
+                CDBDictionaryBuilder::new(temp_dir.path().join("stardict-test-syn")).unwrap();
+            let dict =
+                StarDictImporter::import_path(&ifo_path, dict_builder, &ImportContext::new())
+                    .unwrap();
+            assert_ne!(
+                dict.get_metadata().import_info().unwrap().source_hash,
+                source_hash
+            );
+        }
+    }
+}
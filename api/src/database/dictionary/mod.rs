@@ -1,38 +1,156 @@
+use std::{collections::BTreeMap, sync::Arc};
+
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+pub mod any;
+pub mod archive;
 pub mod cdb;
+pub mod changes;
 pub mod hashmap;
 pub mod importer;
+pub mod sqlite;
+pub mod user;
+
+/// Identifies an entry of a [`MutableDictionary`], stable across edits.
+pub type EntryId = u64;
 
 pub trait DictionaryBuilder {
     type Dictionary: Dictionary;
     type Error: std::error::Error;
 
     fn add(&mut self, key: &str, entry: DictionaryEntry) -> Result<(), Self::Error>;
+
+    /// Gets an encoder which does the work of [`DictionaryBuilder::add`] ahead of time, on other threads,
+    /// or `None` if the builder has nothing worth doing there.
+    fn encoder(&self) -> Option<Arc<dyn EntryEncoder>> {
+        None
+    }
+
+    /// Adds an entry prepared by the builder's [`DictionaryBuilder::encoder`].
+    fn add_encoded(&mut self, key: &str, entry: EncodedEntry) -> Result<(), Self::Error> {
+        match entry {
+            EncodedEntry::Entry(entry) => self.add(key, entry),
+            EncodedEntry::Record(_) => {
+                unreachable!("records are only made by the encoders of builders which add them")
+            }
+        }
+    }
+
     fn build(self, metadata: DictionaryMetadata) -> Result<Self::Dictionary, Self::Error>;
+
+    /// Discards everything added so far, removing any partially written output.
+    fn discard(self) -> Result<(), Self::Error>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+}
+
+/// Encodes entries for a [`DictionaryBuilder`] on other threads than the one adding them.
+pub trait EntryEncoder: Send + Sync {
+    fn encode(&self, entry: DictionaryEntry) -> EncodedEntry;
+}
+
+/// An entry prepared by an [`EntryEncoder`], for [`DictionaryBuilder::add_encoded`].
+pub enum EncodedEntry {
+    /// Left for the builder to encode, when the encoder couldn't do so on its own.
+    Entry(DictionaryEntry),
+    /// What a [`cdb::CDBDictionaryBuilder`] stores for the entry.
+    Record(cdb::EncodedRecord),
 }
 
 pub trait Dictionary {
     fn get(&self, key: &str) -> Vec<DictionaryEntry>;
     fn get_metadata(&self) -> &DictionaryMetadata;
+    /// Iterates over every entry along with its key, in no particular order.
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_>;
+
+    /// Gets every entry which has all of `tags`, along with its key.
+    ///
+    /// This goes through every entry unless the dictionary keeps an index of its tags.
+    fn get_by_tags(&self, tags: &[&str]) -> Vec<(String, DictionaryEntry)> {
+        self.iter()
+            .filter(|(_, entry)| entry.has_tags(tags))
+            .collect()
+    }
+}
+
+/// A dictionary whose entries can still be changed after it is built.
+pub trait MutableDictionary: Dictionary {
+    type Error: std::error::Error;
+
+    fn insert(&mut self, key: &str, entry: DictionaryEntry) -> Result<EntryId, Self::Error>;
+    /// Replaces an entry, moving it to `key` if that differs from its current key.
+    fn update(&mut self, id: EntryId, key: &str, entry: DictionaryEntry)
+        -> Result<(), Self::Error>;
+    fn remove(&mut self, id: EntryId) -> Result<DictionaryEntry, Self::Error>;
 }
 
-#[derive(Debug, bitcode::Encode, bitcode::Decode, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[derive(
+    Debug,
+    bitcode::Encode,
+    bitcode::Decode,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    Hash,
+    Clone,
+)]
+#[archive(check_bytes)]
+#[archive_attr(derive(Debug))]
 pub struct DictionaryEntry {
     pub readings: Vec<String>,
     pub gloss: String,
     pub tags: Vec<String>,
+    /// Identifier of the word in the source, shared by every entry of that word and stable across revisions.
+    #[serde(default)]
+    pub id: Option<String>,
+}
+
+/// Version of the encoding of stored entries, raised whenever it changes.
+///
+/// Version 1 had no ids, see [`LegacyDictionaryEntry`], and version 2 repeated the name of every tag in each entry
+/// rather than referring to the tag table of its [`cdb::CDBDictionary`]. Older versions can still be decoded.
+pub const ENTRY_ENCODING_VERSION: u32 = 3;
+
+/// Encoding of [`DictionaryEntry`] before it had an id, still found in older dictionaries.
+#[derive(bitcode::Decode)]
+#[cfg_attr(test, derive(bitcode::Encode))]
+struct LegacyDictionaryEntry {
+    readings: Vec<String>,
+    gloss: String,
+    tags: Vec<String>,
 }
 
 impl DictionaryEntry {
+    /// Whether the entry has every tag of `tags`.
+    pub fn has_tags(&self, tags: &[&str]) -> bool {
+        tags.iter()
+            .all(|tag| self.tags.iter().any(|own| own == tag))
+    }
+
     pub fn serialize_fast(&self) -> Vec<u8> {
         bitcode::encode(self).unwrap()
     }
 
     pub fn deserialize_fast(data: &[u8]) -> Self {
-        bitcode::decode(data).unwrap()
+        bitcode::decode(data)
+            .or_else(|_| {
+                bitcode::decode(data).map(|legacy: LegacyDictionaryEntry| Self {
+                    readings: legacy.readings,
+                    gloss: legacy.gloss,
+                    tags: legacy.tags,
+                    id: None,
+                })
+            })
+            .unwrap()
     }
 }
 
@@ -44,19 +162,205 @@ pub struct DictionaryMetadata {
     homepage_url: Option<Url>,
     update_url: Option<Url>,
     notes: String,
+    /// Descriptions of the tags used by this dictionary's entries, keyed by tag name.
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    /// Name chosen by the user, shown instead of `name`.
+    #[serde(default)]
+    display_name: Option<String>,
+    /// Release date of the source data, as given by the source.
+    #[serde(default)]
+    source_date: Option<String>,
+    /// Languages of the glosses, as given by the source.
+    #[serde(default)]
+    languages: Vec<String>,
+    /// Version of the source file format, when the source declares one.
+    #[serde(default)]
+    format_version: Option<String>,
+    #[serde(default)]
+    import_info: Option<ImportInfo>,
+    /// Hex encoded SHA-256 of the dictionary's files, for backends which store the metadata outside of them.
+    #[serde(default)]
+    checksum: Option<String>,
+}
+
+/// How and when a dictionary was imported.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ImportInfo {
+    /// Name of the importer, like [`Importer::NAME`](importer::Importer::NAME).
+    pub importer: String,
+    /// Version of this crate at import time.
+    pub importer_version: String,
+    /// Seconds since the Unix epoch.
+    pub imported_at: u64,
+    /// Hex encoded SHA-256 of the source file (every file of the set for StarDict), when imported from a file.
+    pub source_hash: Option<String>,
+}
+
+impl DictionaryMetadata {
+    pub fn builder(name: impl Into<String>) -> DictionaryMetadataBuilder {
+        DictionaryMetadataBuilder::new(name)
+    }
+
+    /// Name of the dictionary as given by its source.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Name to show for the dictionary, which is the user's choice if any.
+    pub fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn set_display_name(&mut self, display_name: Option<String>) {
+        self.display_name = display_name;
+    }
+
+    pub fn author(&self) -> &str {
+        &self.author
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn homepage_url(&self) -> Option<&Url> {
+        self.homepage_url.as_ref()
+    }
+
+    pub fn update_url(&self) -> Option<&Url> {
+        self.update_url.as_ref()
+    }
+
+    pub fn notes(&self) -> &str {
+        &self.notes
+    }
+
+    pub fn tags(&self) -> &BTreeMap<String, String> {
+        &self.tags
+    }
+
+    pub fn source_date(&self) -> Option<&str> {
+        self.source_date.as_deref()
+    }
+
+    pub fn languages(&self) -> &[String] {
+        &self.languages
+    }
+
+    pub fn format_version(&self) -> Option<&str> {
+        self.format_version.as_deref()
+    }
+
+    pub fn import_info(&self) -> Option<&ImportInfo> {
+        self.import_info.as_ref()
+    }
+
+    pub(crate) fn set_import_info(&mut self, import_info: ImportInfo) {
+        self.import_info = Some(import_info);
+    }
+
+    /// Checksum of the dictionary's files when built, checked by [`StoredDictionary::verify`](crate::database::storage::StoredDictionary::verify).
+    pub fn checksum(&self) -> Option<&str> {
+        self.checksum.as_deref()
+    }
+
+    pub(crate) fn set_checksum(&mut self, checksum: Option<String>) {
+        self.checksum = checksum;
+    }
 }
 
 impl Default for DictionaryMetadata {
     fn default() -> Self {
+        DictionaryMetadataBuilder::new("").build()
+    }
+}
+
+/// Builder for [`DictionaryMetadata`], where everything but the name is optional.
+#[derive(Debug, Clone)]
+pub struct DictionaryMetadataBuilder {
+    metadata: DictionaryMetadata,
+}
+
+impl DictionaryMetadataBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
         Self {
-            name: Default::default(),
-            author: Default::default(),
-            version: Version::new(0, 0, 0),
-            homepage_url: Default::default(),
-            update_url: Default::default(),
-            notes: Default::default(),
+            metadata: DictionaryMetadata {
+                name: name.into(),
+                author: Default::default(),
+                version: Version::new(0, 0, 0),
+                homepage_url: Default::default(),
+                update_url: Default::default(),
+                notes: Default::default(),
+                tags: Default::default(),
+                display_name: Default::default(),
+                source_date: Default::default(),
+                languages: Default::default(),
+                format_version: Default::default(),
+                import_info: Default::default(),
+                checksum: Default::default(),
+            },
         }
     }
+
+    pub fn author(mut self, author: impl Into<String>) -> Self {
+        self.metadata.author = author.into();
+        self
+    }
+
+    pub fn version(mut self, version: Version) -> Self {
+        self.metadata.version = version;
+        self
+    }
+
+    pub fn homepage_url(mut self, homepage_url: Option<Url>) -> Self {
+        self.metadata.homepage_url = homepage_url;
+        self
+    }
+
+    pub fn update_url(mut self, update_url: Option<Url>) -> Self {
+        self.metadata.update_url = update_url;
+        self
+    }
+
+    pub fn notes(mut self, notes: impl Into<String>) -> Self {
+        self.metadata.notes = notes.into();
+        self
+    }
+
+    pub fn tags(mut self, tags: BTreeMap<String, String>) -> Self {
+        self.metadata.tags = tags;
+        self
+    }
+
+    pub fn display_name(mut self, display_name: Option<String>) -> Self {
+        self.metadata.display_name = display_name;
+        self
+    }
+
+    pub fn source_date(mut self, source_date: Option<String>) -> Self {
+        self.metadata.source_date = source_date;
+        self
+    }
+
+    pub fn languages(mut self, languages: Vec<String>) -> Self {
+        self.metadata.languages = languages;
+        self
+    }
+
+    pub fn format_version(mut self, format_version: Option<String>) -> Self {
+        self.metadata.format_version = format_version;
+        self
+    }
+
+    pub fn import_info(mut self, import_info: Option<ImportInfo>) -> Self {
+        self.metadata.import_info = import_info;
+        self
+    }
+
+    pub fn build(self) -> DictionaryMetadata {
+        self.metadata
+    }
 }
 
 #[cfg(test)]
@@ -69,11 +373,26 @@ mod tests {
             readings: vec!["あける".to_owned()],
             gloss: "to open (a door, etc.), to unwrap (e.g. parcel, package), to unlock".to_owned(),
             tags: vec!["P".to_owned(), "v1".to_owned(), "vt".to_owned()],
+            id: Some("1202440".to_owned()),
         };
 
         let serialized = dict_entry.serialize_fast();
         let deserialized = DictionaryEntry::deserialize_fast(&serialized);
 
         assert_eq!(dict_entry, deserialized);
+
+        let legacy = LegacyDictionaryEntry {
+            readings: dict_entry.readings.clone(),
+            gloss: dict_entry.gloss.clone(),
+            tags: dict_entry.tags.clone(),
+        };
+        let serialized = bitcode::encode(&legacy).unwrap();
+        assert_eq!(
+            DictionaryEntry::deserialize_fast(&serialized),
+            DictionaryEntry {
+                id: None,
+                ..dict_entry
+            }
+        );
     }
 }
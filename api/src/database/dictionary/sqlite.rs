@@ -0,0 +1,472 @@
+//! Dictionaries stored in a single SQLite file, which can also be searched by gloss and filtered by tag.
+//!
+//! Entries are in the `entries` table, with their readings and tags in `entry_readings` and `entry_tags`,
+//! the glosses indexed by the FTS5 table `glosses`, and the metadata as JSON in `metadata`.
+//! The schema is meant to be easy to query with standard SQLite tools too.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use tempfile::TempPath;
+use thiserror::Error;
+
+use super::{Dictionary, DictionaryBuilder, DictionaryEntry, DictionaryMetadata};
+use crate::database::storage::{self, StoredDictionary};
+
+const SCHEMA: &str = "
+CREATE TABLE metadata (json TEXT NOT NULL);
+CREATE TABLE entries (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL,
+    gloss TEXT NOT NULL,
+    source_id TEXT
+);
+CREATE TABLE entry_readings (
+    entry INTEGER NOT NULL REFERENCES entries (id),
+    position INTEGER NOT NULL,
+    reading TEXT NOT NULL,
+    PRIMARY KEY (entry, position)
+) WITHOUT ROWID;
+CREATE TABLE entry_tags (
+    entry INTEGER NOT NULL REFERENCES entries (id),
+    position INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (entry, position)
+) WITHOUT ROWID;
+CREATE VIRTUAL TABLE glosses USING fts5 (gloss, content = 'entries', content_rowid = 'id');
+";
+
+/// Indexes created once every entry is added, which is faster than updating them on every insert.
+const INDEXES: &str = "
+CREATE INDEX entries_key ON entries (key);
+CREATE INDEX entries_source_id ON entries (source_id);
+CREATE INDEX entry_readings_reading ON entry_readings (reading);
+CREATE INDEX entry_tags_tag ON entry_tags (tag, entry);
+INSERT INTO glosses (glosses) VALUES ('optimize');
+";
+
+/// Amount of entries read at once by [`Dictionary::iter`].
+const ITER_BATCH_LEN: usize = 1024;
+
+pub struct SQLiteDictionaryBuilder {
+    connection: Connection,
+    path: PathBuf,
+    // Entries are written here first, and only moved to `path` once the dictionary is built.
+    tmp_path: TempPath,
+}
+
+impl SQLiteDictionaryBuilder {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        // SQLite opens the file itself, and an empty file is an empty database.
+        let (_, tmp_path) = storage::create_temp_file(&path)?;
+
+        let connection = Connection::open(&tmp_path)?;
+        // Nothing needs to survive a crash before the file is renamed, so durability is traded for speed.
+        connection.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+        connection.execute_batch(SCHEMA)?;
+        connection.execute_batch("BEGIN")?;
+
+        Ok(Self {
+            connection,
+            path,
+            tmp_path,
+        })
+    }
+}
+
+impl DictionaryBuilder for SQLiteDictionaryBuilder {
+    type Dictionary = SQLiteDictionary;
+    type Error = Error;
+
+    fn add(&mut self, key: &str, entry: DictionaryEntry) -> Result<(), Self::Error> {
+        self.connection
+            .prepare_cached("INSERT INTO entries (key, gloss, source_id) VALUES (?1, ?2, ?3)")?
+            .execute(params![key, entry.gloss, entry.id])?;
+        let id = self.connection.last_insert_rowid();
+
+        self.connection
+            .prepare_cached("INSERT INTO glosses (rowid, gloss) VALUES (?1, ?2)")?
+            .execute(params![id, entry.gloss])?;
+        let mut insert_reading = self.connection.prepare_cached(
+            "INSERT INTO entry_readings (entry, position, reading) VALUES (?1, ?2, ?3)",
+        )?;
+        for (position, reading) in entry.readings.iter().enumerate() {
+            insert_reading.execute(params![id, position, reading])?;
+        }
+        let mut insert_tag = self
+            .connection
+            .prepare_cached("INSERT INTO entry_tags (entry, position, tag) VALUES (?1, ?2, ?3)")?;
+        for (position, tag) in entry.tags.iter().enumerate() {
+            insert_tag.execute(params![id, position, tag])?;
+        }
+
+        Ok(())
+    }
+
+    fn build(self, metadata: DictionaryMetadata) -> Result<Self::Dictionary, Self::Error> {
+        self.connection.execute(
+            "INSERT INTO metadata (json) VALUES (?1)",
+            params![serde_json::to_string(&metadata)?],
+        )?;
+        self.connection.execute_batch(INDEXES)?;
+        self.connection.execute_batch("COMMIT")?;
+        drop(self.connection);
+
+        storage::persist_temp_file(self.tmp_path, &self.path)?;
+        SQLiteDictionary::open(self.path)
+    }
+
+    fn discard(self) -> Result<(), Self::Error> {
+        drop(self.connection);
+        self.tmp_path.close()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("SQLite error")]
+    SQLite(#[from] rusqlite::Error),
+    #[error("SQLite dictionary file IO error")]
+    FileIo(#[from] std::io::Error),
+    #[error("invalid SQLite dictionary metadata")]
+    Metadata(#[from] serde_json::Error),
+    #[error("SQLite dictionary has no metadata")]
+    MissingMetadata,
+}
+
+/// A dictionary in a SQLite file, serialized as the path of that file.
+pub struct SQLiteDictionary {
+    /// Connections which no lookup is using. A connection can't be shared between threads, but the dictionary may
+    /// be, so each lookup takes one, or opens another if all are in use, and lookups never wait on each other.
+    connections: Mutex<Vec<Connection>>,
+    path: PathBuf,
+    metadata: DictionaryMetadata,
+}
+
+impl SQLiteDictionary {
+    /// Opens a dictionary built by [`SQLiteDictionaryBuilder`].
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let connection = Connection::open(&path)?;
+        let metadata = connection
+            .query_row("SELECT json FROM metadata", [], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()?
+            .ok_or(Error::MissingMetadata)?;
+
+        Ok(Self {
+            connections: Mutex::new(vec![connection]),
+            path,
+            metadata: serde_json::from_str(&metadata)?,
+        })
+    }
+
+    /// Runs `f` with a connection which no other thread uses meanwhile.
+    fn with_connection<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let connection = self.connections.lock().unwrap().pop();
+        let connection = match connection {
+            Some(connection) => connection,
+            // Lookups never write, and the file is only ever replaced, not changed.
+            None => Connection::open_with_flags(
+                &self.path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?,
+        };
+        let result = f(&connection);
+        self.connections.lock().unwrap().push(connection);
+        result
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the entries having `reading` as one of their readings, along with their keys.
+    pub fn get_by_reading(&self, reading: &str) -> Result<Vec<(String, DictionaryEntry)>, Error> {
+        self.query(
+            "SELECT id, key, gloss, source_id FROM entries
+            WHERE id IN (SELECT entry FROM entry_readings WHERE reading = ?1)
+            ORDER BY id",
+            &[reading],
+        )
+    }
+
+    /// Finds up to `limit` entries whose gloss matches an FTS5 `query` and which have every tag of `tags`.
+    ///
+    /// Without a query, every entry having the tags matches. Entries are sorted by relevance, best first.
+    pub fn search(
+        &self,
+        query: Option<&str>,
+        tags: &[&str],
+        limit: usize,
+    ) -> Result<Vec<(String, DictionaryEntry)>, Error> {
+        let mut sql =
+            "SELECT entries.id, entries.key, entries.gloss, entries.source_id FROM entries"
+                .to_owned();
+        let mut parameters = Vec::new();
+        if let Some(query) = query {
+            sql.push_str(" JOIN glosses ON glosses.rowid = entries.id WHERE glosses MATCH ?");
+            parameters.push(query.to_owned());
+        } else {
+            sql.push_str(" WHERE 1");
+        }
+        for tag in tags {
+            sql.push_str(" AND entries.id IN (SELECT entry FROM entry_tags WHERE tag = ?)");
+            parameters.push((*tag).to_owned());
+        }
+        sql.push_str(match query {
+            Some(_) => " ORDER BY glosses.rank",
+            None => " ORDER BY entries.id",
+        });
+        sql.push_str(&format!(" LIMIT {limit}"));
+
+        self.query(&sql, &parameters)
+    }
+
+    /// Runs a query selecting the id, key, gloss and source id of entries, and completes them with their readings and tags.
+    fn query<P: rusqlite::ToSql>(
+        &self,
+        sql: &str,
+        parameters: &[P],
+    ) -> Result<Vec<(String, DictionaryEntry)>, Error> {
+        Ok(self
+            .query_with_ids(sql, parameters)?
+            .into_iter()
+            .map(|(_, key, entry)| (key, entry))
+            .collect())
+    }
+
+    /// Like [`SQLiteDictionary::query`], but also returns the row id of every entry.
+    fn query_with_ids<P: rusqlite::ToSql>(
+        &self,
+        sql: &str,
+        parameters: &[P],
+    ) -> Result<Vec<(i64, String, DictionaryEntry)>, Error> {
+        self.with_connection(|connection| {
+            let rows = connection
+                .prepare_cached(sql)?
+                .query_map(params_from_iter(parameters), |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut readings = connection.prepare_cached(
+                "SELECT reading FROM entry_readings WHERE entry = ?1 ORDER BY position",
+            )?;
+            let mut tags = connection
+                .prepare_cached("SELECT tag FROM entry_tags WHERE entry = ?1 ORDER BY position")?;
+            rows.into_iter()
+                .map(|(id, key, gloss, source_id)| {
+                    let entry = DictionaryEntry {
+                        readings: readings
+                            .query_map([id], |row| row.get(0))?
+                            .collect::<Result<_, _>>()?,
+                        gloss,
+                        tags: tags
+                            .query_map([id], |row| row.get(0))?
+                            .collect::<Result<_, _>>()?,
+                        id: source_id,
+                    };
+                    Ok((id, key, entry))
+                })
+                .collect()
+        })
+    }
+
+    /// Falls back to nothing found for the [`Dictionary`] methods, which can't fail, after logging why.
+    fn or_log<T: Default>(&self, result: Result<T, Error>) -> T {
+        result.unwrap_or_else(|error| {
+            log::error!("could not read {}: {error}", self.path.display());
+            T::default()
+        })
+    }
+}
+
+impl Dictionary for SQLiteDictionary {
+    fn get(&self, key: &str) -> Vec<DictionaryEntry> {
+        let entries = self.query(
+            "SELECT id, key, gloss, source_id FROM entries WHERE key = ?1 ORDER BY id",
+            &[key],
+        );
+        self.or_log(entries.map(|entries| entries.into_iter().map(|(_, entry)| entry).collect()))
+    }
+
+    fn get_metadata(&self) -> &DictionaryMetadata {
+        &self.metadata
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_> {
+        // Reading in batches keeps the connection free in between, and memory use bounded.
+        let mut last_id = 0;
+        Box::new(
+            std::iter::from_fn(move || {
+                let batch = self.or_log(self
+                    .query_with_ids(
+                        &format!(
+                            "SELECT id, key, gloss, source_id FROM entries WHERE id > ?1 ORDER BY id LIMIT {ITER_BATCH_LEN}"
+                        ),
+                        &[last_id],
+                    ));
+                // Ids may have gaps, so the next batch starts after the last one actually read.
+                last_id = batch.last()?.0;
+                Some(batch.into_iter().map(|(_, key, entry)| (key, entry)))
+            })
+            .flatten(),
+        )
+    }
+
+    fn get_by_tags(&self, tags: &[&str]) -> Vec<(String, DictionaryEntry)> {
+        // SQLite limits are signed.
+        self.or_log(self.search(None, tags, i64::MAX as usize))
+    }
+}
+
+impl Serialize for SQLiteDictionary {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        storage::serialize_path(&self.path, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SQLiteDictionary {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let path = storage::deserialize_path(deserializer)?;
+        Self::open(path).map_err(de::Error::custom)
+    }
+}
+
+impl StoredDictionary for SQLiteDictionary {
+    fn files(&self) -> Vec<PathBuf> {
+        vec![self.path.clone()]
+    }
+
+    fn serialized_files(serialized: &serde_json::Value) -> Vec<PathBuf> {
+        storage::deserialize_path(serialized).into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = temp_dir.path().join("sqlite-test-basic.sqlite");
+
+        let entry = |readings: &[&str], gloss: &str, tags: &[&str]| DictionaryEntry {
+            readings: readings.iter().map(|s| s.to_string()).collect(),
+            gloss: gloss.to_owned(),
+            tags: tags.iter().map(|s| s.to_string()).collect(),
+            id: Some("1".to_owned()),
+        };
+        let open = entry(&["あける"], "to open (a door, etc.)", &["v1", "vt"]);
+        let dawn = entry(&["あける"], "to dawn\nto grow light", &["v1", "vi"]);
+        let empty = entry(&["あける", "からける"], "to empty", &["v1", "vt"]);
+
+        let mut dict_builder = SQLiteDictionaryBuilder::new(&path).unwrap();
+        dict_builder.add("開ける", open.clone()).unwrap();
+        dict_builder.add("明ける", dawn.clone()).unwrap();
+        dict_builder.add("明ける", open.clone()).unwrap();
+        dict_builder.add("空ける", empty.clone()).unwrap();
+        let dict = dict_builder
+            .build(DictionaryMetadata::builder("sqlite test basic").build())
+            .unwrap();
+
+        assert_eq!(dict.get("明ける"), vec![dawn.clone(), open.clone()]);
+        assert!(dict.get("開く").is_empty());
+        assert_eq!(
+            dict.get_by_reading("からける").unwrap(),
+            vec![("空ける".to_owned(), empty.clone())]
+        );
+        assert_eq!(dict.iter().count(), 4);
+
+        let keys = |results: Vec<(String, DictionaryEntry)>| {
+            results
+                .into_iter()
+                .map(|(key, _)| key)
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            keys(dict.search(Some("dawn"), &[], 10).unwrap()),
+            ["明ける"]
+        );
+        assert_eq!(
+            keys(dict.search(Some("open OR empty"), &["vt"], 10).unwrap()).len(),
+            3
+        );
+        assert_eq!(
+            keys(dict.search(None, &["vi", "v1"], 10).unwrap()),
+            ["明ける"]
+        );
+
+        let serialized = serde_json::to_string(&dict).unwrap();
+        drop(dict);
+        let dict = serde_json::from_str::<SQLiteDictionary>(&serialized).unwrap();
+        assert_eq!(dict.get_metadata().name(), "sqlite test basic");
+        assert_eq!(dict.get("空ける"), vec![empty]);
+
+        // Ids aren't contiguous anymore once entries are deleted.
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "DELETE FROM entry_readings WHERE entry = 2;
+                DELETE FROM entry_tags WHERE entry = 2;
+                DELETE FROM entries WHERE id = 2;",
+            )
+            .unwrap();
+        assert_eq!(dict.iter().count(), 3);
+    }
+
+    #[test]
+    fn concurrent() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let mut dict_builder =
+            SQLiteDictionaryBuilder::new(temp_dir.path().join("sqlite-test-concurrent.sqlite"))
+                .unwrap();
+        for index in 0..100 {
+            dict_builder
+                .add(
+                    &index.to_string(),
+                    DictionaryEntry {
+                        readings: vec![],
+                        gloss: index.to_string(),
+                        tags: vec![],
+                        id: None,
+                    },
+                )
+                .unwrap();
+        }
+        let dict = dict_builder.build(Default::default()).unwrap();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for (key, entry) in dict.iter() {
+                        assert_eq!(dict.get(&key), vec![entry]);
+                    }
+                });
+            }
+        });
+        // A lookup while another holds a connection doesn't wait for it.
+        dict.with_connection(|_| {
+            assert_eq!(dict.get("7")[0].gloss, "7");
+            Ok(())
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,390 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{
+    Dictionary, DictionaryBuilder, DictionaryEntry, DictionaryMetadata, EntryId, MutableDictionary,
+};
+
+#[derive(Default)]
+pub struct UserDictionaryBuilder {
+    dictionary: UserDictionary,
+}
+
+impl UserDictionaryBuilder {
+    pub fn new() -> Self {
+        Self {
+            dictionary: Default::default(),
+        }
+    }
+}
+
+impl DictionaryBuilder for UserDictionaryBuilder {
+    type Dictionary = UserDictionary;
+    type Error = Error;
+
+    fn add(&mut self, key: &str, entry: DictionaryEntry) -> Result<(), Self::Error> {
+        self.dictionary.insert(key, entry)?;
+        Ok(())
+    }
+
+    fn build(mut self, metadata: DictionaryMetadata) -> Result<Self::Dictionary, Self::Error> {
+        self.dictionary.metadata = metadata;
+        Ok(self.dictionary)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no entry with ID {}", .0)]
+    UnknownEntry(EntryId),
+    #[error("dictionary {} is not a user dictionary", .0)]
+    NotUserDictionary(usize),
+    #[error("CSV error: {}", .0)]
+    Csv(#[from] csv::Error),
+    #[error("CSV line {} has no column {}", .line, .column)]
+    MissingColumn { line: u64, column: usize },
+}
+
+/// A dictionary whose entries can be added, edited and deleted after it is built.
+///
+/// Meant for small personal dictionaries, so everything is kept in memory and serialized inline.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(from = "SerializedUserDictionary", into = "SerializedUserDictionary")]
+pub struct UserDictionary {
+    entries: BTreeMap<EntryId, UserEntry>,
+    index: HashMap<String, Vec<EntryId>>,
+    next_id: EntryId,
+    metadata: DictionaryMetadata,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UserEntry {
+    pub key: String,
+    pub entry: DictionaryEntry,
+}
+
+/// The serialized form of a [`UserDictionary`], without the key index which is rebuilt on load.
+#[derive(Serialize, Deserialize)]
+struct SerializedUserDictionary {
+    entries: BTreeMap<EntryId, UserEntry>,
+    next_id: EntryId,
+    metadata: DictionaryMetadata,
+}
+
+impl From<SerializedUserDictionary> for UserDictionary {
+    fn from(serialized: SerializedUserDictionary) -> Self {
+        let mut index: HashMap<String, Vec<EntryId>> = HashMap::new();
+        for (id, user_entry) in serialized.entries.iter() {
+            index.entry(user_entry.key.clone()).or_default().push(*id);
+        }
+
+        Self {
+            entries: serialized.entries,
+            index,
+            next_id: serialized.next_id,
+            metadata: serialized.metadata,
+        }
+    }
+}
+
+impl From<UserDictionary> for SerializedUserDictionary {
+    fn from(dictionary: UserDictionary) -> Self {
+        Self {
+            entries: dictionary.entries,
+            next_id: dictionary.next_id,
+            metadata: dictionary.metadata,
+        }
+    }
+}
+
+impl UserDictionary {
+    pub fn get_entry(&self, id: EntryId) -> Option<&UserEntry> {
+        self.entries.get(&id)
+    }
+
+    /// Gets the entries for a key along with their IDs, for editing.
+    pub fn get_with_ids(&self, key: &str) -> Vec<(EntryId, &DictionaryEntry)> {
+        self.index
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(|id| (*id, &self.entries[id].entry))
+            .collect()
+    }
+
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (EntryId, &UserEntry)> {
+        self.entries
+            .iter()
+            .map(|(id, user_entry)| (*id, user_entry))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn set_metadata(&mut self, metadata: DictionaryMetadata) {
+        self.metadata = metadata;
+    }
+
+    /// Adds every record of a CSV or TSV file as an entry, returning the amount of entries added.
+    ///
+    /// Nothing is added if any record is invalid.
+    pub fn import_csv<R: Read>(&mut self, reader: R, options: &CsvOptions) -> Result<usize, Error> {
+        let mut csv_reader = csv::ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .has_headers(options.has_headers)
+            .flexible(true)
+            .from_reader(reader);
+
+        let mut new_entries = Vec::new();
+        for record in csv_reader.records() {
+            let record = record?;
+            let list = |column: Option<usize>| -> Result<Vec<String>, Error> {
+                Ok(match column {
+                    Some(column) => split_list(column_value(&record, column)?, options.separator),
+                    None => Vec::new(),
+                })
+            };
+
+            let key = column_value(&record, options.columns.key)?.trim();
+            if key.is_empty() {
+                continue;
+            }
+
+            new_entries.push((
+                key.to_owned(),
+                DictionaryEntry {
+                    readings: list(options.columns.readings)?,
+                    gloss: column_value(&record, options.columns.gloss)?.to_owned(),
+                    tags: list(options.columns.tags)?,
+                    id: None,
+                },
+            ));
+        }
+
+        let added = new_entries.len();
+        for (key, entry) in new_entries {
+            self.insert(&key, entry)?;
+        }
+
+        Ok(added)
+    }
+
+    fn unindex(&mut self, key: &str, id: EntryId) {
+        if let Some(ids) = self.index.get_mut(key) {
+            ids.retain(|other| *other != id);
+            if ids.is_empty() {
+                self.index.remove(key);
+            }
+        }
+    }
+}
+
+fn column_value(record: &csv::StringRecord, column: usize) -> Result<&str, Error> {
+    record.get(column).ok_or(Error::MissingColumn {
+        line: record.position().map_or(0, |position| position.line()),
+        column,
+    })
+}
+
+fn split_list(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+impl Dictionary for UserDictionary {
+    fn get(&self, key: &str) -> Vec<DictionaryEntry> {
+        self.get_with_ids(key)
+            .into_iter()
+            .map(|(_, entry)| entry.clone())
+            .collect()
+    }
+
+    fn get_metadata(&self) -> &DictionaryMetadata {
+        &self.metadata
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (String, DictionaryEntry)> + '_> {
+        Box::new(
+            self.entries
+                .values()
+                .map(|user_entry| (user_entry.key.clone(), user_entry.entry.clone())),
+        )
+    }
+}
+
+impl MutableDictionary for UserDictionary {
+    type Error = Error;
+
+    fn insert(&mut self, key: &str, entry: DictionaryEntry) -> Result<EntryId, Self::Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.entries.insert(
+            id,
+            UserEntry {
+                key: key.to_owned(),
+                entry,
+            },
+        );
+        self.index.entry(key.to_owned()).or_default().push(id);
+
+        Ok(id)
+    }
+
+    fn update(
+        &mut self,
+        id: EntryId,
+        key: &str,
+        entry: DictionaryEntry,
+    ) -> Result<(), Self::Error> {
+        let old_key = self
+            .entries
+            .get(&id)
+            .ok_or(Error::UnknownEntry(id))?
+            .key
+            .clone();
+
+        if old_key != key {
+            self.unindex(&old_key, id);
+            self.index.entry(key.to_owned()).or_default().push(id);
+        }
+        self.entries.insert(
+            id,
+            UserEntry {
+                key: key.to_owned(),
+                entry,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn remove(&mut self, id: EntryId) -> Result<DictionaryEntry, Self::Error> {
+        let user_entry = self.entries.remove(&id).ok_or(Error::UnknownEntry(id))?;
+        self.unindex(&user_entry.key, id);
+        Ok(user_entry.entry)
+    }
+}
+
+/// Which columns of a CSV or TSV file hold which entry fields, by zero-based index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ColumnMapping {
+    pub key: usize,
+    pub gloss: usize,
+    pub readings: Option<usize>,
+    pub tags: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CsvOptions {
+    pub columns: ColumnMapping,
+    pub delimiter: u8,
+    pub has_headers: bool,
+    /// Separates multiple readings or tags within a single column.
+    pub separator: char,
+}
+
+impl CsvOptions {
+    pub fn csv(columns: ColumnMapping) -> Self {
+        Self {
+            columns,
+            delimiter: b',',
+            has_headers: true,
+            separator: ';',
+        }
+    }
+
+    pub fn tsv(columns: ColumnMapping) -> Self {
+        Self {
+            delimiter: b'\t',
+            ..Self::csv(columns)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let mut dict = UserDictionaryBuilder::new()
+            .build(Default::default())
+            .unwrap();
+
+        let csv = "term\treading\tmeaning\ttags\n\
+            勇者\tゆうしゃ\thero (party leader)\tn;game\n\
+            魔王城\tまおうじょう\tDemon King's castle\tn\n";
+        let added = dict
+            .import_csv(
+                csv.as_bytes(),
+                &CsvOptions::tsv(ColumnMapping {
+                    key: 0,
+                    gloss: 2,
+                    readings: Some(1),
+                    tags: Some(3),
+                }),
+            )
+            .unwrap();
+        assert_eq!(added, 2);
+
+        let (id, entry) = dict.get_with_ids("勇者")[0];
+        assert_eq!(entry.readings, vec!["ゆうしゃ"]);
+        assert_eq!(entry.tags, vec!["n", "game"]);
+
+        let mut edited = entry.clone();
+        edited.gloss = "hero".to_owned();
+        dict.update(id, "ゆうしゃ", edited.clone()).unwrap();
+        assert!(dict.get("勇者").is_empty());
+        assert_eq!(dict.get("ゆうしゃ"), vec![edited]);
+
+        let id = dict
+            .insert(
+                "セーブ",
+                DictionaryEntry {
+                    readings: vec![],
+                    gloss: "save (game progress)".to_owned(),
+                    tags: vec![],
+                    id: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(dict.len(), 3);
+        dict.remove(id).unwrap();
+        assert!(dict.get("セーブ").is_empty());
+        assert!(matches!(dict.remove(id), Err(Error::UnknownEntry(_))));
+
+        let serialized = serde_json::to_string(&dict).expect("could not serialize dictionary");
+        let deserialized = serde_json::from_str::<UserDictionary>(&serialized)
+            .expect("could not deserialize dictionary");
+        assert_eq!(deserialized.get("魔王城"), dict.get("魔王城"));
+        assert_eq!(deserialized.next_id, dict.next_id);
+
+        let invalid = "a,b\nc\n";
+        let result = dict.import_csv(
+            invalid.as_bytes(),
+            &CsvOptions::csv(ColumnMapping {
+                key: 0,
+                gloss: 1,
+                readings: None,
+                tags: None,
+            }),
+        );
+        assert!(matches!(result, Err(Error::MissingColumn { .. })));
+        assert_eq!(dict.len(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+//! Example sentences, linked to the headwords they illustrate.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use cdb::{CDBMake, CDB};
+use serde::{Deserialize, Serialize};
+use tempfile::TempPath;
+use thiserror::Error;
+
+use super::{
+    dictionary::cdb::{deserialize_cdb, serialize_cdb},
+    storage::{self, StoredFile},
+};
+
+pub mod tanaka;
+
+/// Prefix of the keys sentences are stored under, which can't clash with a headword.
+const SENTENCE_KEY_PREFIX: u8 = 0;
+
+#[derive(Debug, bitcode::Encode, bitcode::Decode, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct ExampleSentence {
+    /// ID of the Japanese sentence on Tatoeba.
+    pub id: u64,
+    pub japanese: String,
+    pub english: String,
+}
+
+impl ExampleSentence {
+    pub fn serialize_fast(&self) -> Vec<u8> {
+        bitcode::encode(self).unwrap()
+    }
+
+    pub fn deserialize_fast(data: &[u8]) -> Self {
+        bitcode::decode(data).unwrap()
+    }
+}
+
+/// A link from a headword to a sentence using it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExampleLink {
+    pub sentence_id: u64,
+    /// Whether the sentence was checked to be a good example for this headword.
+    pub checked: bool,
+}
+
+impl ExampleLink {
+    fn to_bytes(self) -> [u8; 9] {
+        let mut bytes = [0; 9];
+        bytes[..8].copy_from_slice(&self.sentence_id.to_le_bytes());
+        bytes[8] = self.checked as u8;
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self {
+            sentence_id: u64::from_le_bytes(bytes.get(..8)?.try_into().ok()?),
+            checked: bytes.get(8) == Some(&1),
+        })
+    }
+}
+
+fn sentence_key(id: u64) -> [u8; 9] {
+    let mut key = [SENTENCE_KEY_PREFIX; 9];
+    key[1..].copy_from_slice(&id.to_le_bytes());
+    key
+}
+
+pub struct ExampleStoreBuilder {
+    cdb_make: CDBMake,
+    path: PathBuf,
+    tmp_path: TempPath,
+}
+
+impl ExampleStoreBuilder {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let (file, tmp_path) = storage::create_temp_file(&path)?;
+
+        Ok(Self {
+            cdb_make: CDBMake::new(file)?,
+            path,
+            tmp_path,
+        })
+    }
+
+    /// Adds a sentence along with the headwords it illustrates.
+    pub fn add(&mut self, sentence: &ExampleSentence, links: &[(&str, bool)]) -> Result<(), Error> {
+        self.cdb_make
+            .add(&sentence_key(sentence.id), &sentence.serialize_fast())?;
+
+        for (headword, checked) in links {
+            let link = ExampleLink {
+                sentence_id: sentence.id,
+                checked: *checked,
+            };
+            self.cdb_make.add(headword.as_bytes(), &link.to_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<ExampleStore, Error> {
+        self.cdb_make.finish()?;
+        storage::persist_temp_file(self.tmp_path, &self.path)?;
+        Ok(ExampleStore {
+            cdb_pathbuf: (CDB::open(&self.path)?, self.path),
+        })
+    }
+
+    /// Discards everything added so far, removing the partially written store.
+    pub fn discard(self) -> Result<(), Error> {
+        drop(self.cdb_make);
+        self.tmp_path.close()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("CDB file io error")]
+    CDBFileIo(#[from] std::io::Error),
+}
+
+/// A store of example sentences, looked up by headword.
+#[derive(Serialize, Deserialize)]
+pub struct ExampleStore {
+    #[serde(serialize_with = "serialize_cdb", deserialize_with = "deserialize_cdb")]
+    cdb_pathbuf: (CDB, PathBuf),
+}
+
+impl StoredFile for ExampleStore {
+    fn file(&self) -> &Path {
+        self.path()
+    }
+
+    fn serialized_file(serialized: &serde_json::Value) -> Option<PathBuf> {
+        storage::deserialize_path(serialized.get("cdb_pathbuf")?).ok()
+    }
+}
+
+impl ExampleStore {
+    pub fn path(&self) -> &Path {
+        &self.cdb_pathbuf.1
+    }
+
+    /// Gets up to `limit` sentences using `headword`, checked examples first.
+    pub fn get(&self, headword: &str, limit: usize) -> Vec<ExampleSentence> {
+        let mut links = self
+            .cdb_pathbuf
+            .0
+            .find(headword.as_bytes())
+            .filter_map(Result::ok)
+            .filter_map(|v| ExampleLink::from_bytes(&v))
+            .collect::<Vec<ExampleLink>>();
+        // Stable, so sentences keep the corpus order otherwise.
+        links.sort_by_key(|link| !link.checked);
+        let mut seen = HashSet::new();
+        links.retain(|link| seen.insert(link.sentence_id));
+
+        links
+            .into_iter()
+            .filter_map(|link| self.get_sentence(link.sentence_id))
+            .take(limit)
+            .collect()
+    }
+
+    pub fn get_sentence(&self, id: u64) -> Option<ExampleSentence> {
+        self.cdb_pathbuf
+            .0
+            .get(&sentence_key(id))
+            .and_then(Result::ok)
+            .map(|v| ExampleSentence::deserialize_fast(&v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let mut builder = ExampleStoreBuilder::new(temp_dir.path().join("examples.cdb")).unwrap();
+        let first = ExampleSentence {
+            id: 1,
+            japanese: "猫が好きです。".to_owned(),
+            english: "I like cats.".to_owned(),
+        };
+        let second = ExampleSentence {
+            id: 2,
+            japanese: "猫を飼っています。".to_owned(),
+            english: "I have a cat.".to_owned(),
+        };
+        builder
+            .add(&first, &[("猫", false), ("好き", true)])
+            .unwrap();
+        builder.add(&second, &[("猫", true), ("猫", true)]).unwrap();
+        let store = builder.build().unwrap();
+
+        assert_eq!(store.get("猫", 10), vec![second.clone(), first.clone()]);
+        assert_eq!(store.get("猫", 1), vec![second]);
+        assert_eq!(store.get("好き", 10), vec![first]);
+        assert!(store.get("犬", 10).is_empty());
+    }
+}
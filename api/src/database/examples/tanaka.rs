@@ -0,0 +1,184 @@
+//! Importer for the Tanaka corpus as distributed with Tatoeba (`examples.utf`).
+//!
+//! Every sentence pair is an `A:` line with the translation after a tab, followed by a `B:` line indexing the headwords
+//! used in the sentence:
+//!
+//! ```text
+//! A: 彼は忙しい生活の中で家族と会うことがない。    He doesn't see his family in his busy life.#ID=303645_100000
+//! B: 彼(かれ)[01] は 忙しい 生活 の 中 で 家族 と 会う[01]{会う} 事(こと){こと} が 無い{ない}
+//! ```
+//!
+//! In the `B:` line, a headword can be followed by its reading in `()`, its sense number in `[]`,
+//! its form in the sentence in `{}`, and `~` if the sentence was checked to be a good example for it.
+
+use std::{fs::File, io::BufRead, path::Path};
+
+use thiserror::Error;
+
+use crate::database::dictionary::importer::{self, context::ImportContext, source};
+
+use super::{ExampleSentence, ExampleStore, ExampleStoreBuilder};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("malformed sentence on line {}", .0)]
+    MalformedSentence(usize),
+}
+
+type ImportError = importer::Error<Error, super::Error>;
+
+/// Imports the corpus from a file, which may be compressed or inside an archive.
+pub fn import_path(
+    path: impl AsRef<Path>,
+    builder: ExampleStoreBuilder,
+    context: &ImportContext,
+) -> Result<ExampleStore, ImportError> {
+    let file = File::open(path).map_err(importer::Error::DictFileIo)?;
+    let total_bytes = file.metadata().ok().map(|metadata| metadata.len());
+
+    source::decompress(context.track_reader(file, total_bytes), |reader| {
+        import(reader, builder, context)
+    })
+    .map_err(importer::Error::DictFileIo)?
+}
+
+/// Imports the corpus from an uncompressed source.
+///
+/// If the import fails or is cancelled, anything written by `builder` is discarded.
+pub fn import<R: BufRead>(
+    reader: R,
+    mut builder: ExampleStoreBuilder,
+    context: &ImportContext,
+) -> Result<ExampleStore, ImportError> {
+    if let Err(error) = add_sentences(reader, &mut builder, context) {
+        // The import error is more relevant to the caller than a failure to clean up.
+        let _ = builder.discard();
+        return Err(error);
+    }
+
+    builder.build().map_err(importer::Error::DictBuilder)
+}
+
+fn add_sentences<R: BufRead>(
+    reader: R,
+    builder: &mut ExampleStoreBuilder,
+    context: &ImportContext,
+) -> Result<(), ImportError> {
+    let mut sentence = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(importer::Error::DictFileIo)?;
+        let line_number = index + 1;
+
+        if let Some(a_line) = line.strip_prefix("A: ") {
+            sentence = Some(
+                parse_sentence(a_line).ok_or(importer::Error::ImporterSpecific(
+                    Error::MalformedSentence(line_number),
+                ))?,
+            );
+        } else if let Some(b_line) = line.strip_prefix("B: ") {
+            // A word index without a sentence is useless, so it's skipped.
+            let Some(sentence) = sentence.take() else {
+                continue;
+            };
+
+            builder
+                .add(&sentence, &parse_headwords(b_line))
+                .map_err(importer::Error::DictBuilder)?;
+            context
+                .word_processed()
+                .map_err(|_| importer::Error::Cancelled)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses the contents of an `A:` line.
+fn parse_sentence(line: &str) -> Option<ExampleSentence> {
+    let (japanese, rest) = line.split_once('\t')?;
+    let (english, ids) = rest.rsplit_once("#ID=")?;
+    let id = ids.split('_').next()?.trim().parse().ok()?;
+
+    Some(ExampleSentence {
+        id,
+        japanese: japanese.trim().to_owned(),
+        english: english.trim().to_owned(),
+    })
+}
+
+/// Parses the contents of a `B:` line into headwords, and whether the sentence is a checked example for each.
+fn parse_headwords(line: &str) -> Vec<(&str, bool)> {
+    let mut headwords: Vec<(&str, bool)> = Vec::new();
+
+    for word in line.split_whitespace() {
+        let headword_end = word.find(['(', '[', '{', '~']).unwrap_or(word.len());
+        let headword = &word[..headword_end];
+        if headword.is_empty() {
+            continue;
+        }
+        let checked = word.contains('~');
+
+        match headwords.iter_mut().find(|(other, _)| *other == headword) {
+            Some((_, other_checked)) => *other_checked |= checked,
+            None => headwords.push((headword, checked)),
+        }
+    }
+
+    headwords
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let corpus = "A: 彼は忙しい生活の中で家族と会うことがない。\tHe doesn't see his family in his busy life.#ID=303645_100000
+B: 彼(かれ)[01] は 忙しい 生活 の 中 で 家族 と 会う[01]{会う} 事(こと){こと} が 無い{ない}
+A: 家族を大切にしてください。\tPlease take care of your family.#ID=123_456
+B: 家族~ を 大切{大切に} 為る(する){して} 下さる{ください}
+";
+        let path = temp_dir.path().join("examples.utf");
+        fs::write(&path, corpus).expect("could not write corpus to temp dir");
+
+        let store = import_path(
+            &path,
+            ExampleStoreBuilder::new(temp_dir.path().join("examples.cdb")).unwrap(),
+            &Default::default(),
+        )
+        .expect("error while importing corpus");
+
+        let examples = store.get("家族", 10);
+        assert_eq!(examples.len(), 2);
+        assert_eq!(examples[0].id, 123);
+        assert_eq!(examples[0].english, "Please take care of your family.");
+        assert_eq!(
+            examples[1].japanese,
+            "彼は忙しい生活の中で家族と会うことがない。"
+        );
+        assert_eq!(store.get("事", 10).len(), 1);
+        assert!(store.get("こと", 10).is_empty());
+
+        fs::write(&path, "A: no tab or ID\n").expect("could not write corpus to temp dir");
+        assert!(matches!(
+            import_path(
+                &path,
+                ExampleStoreBuilder::new(temp_dir.path().join("invalid.cdb")).unwrap(),
+                &Default::default(),
+            ),
+            Err(importer::Error::ImporterSpecific(Error::MalformedSentence(
+                1
+            )))
+        ));
+        assert!(!fs::read_dir(temp_dir.path()).unwrap().any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .ends_with(".tmp")));
+    }
+}
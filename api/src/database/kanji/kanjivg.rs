@@ -0,0 +1,264 @@
+//! Importer for the KanjiVG stroke order data.
+//!
+//! Both release forms are supported: the combined `kanjivg-<date>.xml` (optionally gzipped),
+//! and a directory of per-kanji SVG files such as `kanji/05b57.svg`.
+//! Variant files (`05b57-Kaisho.svg`) are skipped, keeping only the standard stroke order.
+
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use quick_xml::{events::Event, Reader};
+use thiserror::Error;
+
+use crate::database::dictionary::importer::{self, context::ImportContext, source};
+
+use super::strokes::{self, Stroke, StrokeOrder, StrokeOrderStore, StrokeOrderStoreBuilder};
+
+/// Prefix of the element ID wrapping a kanji's strokes in the combined XML file.
+const KANJI_ID_PREFIX: &str = "kvg:kanji_";
+/// Prefix of the group ID wrapping a kanji's strokes in a SVG file.
+const STROKE_PATHS_ID_PREFIX: &str = "kvg:StrokePaths_";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("XML error: {}", .0)]
+    Xml(#[from] quick_xml::Error),
+    #[error("invalid kanji ID: {}", .0)]
+    InvalidKanjiId(String),
+}
+
+type ImportError = importer::Error<Error, strokes::Error>;
+
+/// Imports the combined XML file or a directory of SVG files.
+pub fn import_path(
+    path: impl AsRef<Path>,
+    builder: StrokeOrderStoreBuilder,
+    context: &ImportContext,
+) -> Result<StrokeOrderStore, ImportError> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        let file = File::open(path).map_err(importer::Error::DictFileIo)?;
+        let total_bytes = file.metadata().ok().map(|metadata| metadata.len());
+
+        return source::decompress(context.track_reader(file, total_bytes), |reader| {
+            import(reader, builder, context)
+        })
+        .map_err(importer::Error::DictFileIo)?;
+    }
+
+    let mut svg_paths = fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .map_err(importer::Error::DictFileIo)?;
+    svg_paths.retain(|path| path.extension().is_some_and(|extension| extension == "svg"));
+    svg_paths.sort();
+
+    with_builder(builder, |builder| {
+        for svg_path in svg_paths {
+            let file = File::open(svg_path).map_err(importer::Error::DictFileIo)?;
+            add_stroke_orders(BufReader::new(file), builder, context)?;
+        }
+        Ok(())
+    })
+}
+
+/// Imports the combined XML file, or a single SVG file, from an uncompressed source.
+///
+/// If the import fails or is cancelled, anything written by `builder` is discarded.
+pub fn import<R: BufRead>(
+    reader: R,
+    builder: StrokeOrderStoreBuilder,
+    context: &ImportContext,
+) -> Result<StrokeOrderStore, ImportError> {
+    with_builder(builder, |builder| {
+        add_stroke_orders(reader, builder, context)
+    })
+}
+
+fn with_builder(
+    mut builder: StrokeOrderStoreBuilder,
+    f: impl FnOnce(&mut StrokeOrderStoreBuilder) -> Result<(), ImportError>,
+) -> Result<StrokeOrderStore, ImportError> {
+    if let Err(error) = f(&mut builder) {
+        // The import error is more relevant to the caller than a failure to clean up.
+        let _ = builder.discard();
+        return Err(error);
+    }
+
+    builder.build().map_err(importer::Error::DictBuilder)
+}
+
+fn add_stroke_orders<R: BufRead>(
+    reader: R,
+    builder: &mut StrokeOrderStoreBuilder,
+    context: &ImportContext,
+) -> Result<(), ImportError> {
+    let mut reader = Reader::from_reader(reader);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    // The kanji being read, or `None` when reading a variant or anything else which is skipped.
+    let mut current: Option<(char, Vec<Stroke>)> = None;
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|error| importer::Error::ImporterSpecific(error.into()))?;
+
+        match event {
+            Event::Start(start) | Event::Empty(start) => match start.name().as_ref() {
+                b"kanji" | b"g" => {
+                    let id = attribute(&start, b"id")?.unwrap_or_default();
+                    if let Some(code) = id
+                        .strip_prefix(KANJI_ID_PREFIX)
+                        .or_else(|| id.strip_prefix(STROKE_PATHS_ID_PREFIX))
+                    {
+                        finish_kanji(current.take(), builder, context)?;
+                        if !code.contains('-') {
+                            let kanji = u32::from_str_radix(code, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| {
+                                    importer::Error::ImporterSpecific(Error::InvalidKanjiId(
+                                        id.clone(),
+                                    ))
+                                })?;
+                            current = Some((kanji, Vec::new()));
+                        }
+                    }
+                }
+                b"path" => {
+                    if let (Some((_, strokes)), Some(path)) =
+                        (current.as_mut(), attribute(&start, b"d")?)
+                    {
+                        strokes.push(Stroke {
+                            path,
+                            stroke_type: attribute(&start, b"kvg:type")?,
+                        });
+                    }
+                }
+                _ => (),
+            },
+            Event::Eof => break,
+            _ => (),
+        }
+
+        buf.clear();
+    }
+
+    finish_kanji(current, builder, context)
+}
+
+fn finish_kanji(
+    kanji: Option<(char, Vec<Stroke>)>,
+    builder: &mut StrokeOrderStoreBuilder,
+    context: &ImportContext,
+) -> Result<(), ImportError> {
+    let Some((kanji, strokes)) = kanji else {
+        return Ok(());
+    };
+    if strokes.is_empty() {
+        return Ok(());
+    }
+
+    builder
+        .add(kanji, &StrokeOrder { strokes })
+        .map_err(importer::Error::DictBuilder)?;
+    context
+        .word_processed()
+        .map_err(|_| importer::Error::Cancelled)
+}
+
+fn attribute(
+    start: &quick_xml::events::BytesStart,
+    name: &[u8],
+) -> Result<Option<String>, ImportError> {
+    let Some(attribute) = start
+        .try_get_attribute(name)
+        .map_err(|error| importer::Error::ImporterSpecific(error.into()))?
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(
+        attribute
+            .unescape_value()
+            .map_err(|error| importer::Error::ImporterSpecific(error.into()))?
+            .into_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+
+        let combined = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kanjivg xmlns:kvg='http://kanjivg.tagaini.net'>
+<kanji id="kvg:kanji_04e00">
+<g id="kvg:04e00" kvg:element="一" kvg:radical="general">
+	<path id="kvg:04e00-s1" kvg:type="㇐" d="M11,54.25c3.19,0.62,6.25,0.75,9.73,0.5"/>
+</g>
+</kanji>
+<kanji id="kvg:kanji_04e8c">
+<g id="kvg:04e8c" kvg:element="二">
+	<path id="kvg:04e8c-s1" kvg:type="㇐" d="M22.5,28.5c2.5,0.5,5,0.5,7.5,0.25"/>
+	<path id="kvg:04e8c-s2" kvg:type="㇐" d="M12.75,81.5c3,0.5,6,0.75,9,0.5"/>
+</g>
+</kanji>
+</kanjivg>
+"#;
+        let path = temp_dir.path().join("kanjivg.xml");
+        fs::write(&path, combined).expect("could not write KanjiVG file to temp dir");
+        let store = import_path(
+            &path,
+            StrokeOrderStoreBuilder::new(temp_dir.path().join("combined.cdb")).unwrap(),
+            &Default::default(),
+        )
+        .expect("error while importing KanjiVG file");
+
+        let strokes = store.get('二').unwrap().strokes;
+        assert_eq!(strokes.len(), 2);
+        assert_eq!(strokes[1].stroke_type.as_deref(), Some("㇐"));
+        assert_eq!(strokes[1].start(), Some((12.75, 81.5)));
+        assert!(store.get('三').is_none());
+
+        let svg_dir = temp_dir.path().join("kanji");
+        fs::create_dir(&svg_dir).unwrap();
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="109" height="109" viewBox="0 0 109 109">
+<g id="kvg:StrokePaths_04e00" style="fill:none;stroke:#000000;">
+<g id="kvg:04e00" kvg:element="一">
+	<path id="kvg:04e00-s1" kvg:type="㇐" d="M11,54.25c3.19,0.62,6.25,0.75,9.73,0.5"/>
+</g>
+</g>
+<g id="kvg:StrokeNumbers_04e00" style="font-size:8;fill:#808080">
+	<text transform="matrix(1 0 0 1 4.25 54.13)">1</text>
+</g>
+</svg>
+"#;
+        fs::write(svg_dir.join("04e00.svg"), svg).unwrap();
+        fs::write(
+            svg_dir.join("04e00-Kaisho.svg"),
+            svg.replace("_04e00", "_04e00-Kaisho"),
+        )
+        .unwrap();
+        let store = import_path(
+            &svg_dir,
+            StrokeOrderStoreBuilder::new(temp_dir.path().join("svg.cdb")).unwrap(),
+            &Default::default(),
+        )
+        .expect("error while importing KanjiVG directory");
+
+        assert_eq!(store.get('一').unwrap().strokes.len(), 1);
+        assert_eq!(store.stroke_counts().collect::<Vec<_>>(), vec![('一', 1)]);
+    }
+}
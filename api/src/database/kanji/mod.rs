@@ -0,0 +1,29 @@
+//! Kanji-level data, as opposed to the word-level data of dictionaries.
+
+use std::io::{self, Read};
+
+pub mod kanjivg;
+pub mod radicals;
+pub mod strokes;
+
+/// Reads a whole EDRDG data file, decoding it from EUC-JP unless it is already valid UTF-8.
+///
+/// The original releases are EUC-JP, but UTF-8 conversions are commonly distributed too.
+pub(crate) fn read_edrdg_text<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+
+    match String::from_utf8(data) {
+        Ok(text) => Ok(text),
+        Err(error) => {
+            let (text, _, had_errors) = encoding_rs::EUC_JP.decode(error.as_bytes());
+            if had_errors {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "file is neither UTF-8 nor EUC-JP",
+                ));
+            }
+            Ok(text.into_owned())
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! Radical-based kanji search, from the EDRDG's RADKFILE and KRADFILE, sorted with the stroke counts of KANJIDIC.
+//!
+//! RADKFILE lists the kanji containing each radical, under a `$ <radical> <strokes>` line per radical.
+//! KRADFILE lists the radicals of each kanji, one `<kanji> : <radicals>` line per kanji.
+//! KANJIDIC has a line per kanji, whose first `S<strokes>` field is the stroke count of the kanji.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::database::storage::{self, StoredFile};
+
+use super::read_edrdg_text;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("radical file IO error")]
+    FileIo(#[from] std::io::Error),
+    #[error("malformed line {} in radical file", .0)]
+    MalformedLine(usize),
+}
+
+/// Result of a radical search.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RadicalSearch {
+    /// Kanji containing every searched radical, by stroke count.
+    pub kanji: Vec<char>,
+    /// Radicals which can be added to the search and still match some kanji.
+    pub valid_radicals: BTreeSet<char>,
+}
+
+/// Everything a [`RadicalIndex`] stores in its file.
+#[derive(Debug, bitcode::Encode, bitcode::Decode, Clone, Default)]
+struct RadicalData {
+    /// Stroke count of each radical.
+    radicals: BTreeMap<char, u8>,
+    /// Radicals of each kanji.
+    kanji: HashMap<char, BTreeSet<char>>,
+    /// Stroke count of each kanji.
+    stroke_counts: HashMap<char, u8>,
+}
+
+pub struct RadicalIndexBuilder {
+    data: RadicalData,
+    path: PathBuf,
+}
+
+impl RadicalIndexBuilder {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            data: RadicalData::default(),
+            path: path.into(),
+        }
+    }
+
+    /// Adds the radicals, their stroke counts and the kanji containing them from a RADKFILE.
+    pub fn import_radkfile<R: Read>(&mut self, reader: R) -> Result<(), Error> {
+        let text = read_edrdg_text(reader)?;
+        let mut radical = None;
+
+        for (index, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('$') {
+                let mut fields = header.split_whitespace();
+                let (Some(current), Some(strokes)) = (
+                    fields.next().and_then(single_char),
+                    fields.next().and_then(|strokes| strokes.parse().ok()),
+                ) else {
+                    return Err(Error::MalformedLine(index + 1));
+                };
+
+                self.data.radicals.insert(current, strokes);
+                radical = Some(current);
+            } else {
+                let radical = radical.ok_or(Error::MalformedLine(index + 1))?;
+                for kanji in line.chars().filter(|c| !c.is_whitespace()) {
+                    self.data.kanji.entry(kanji).or_default().insert(radical);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds the radicals of every kanji from a KRADFILE.
+    ///
+    /// Radicals are only searchable once their stroke count is known from a RADKFILE.
+    pub fn import_kradfile<R: Read>(&mut self, reader: R) -> Result<(), Error> {
+        let text = read_edrdg_text(reader)?;
+
+        for (index, line) in text.lines().enumerate() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+
+            let Some((kanji, radicals)) = line.split_once(':') else {
+                return Err(Error::MalformedLine(index + 1));
+            };
+            let kanji = single_char(kanji.trim()).ok_or(Error::MalformedLine(index + 1))?;
+
+            self.data
+                .kanji
+                .entry(kanji)
+                .or_default()
+                .extend(radicals.split_whitespace().filter_map(single_char));
+        }
+
+        Ok(())
+    }
+
+    /// Adds the stroke count of every kanji from a KANJIDIC, which search results are sorted with.
+    pub fn import_kanjidic<R: Read>(&mut self, reader: R) -> Result<(), Error> {
+        let text = read_edrdg_text(reader)?;
+
+        for (index, line) in text.lines().enumerate() {
+            // The header line starts with a full-width `＃`.
+            if line.starts_with(['#', '＃']) || line.trim().is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let kanji = fields
+                .next()
+                .and_then(single_char)
+                .ok_or(Error::MalformedLine(index + 1))?;
+            // Later `S` fields are common miscounts, and the meanings in braces come after every field.
+            let strokes = fields
+                .take_while(|field| !field.starts_with('{'))
+                .find_map(|field| field.strip_prefix('S')?.parse().ok());
+
+            if let Some(strokes) = strokes {
+                self.data.stroke_counts.insert(kanji, strokes);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the index to its file.
+    pub fn build(self) -> Result<RadicalIndex, Error> {
+        let (mut file, tmp_path) = storage::create_temp_file(&self.path)?;
+        file.write_all(&bitcode::encode(&self.data).unwrap())?;
+        drop(file);
+        storage::persist_temp_file(tmp_path, &self.path)?;
+
+        Ok(RadicalIndex {
+            data_pathbuf: (self.data, self.path),
+        })
+    }
+}
+
+/// An index from radicals to the kanji containing them.
+///
+/// Stored in a file which is read whole when loaded, since every search goes through all the kanji.
+#[derive(Serialize, Deserialize)]
+pub struct RadicalIndex {
+    #[serde(
+        serialize_with = "serialize_data",
+        deserialize_with = "deserialize_data"
+    )]
+    data_pathbuf: (RadicalData, PathBuf),
+}
+
+fn serialize_data<S>(
+    data_pathbuf: &(RadicalData, PathBuf),
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    storage::serialize_path(&data_pathbuf.1, serializer)
+}
+
+fn deserialize_data<'de, D>(deserializer: D) -> Result<(RadicalData, PathBuf), D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pathbuf = storage::deserialize_path(deserializer)?;
+    let bytes = std::fs::read(&pathbuf).map_err(de::Error::custom)?;
+    let data = bitcode::decode(&bytes).map_err(de::Error::custom)?;
+
+    Ok((data, pathbuf))
+}
+
+impl StoredFile for RadicalIndex {
+    fn file(&self) -> &Path {
+        self.path()
+    }
+
+    fn serialized_file(serialized: &serde_json::Value) -> Option<PathBuf> {
+        storage::deserialize_path(serialized.get("data_pathbuf")?).ok()
+    }
+}
+
+impl RadicalIndex {
+    pub fn path(&self) -> &Path {
+        &self.data_pathbuf.1
+    }
+
+    fn data(&self) -> &RadicalData {
+        &self.data_pathbuf.0
+    }
+
+    pub fn stroke_count(&self, kanji: char) -> Option<u8> {
+        self.data().stroke_counts.get(&kanji).copied()
+    }
+
+    /// Gets every searchable radical with its stroke count, sorted by stroke count.
+    pub fn radicals(&self) -> Vec<(char, u8)> {
+        let mut radicals = self
+            .data()
+            .radicals
+            .iter()
+            .map(|(radical, strokes)| (*radical, *strokes))
+            .collect::<Vec<(char, u8)>>();
+        radicals.sort_by_key(|(_, strokes)| *strokes);
+        radicals
+    }
+
+    pub fn radicals_of(&self, kanji: char) -> Option<&BTreeSet<char>> {
+        self.data().kanji.get(&kanji)
+    }
+
+    /// Finds the kanji containing every radical in `radicals`.
+    ///
+    /// Kanji are sorted by stroke count, with kanji of unknown stroke count last.
+    /// Searching without any radical matches no kanji, and leaves every radical valid.
+    pub fn search(&self, radicals: &[char]) -> RadicalSearch {
+        let data = self.data();
+        if radicals.is_empty() {
+            return RadicalSearch {
+                kanji: Vec::new(),
+                valid_radicals: data.radicals.keys().copied().collect(),
+            };
+        }
+
+        let mut kanji = data
+            .kanji
+            .iter()
+            .filter(|(_, kanji_radicals)| radicals.iter().all(|r| kanji_radicals.contains(r)))
+            .map(|(kanji, _)| *kanji)
+            .collect::<Vec<char>>();
+        kanji.sort_by_key(|kanji| (self.stroke_count(*kanji).unwrap_or(u8::MAX), *kanji));
+
+        let valid_radicals = kanji
+            .iter()
+            .flat_map(|kanji| &data.kanji[kanji])
+            .filter(|radical| data.radicals.contains_key(radical) && !radicals.contains(radical))
+            .copied()
+            .collect();
+
+        RadicalSearch {
+            kanji,
+            valid_radicals,
+        }
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let radkfile = "# comment
+$ 一 1
+二三亜
+$ 口 3 3850
+亜品
+$ ｜ 1
+中亜
+";
+        let kradfile = "# comment
+中 : ｜ 口
+品 : 口
+";
+        let kanjidic = "＃ KANJIDIC JIS X 0208 Kanji File/
+亜 3021 U4e9c N43 B1 C7 G8 S7 XJ05033 F1509 ア つ.ぐ {Asia} {rank next}
+中 4366 U4e2d N33 B2 G1 S4 S5 F10 チュウ なか {in} {inside}
+品 494A U54c1 N916 B30 G3 S9 F201 ヒン しな {goods} {S10}
+";
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = temp_dir.path().join("radicals");
+        let mut builder = RadicalIndexBuilder::new(&path);
+        builder.import_radkfile(radkfile.as_bytes()).unwrap();
+        builder.import_kradfile(kradfile.as_bytes()).unwrap();
+        builder.import_kanjidic(kanjidic.as_bytes()).unwrap();
+        let index = builder.build().unwrap();
+        assert_eq!(index.stroke_count('中'), Some(4));
+        assert_eq!(index.stroke_count('品'), Some(9));
+
+        let (euc_jp, _, _) = encoding_rs::EUC_JP.encode(radkfile);
+        let mut from_euc_jp = RadicalIndexBuilder::new(temp_dir.path().join("euc-jp"));
+        from_euc_jp.import_radkfile(&*euc_jp).unwrap();
+        let from_euc_jp = from_euc_jp.build().unwrap();
+        assert_eq!(from_euc_jp.radicals_of('亜'), index.radicals_of('亜'));
+
+        let result = index.search(&['口']);
+        assert_eq!(result.kanji, vec!['中', '亜', '品']);
+        assert_eq!(result.valid_radicals, BTreeSet::from(['一', '｜']));
+
+        let result = index.search(&['口', '一']);
+        assert_eq!(result.kanji, vec!['亜']);
+        assert_eq!(result.valid_radicals, BTreeSet::from(['｜']));
+
+        assert_eq!(index.radicals(), vec![('一', 1), ('｜', 1), ('口', 3)]);
+        assert!(matches!(
+            RadicalIndexBuilder::new(&path).import_kradfile("中 ｜ 口".as_bytes()),
+            Err(Error::MalformedLine(1))
+        ));
+
+        // Only the path is serialized, and the index is read back from its file.
+        let serialized = serde_json::to_string(&index).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&serialized).unwrap(),
+            serde_json::json!({ "data_pathbuf": path })
+        );
+        let deserialized = serde_json::from_str::<RadicalIndex>(&serialized).unwrap();
+        assert_eq!(deserialized.search(&['口']), index.search(&['口']));
+    }
+}
@@ -0,0 +1,142 @@
+//! Stroke order of kanji, as ordered SVG stroke paths.
+
+use std::path::{Path, PathBuf};
+
+use cdb::{CDBMake, CDB};
+use serde::{Deserialize, Serialize};
+use tempfile::TempPath;
+use thiserror::Error;
+
+use crate::database::{
+    dictionary::cdb::{deserialize_cdb, serialize_cdb},
+    storage::{self, StoredFile},
+};
+
+/// A single stroke of a kanji.
+#[derive(Debug, bitcode::Encode, bitcode::Decode, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Stroke {
+    /// SVG path data, in a 109×109 coordinate space.
+    pub path: String,
+    /// Stroke type as a CJK stroke character (like `㇐` or `㇔`), optionally followed by variants (`㇔/㇀`).
+    pub stroke_type: Option<String>,
+}
+
+impl Stroke {
+    /// Gets the starting point of the stroke, where its number is usually drawn.
+    pub fn start(&self) -> Option<(f32, f32)> {
+        let coordinates = self.path.trim_start().strip_prefix(['M', 'm'])?;
+        let mut numbers = coordinates
+            .split(|c: char| c == ',' || c.is_whitespace() || c.is_ascii_alphabetic())
+            .filter(|number| !number.is_empty());
+
+        Some((numbers.next()?.parse().ok()?, numbers.next()?.parse().ok()?))
+    }
+}
+
+/// The strokes of a kanji, in writing order.
+#[derive(Debug, bitcode::Encode, bitcode::Decode, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct StrokeOrder {
+    pub strokes: Vec<Stroke>,
+}
+
+impl StrokeOrder {
+    pub fn serialize_fast(&self) -> Vec<u8> {
+        bitcode::encode(self).unwrap()
+    }
+
+    pub fn deserialize_fast(data: &[u8]) -> Self {
+        bitcode::decode(data).unwrap()
+    }
+}
+
+pub struct StrokeOrderStoreBuilder {
+    cdb_make: CDBMake,
+    path: PathBuf,
+    tmp_path: TempPath,
+}
+
+impl StrokeOrderStoreBuilder {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Result<Self, Error> {
+        let path = path.into();
+        let (file, tmp_path) = storage::create_temp_file(&path)?;
+
+        Ok(Self {
+            cdb_make: CDBMake::new(file)?,
+            path,
+            tmp_path,
+        })
+    }
+
+    pub fn add(&mut self, kanji: char, stroke_order: &StrokeOrder) -> Result<(), Error> {
+        self.cdb_make.add(
+            kanji.encode_utf8(&mut [0; 4]).as_bytes(),
+            &stroke_order.serialize_fast(),
+        )?;
+        Ok(())
+    }
+
+    pub fn build(self) -> Result<StrokeOrderStore, Error> {
+        self.cdb_make.finish()?;
+        storage::persist_temp_file(self.tmp_path, &self.path)?;
+        Ok(StrokeOrderStore {
+            cdb_pathbuf: (CDB::open(&self.path)?, self.path),
+        })
+    }
+
+    /// Discards everything added so far, removing the partially written store.
+    pub fn discard(self) -> Result<(), Error> {
+        drop(self.cdb_make);
+        self.tmp_path.close()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("CDB file io error")]
+    CDBFileIo(#[from] std::io::Error),
+}
+
+/// A store of kanji stroke orders, looked up by kanji.
+#[derive(Serialize, Deserialize)]
+pub struct StrokeOrderStore {
+    #[serde(serialize_with = "serialize_cdb", deserialize_with = "deserialize_cdb")]
+    cdb_pathbuf: (CDB, PathBuf),
+}
+
+impl StoredFile for StrokeOrderStore {
+    fn file(&self) -> &Path {
+        self.path()
+    }
+
+    fn serialized_file(serialized: &serde_json::Value) -> Option<PathBuf> {
+        storage::deserialize_path(serialized.get("cdb_pathbuf")?).ok()
+    }
+}
+
+impl StrokeOrderStore {
+    pub fn path(&self) -> &Path {
+        &self.cdb_pathbuf.1
+    }
+
+    pub fn get(&self, kanji: char) -> Option<StrokeOrder> {
+        self.cdb_pathbuf
+            .0
+            .get(kanji.encode_utf8(&mut [0; 4]).as_bytes())
+            .and_then(Result::ok)
+            .map(|v| StrokeOrder::deserialize_fast(&v))
+    }
+
+    /// Gets the stroke count of every kanji in the store.
+    pub fn stroke_counts(&self) -> impl Iterator<Item = (char, u8)> + '_ {
+        self.cdb_pathbuf
+            .0
+            .iter()
+            .filter_map(Result::ok)
+            .filter_map(|(key, value)| {
+                let kanji = std::str::from_utf8(&key).ok()?.chars().next()?;
+                let strokes = StrokeOrder::deserialize_fast(&value).strokes.len();
+                Some((kanji, strokes.try_into().ok()?))
+            })
+    }
+}
@@ -1,47 +1,499 @@
-use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
 
-use self::dictionary::{Dictionary, DictionaryEntry};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
 
+use self::{
+    dictionary::{
+        any::AnyDictionary,
+        changes::{self, DictionaryChanges, UpdateError},
+        user::{self, UserDictionary},
+        Dictionary, DictionaryEntry,
+    },
+    examples::{ExampleSentence, ExampleStore},
+    kanji::{
+        radicals::{RadicalIndex, RadicalSearch},
+        strokes::{StrokeOrder, StrokeOrderStore},
+    },
+    storage::{IntegrityError, StoredDictionary, StoredFile},
+};
+
+pub mod bundle;
 pub mod dictionary;
+pub mod examples;
+pub mod kanji;
+pub mod storage;
+
+/// The dictionaries of a [`Database`] at one point in time.
+pub type DictionarySnapshot<D> = Arc<Vec<DictionarySlot<D>>>;
+
+/// Entries along with the key they are stored under.
+pub type KeyedEntries = Vec<(String, DictionaryEntry)>;
+
+/// A dictionary of a [`Database`], which may have failed to load.
+pub enum DictionarySlot<D> {
+    Available(Arc<D>),
+    Unavailable(Arc<UnavailableDictionary>),
+}
+
+impl<D> DictionarySlot<D> {
+    pub fn available(&self) -> Option<&Arc<D>> {
+        match self {
+            Self::Available(dictionary) => Some(dictionary),
+            Self::Unavailable(_) => None,
+        }
+    }
+
+    /// Whether both slots hold the very same dictionary.
+    fn is_same(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Available(a), Self::Available(b)) => Arc::ptr_eq(a, b),
+            (Self::Unavailable(a), Self::Unavailable(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl<D: Serialize> Serialize for DictionarySlot<D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Available(dictionary) => dictionary.serialize(serializer),
+            Self::Unavailable(dictionary) => dictionary.serialized.serialize(serializer),
+        }
+    }
+}
 
+impl<D> Clone for DictionarySlot<D> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Available(dictionary) => Self::Available(dictionary.clone()),
+            Self::Unavailable(dictionary) => Self::Unavailable(dictionary.clone()),
+        }
+    }
+}
+
+/// A dictionary which could not be loaded, such as one whose files are missing or damaged.
+///
+/// It is kept as it was serialized, so that it is loaded again next time if its files are restored,
+/// and so that the user can be told which dictionary to import again.
+#[derive(Debug)]
+pub struct UnavailableDictionary {
+    serialized: serde_json::Value,
+    error: String,
+}
+
+impl UnavailableDictionary {
+    /// Name to show for the dictionary, if it can be found in its serialized form.
+    pub fn display_name(&self) -> Option<&str> {
+        let metadata = self.serialized.get("metadata")?;
+        metadata
+            .get("display_name")
+            .and_then(serde_json::Value::as_str)
+            .or_else(|| metadata.get("name")?.as_str())
+    }
+
+    /// Why the dictionary could not be loaded.
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+}
+
+/// Data of a [`Database`] besides its dictionaries, which may have failed to load like a [`DictionarySlot`].
+pub enum StoreSlot<T> {
+    Available(T),
+    Unavailable(UnavailableStore),
+}
+
+impl<T> StoreSlot<T> {
+    pub fn available(&self) -> Option<&T> {
+        match self {
+            Self::Available(store) => Some(store),
+            Self::Unavailable(_) => None,
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for StoreSlot<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Available(store) => store.serialize(serializer),
+            Self::Unavailable(store) => store.serialized.serialize(serializer),
+        }
+    }
+}
+
+/// A store which could not be loaded, kept as it was serialized like an [`UnavailableDictionary`].
+#[derive(Debug)]
+pub struct UnavailableStore {
+    serialized: serde_json::Value,
+    error: String,
+}
+
+impl UnavailableStore {
+    /// Why the store could not be loaded.
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+}
+
+/// Dictionaries and other data used for lookups.
+///
+/// The dictionaries are kept as an immutable [`DictionarySnapshot`] which is swapped atomically when they change,
+/// so lookups never wait on an import or update, and keep using the dictionaries they started with.
+/// Example sentences, radicals and stroke orders are swapped the same way when replaced. A database is `Send` and `Sync` whenever its dictionaries are.
 #[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "D: Serialize", deserialize = "D: DeserializeOwned"))]
 pub struct Database<D: Dictionary> {
-    pub dictionaries: Vec<D>,
+    #[serde(
+        serialize_with = "serialize_dictionaries",
+        deserialize_with = "deserialize_dictionaries"
+    )]
+    dictionaries: ArcSwap<Vec<DictionarySlot<D>>>,
+    /// Held while the dictionaries are changed, so that concurrent changes don't overwrite each other.
+    #[serde(skip)]
+    dictionaries_writer: Mutex<()>,
+    #[serde(
+        default,
+        serialize_with = "serialize_store",
+        deserialize_with = "deserialize_store"
+    )]
+    examples: ArcSwapOption<StoreSlot<ExampleStore>>,
+    #[serde(
+        default,
+        serialize_with = "serialize_store",
+        deserialize_with = "deserialize_store"
+    )]
+    radicals: ArcSwapOption<StoreSlot<RadicalIndex>>,
+    #[serde(
+        default,
+        serialize_with = "serialize_store",
+        deserialize_with = "deserialize_store"
+    )]
+    stroke_orders: ArcSwapOption<StoreSlot<StrokeOrderStore>>,
+}
+
+fn serialize_dictionaries<D, S>(
+    dictionaries: &ArcSwap<Vec<DictionarySlot<D>>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    D: Serialize,
+    S: Serializer,
+{
+    serializer.collect_seq(dictionaries.load().iter())
+}
+
+/// Deserializes every dictionary on its own, so that one which fails to load doesn't prevent loading the others.
+fn deserialize_dictionaries<'de, D, De>(
+    deserializer: De,
+) -> Result<ArcSwap<Vec<DictionarySlot<D>>>, De::Error>
+where
+    D: DeserializeOwned,
+    De: Deserializer<'de>,
+{
+    let dictionaries = Vec::<serde_json::Value>::deserialize(deserializer)?
+        .into_iter()
+        .map(|serialized| match D::deserialize(&serialized) {
+            Ok(dictionary) => DictionarySlot::Available(Arc::new(dictionary)),
+            Err(error) => DictionarySlot::Unavailable(Arc::new(UnavailableDictionary {
+                serialized,
+                error: error.to_string(),
+            })),
+        })
+        .collect();
+    Ok(ArcSwap::from_pointee(dictionaries))
+}
+
+fn serialize_store<T, S>(
+    store: &ArcSwapOption<StoreSlot<T>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    store.load().as_deref().serialize(serializer)
+}
+
+/// Deserializes a store on its own, so that one which fails to load doesn't prevent loading the database.
+fn deserialize_store<'de, T, De>(deserializer: De) -> Result<ArcSwapOption<StoreSlot<T>>, De::Error>
+where
+    T: DeserializeOwned,
+    De: Deserializer<'de>,
+{
+    let slot = Option::<serde_json::Value>::deserialize(deserializer)?.map(|serialized| {
+        let slot = match T::deserialize(&serialized) {
+            Ok(store) => StoreSlot::Available(store),
+            Err(error) => StoreSlot::Unavailable(UnavailableStore {
+                serialized,
+                error: error.to_string(),
+            }),
+        };
+        Arc::new(slot)
+    });
+    Ok(ArcSwapOption::new(slot))
 }
 
 impl<D: Dictionary> Database<D> {
     pub fn new() -> Self {
         Self {
-            dictionaries: Vec::new(),
+            dictionaries: ArcSwap::from_pointee(Vec::new()),
+            dictionaries_writer: Mutex::new(()),
+            examples: ArcSwapOption::empty(),
+            radicals: ArcSwapOption::empty(),
+            stroke_orders: ArcSwapOption::empty(),
         }
     }
 
-    pub fn add_dictionary(&mut self, dictionary: D) {
-        self.dictionaries.push(dictionary);
+    /// Gets the current dictionaries, which stay usable and unchanged however the database changes afterwards.
+    pub fn dictionaries(&self) -> DictionarySnapshot<D> {
+        self.dictionaries.load_full()
+    }
+
+    /// Adds a dictionary after the others, returning its index.
+    pub fn add_dictionary(&self, dictionary: D) -> usize {
+        let _writer = self.dictionaries_writer.lock().unwrap();
+        let mut dictionaries = Vec::clone(&self.dictionaries.load());
+        dictionaries.push(DictionarySlot::Available(Arc::new(dictionary)));
+        let index = dictionaries.len() - 1;
+        self.dictionaries.store(Arc::new(dictionaries));
+        index
     }
 
-    pub fn get(&self, key: &str) -> Vec<(&D, Vec<DictionaryEntry>)> {
-        self.dictionaries.iter().map(|d| (d, d.get(key))).collect()
+    /// Replaces a dictionary with a newer revision of it, reporting which entries were added, removed or changed.
+    ///
+    /// Sources keep entry ids across revisions, so data keyed by them stays valid unless the entry was removed.
+    /// An unavailable dictionary can be replaced by any dictionary, which counts as entirely added.
+    /// The previous dictionary is returned, so that its files can be deleted once no snapshot uses it anymore.
+    pub fn update_dictionary(
+        &self,
+        index: usize,
+        dictionary: D,
+    ) -> Result<(DictionarySlot<D>, DictionaryChanges), UpdateError> {
+        loop {
+            // Comparing the dictionaries reads both entirely, so it's done before taking the writer's lock.
+            let snapshot = self.dictionaries.load_full();
+            let previous = snapshot
+                .get(index)
+                .ok_or(UpdateError::NoSuchDictionary(index))?;
+            let changes = match previous {
+                DictionarySlot::Available(old) => {
+                    changes::check_update(old.get_metadata(), dictionary.get_metadata())?;
+                    DictionaryChanges::between(&**old, &dictionary)
+                }
+                DictionarySlot::Unavailable(_) => DictionaryChanges::added(&dictionary),
+            };
+
+            let _writer = self.dictionaries_writer.lock().unwrap();
+            let mut dictionaries = Vec::clone(&self.dictionaries.load());
+            // Compared again if the dictionary was replaced meanwhile.
+            if !dictionaries
+                .get(index)
+                .is_some_and(|slot| slot.is_same(previous))
+            {
+                continue;
+            }
+
+            let old = std::mem::replace(
+                &mut dictionaries[index],
+                DictionarySlot::Available(Arc::new(dictionary)),
+            );
+            self.dictionaries.store(Arc::new(dictionaries));
+            return Ok((old, changes));
+        }
+    }
+
+    /// Dictionaries which could not be loaded, along with their index.
+    pub fn unavailable_dictionaries(&self) -> Vec<(usize, Arc<UnavailableDictionary>)> {
+        self.dictionaries
+            .load()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| match slot {
+                DictionarySlot::Available(_) => None,
+                DictionarySlot::Unavailable(dictionary) => Some((index, dictionary.clone())),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Vec<(Arc<D>, Vec<DictionaryEntry>)> {
+        self.dictionaries
+            .load()
+            .iter()
+            .filter_map(DictionarySlot::available)
+            .map(|d| (d.clone(), d.get(key)))
+            .collect()
+    }
+
+    /// Gets the entries of every dictionary which have all of `tags`, along with their keys.
+    pub fn get_by_tags(&self, tags: &[&str]) -> Vec<(Arc<D>, KeyedEntries)> {
+        self.dictionaries
+            .load()
+            .iter()
+            .filter_map(DictionarySlot::available)
+            .map(|d| (d.clone(), d.get_by_tags(tags)))
+            .collect()
+    }
+
+    /// Replaces the example sentences. Lookups which already started keep using the previous ones.
+    pub fn set_examples(&self, examples: ExampleStore) {
+        self.examples
+            .store(Some(Arc::new(StoreSlot::Available(examples))));
+    }
+
+    /// Gets up to `limit` example sentences for the headword of a lookup result.
+    pub fn get_examples(&self, key: &str, limit: usize) -> Vec<ExampleSentence> {
+        self.examples
+            .load()
+            .as_ref()
+            .and_then(|slot| slot.available())
+            .map(|examples| examples.get(key, limit))
+            .unwrap_or_default()
+    }
+
+    pub fn set_radicals(&self, radicals: RadicalIndex) {
+        self.radicals
+            .store(Some(Arc::new(StoreSlot::Available(radicals))));
+    }
+
+    /// Finds the kanji containing every radical in `radicals`, see [`RadicalIndex::search`].
+    pub fn search_kanji_by_radicals(&self, radicals: &[char]) -> Option<RadicalSearch> {
+        self.radicals
+            .load()
+            .as_ref()
+            .and_then(|slot| slot.available())
+            .map(|index| index.search(radicals))
+    }
+
+    pub fn set_stroke_orders(&self, stroke_orders: StrokeOrderStore) {
+        self.stroke_orders
+            .store(Some(Arc::new(StoreSlot::Available(stroke_orders))));
+    }
+
+    pub fn get_stroke_order(&self, kanji: char) -> Option<StrokeOrder> {
+        self.stroke_orders.load().as_ref()?.available()?.get(kanji)
+    }
+
+    /// Stores which could not be loaded, by the name to show for them, along with why.
+    pub fn unavailable_stores(&self) -> Vec<(&'static str, String)> {
+        fn error<T>(store: &ArcSwapOption<StoreSlot<T>>) -> Option<String> {
+            match store.load().as_deref()? {
+                StoreSlot::Available(_) => None,
+                StoreSlot::Unavailable(store) => Some(store.error.clone()),
+            }
+        }
+
+        [
+            ("example sentences", error(&self.examples)),
+            ("radicals", error(&self.radicals)),
+            ("stroke orders", error(&self.stroke_orders)),
+        ]
+        .into_iter()
+        .filter_map(|(name, error)| Some((name, error?)))
+        .collect()
+    }
+}
+
+impl Database<AnyDictionary> {
+    /// Edits the user dictionary at `index` with `edit`, returning what it returns.
+    ///
+    /// The edit is made on a copy which then replaces the dictionary, so lookups never see it half done,
+    /// and nothing changes if `edit` fails.
+    pub fn edit_user_dictionary<T>(
+        &self,
+        index: usize,
+        edit: impl FnOnce(&mut UserDictionary) -> Result<T, user::Error>,
+    ) -> Result<T, user::Error> {
+        let _writer = self.dictionaries_writer.lock().unwrap();
+        let mut dictionaries = Vec::clone(&self.dictionaries.load());
+        let mut dictionary = dictionaries
+            .get(index)
+            .and_then(DictionarySlot::available)
+            .and_then(|dictionary| dictionary.as_user())
+            .ok_or(user::Error::NotUserDictionary(index))?
+            .clone();
+
+        let result = edit(&mut dictionary)?;
+        dictionaries[index] = DictionarySlot::Available(Arc::new(dictionary.into()));
+        self.dictionaries.store(Arc::new(dictionaries));
+        Ok(result)
+    }
+}
+
+/// Path of the file of a store, found in its serialized form if it failed to load.
+fn store_file<T: StoredFile>(store: &ArcSwapOption<StoreSlot<T>>) -> Option<PathBuf> {
+    match store.load().as_deref()? {
+        StoreSlot::Available(store) => Some(store.file().to_owned()),
+        StoreSlot::Unavailable(store) => T::serialized_file(&store.serialized),
+    }
+}
+
+impl<D: StoredDictionary> Database<D> {
+    /// Paths of every file the database uses, including those of dictionaries and stores which failed to load.
+    ///
+    /// Paths of those are serialized, so they are only resolved within the [`storage::DictionaryStorage::scope`]
+    /// they were loaded in.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut files = self
+            .dictionaries
+            .load()
+            .iter()
+            .flat_map(|slot| match slot {
+                DictionarySlot::Available(dictionary) => dictionary.files(),
+                DictionarySlot::Unavailable(dictionary) => {
+                    D::serialized_files(&dictionary.serialized)
+                }
+            })
+            .collect::<Vec<_>>();
+        files.extend(store_file(&self.examples));
+        files.extend(store_file(&self.radicals));
+        files.extend(store_file(&self.stroke_orders));
+        files
+    }
+
+    /// Verifies every available dictionary, see [`StoredDictionary::verify`], returning the index of those which failed.
+    pub fn verify_dictionaries(&self) -> Vec<(usize, IntegrityError)> {
+        self.dictionaries
+            .load()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, slot)| Some((index, slot.available()?.verify().err()?)))
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::database::dictionary::hashmap::HashMapDictionary;
+    use crate::database::{
+        dictionary::{
+            cdb::{CDBDictionary, CDBDictionaryBuilder},
+            hashmap::HashMapDictionary,
+        },
+        kanji::radicals::RadicalIndexBuilder,
+    };
+
+    use semver::Version;
 
     use super::{
-        dictionary::{hashmap::HashMapDictionaryBuilder, DictionaryBuilder, DictionaryEntry},
+        dictionary::{
+            hashmap::HashMapDictionaryBuilder, user::UserDictionaryBuilder, DictionaryBuilder,
+            DictionaryEntry, DictionaryMetadata, MutableDictionary,
+        },
         *,
     };
 
     #[test]
     fn basic() {
-        let mut database = Database::new();
+        let database = Database::new();
         let mut dict_builder = HashMapDictionaryBuilder::new();
         let dict_entry = DictionaryEntry {
             readings: vec!["あける".to_owned()],
             gloss: "to open (a door, etc.), to unwrap (e.g. parcel, package), to unlock".to_owned(),
             tags: vec!["P".to_owned(), "v1".to_owned(), "vt".to_owned()],
+            id: None,
         };
         dict_builder.add("test", dict_entry.clone()).unwrap();
         let dict = dict_builder.build(Default::default()).unwrap();
@@ -56,4 +508,188 @@ mod tests {
             &dict_entry
         );
     }
+
+    #[test]
+    fn update() {
+        let entry = |id: &str, gloss: &str| DictionaryEntry {
+            readings: vec![],
+            gloss: gloss.to_owned(),
+            tags: vec![],
+            id: Some(id.to_owned()),
+        };
+        let build = |entries: &[(&str, DictionaryEntry)], version| {
+            let mut dict_builder = HashMapDictionaryBuilder::new();
+            for (key, entry) in entries {
+                dict_builder.add(key, entry.clone()).unwrap();
+            }
+            dict_builder
+                .build(DictionaryMetadata::builder("test").version(version).build())
+                .unwrap()
+        };
+
+        let database = Database::new();
+        database.add_dictionary(build(
+            &[
+                ("開ける", entry("1", "to open")),
+                ("あける", entry("1", "to open")),
+                ("閉める", entry("2", "to close")),
+                ("猫", entry("3", "cat")),
+            ],
+            Version::new(1, 0, 0),
+        ));
+
+        let (_, changes) = database
+            .update_dictionary(
+                0,
+                build(
+                    &[
+                        ("開ける", entry("1", "to open")),
+                        ("あける", entry("1", "to open")),
+                        ("閉める", entry("2", "to shut")),
+                        ("犬", entry("4", "dog")),
+                    ],
+                    Version::new(1, 1, 0),
+                ),
+            )
+            .unwrap();
+        assert_eq!(
+            changes,
+            DictionaryChanges {
+                added: vec!["4".to_owned()],
+                removed: vec!["3".to_owned()],
+                changed: vec!["2".to_owned()],
+            }
+        );
+        assert_eq!(database.get("犬")[0].1, vec![entry("4", "dog")]);
+
+        assert!(matches!(
+            database.update_dictionary(0, build(&[], Version::new(0, 9, 0))),
+            Err(UpdateError::OlderRevision { .. })
+        ));
+    }
+
+    #[test]
+    fn snapshots() {
+        let build = |gloss: &str| {
+            let mut dict_builder = HashMapDictionaryBuilder::new();
+            dict_builder
+                .add(
+                    "test",
+                    DictionaryEntry {
+                        readings: vec![],
+                        gloss: gloss.to_owned(),
+                        tags: vec![],
+                        id: None,
+                    },
+                )
+                .unwrap();
+            dict_builder.build(Default::default()).unwrap()
+        };
+
+        let database = Database::new();
+        database.add_dictionary(build("0"));
+        let snapshot = database.dictionaries();
+
+        std::thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                // Every lookup sees a whole set of dictionaries, whatever is added meanwhile.
+                (0..1000)
+                    .map(|_| database.get("test").len())
+                    .all(|len| (1..=11).contains(&len))
+            });
+            for index in 1..=10 {
+                database.add_dictionary(build(&index.to_string()));
+            }
+            assert!(reader.join().unwrap());
+        });
+
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(database.dictionaries().len(), 11);
+        assert_eq!(database.get("test")[10].1[0].gloss, "10");
+    }
+
+    #[test]
+    fn user_dictionary() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let entry = |gloss: &str| DictionaryEntry {
+            readings: vec![],
+            gloss: gloss.to_owned(),
+            tags: vec![],
+            id: None,
+        };
+        let mut dict_builder = CDBDictionaryBuilder::new(temp_dir.path().join("words")).unwrap();
+        dict_builder.add("勇者", entry("hero")).unwrap();
+
+        let database = Database::<AnyDictionary>::new();
+        database.add_dictionary(dict_builder.build(Default::default()).unwrap().into());
+        let index = database.add_dictionary(
+            UserDictionaryBuilder::new()
+                .build(Default::default())
+                .unwrap()
+                .into(),
+        );
+        let snapshot = database.dictionaries();
+
+        let id = database
+            .edit_user_dictionary(index, |dict| dict.insert("勇者", entry("party leader")))
+            .unwrap();
+        let glosses = |database: &Database<AnyDictionary>| {
+            database
+                .get("勇者")
+                .into_iter()
+                .flat_map(|(_, entries)| entries)
+                .map(|entry| entry.gloss)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(glosses(&database), vec!["hero", "party leader"]);
+        assert!(snapshot[1].available().unwrap().get("勇者").is_empty());
+
+        database
+            .edit_user_dictionary(index, |dict| dict.update(id, "勇者", entry("brave")))
+            .unwrap();
+        assert_eq!(glosses(&database), vec!["hero", "brave"]);
+        assert!(matches!(
+            database.edit_user_dictionary(0, |dict| dict.remove(id)),
+            Err(user::Error::NotUserDictionary(0))
+        ));
+
+        let serialized = serde_json::to_string(&database).unwrap();
+        let database = serde_json::from_str::<Database<AnyDictionary>>(&serialized).unwrap();
+        assert_eq!(glosses(&database), vec!["hero", "brave"]);
+    }
+
+    #[test]
+    fn unavailable() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = temp_dir.path().join("unavailable");
+        let dict = CDBDictionaryBuilder::new(&path)
+            .unwrap()
+            .build(DictionaryMetadata::builder("unavailable").build())
+            .unwrap();
+        let radicals_path = temp_dir.path().join("radicals");
+        let radicals = RadicalIndexBuilder::new(&radicals_path).build().unwrap();
+        let database = Database::new();
+        database.add_dictionary(dict);
+        database.set_radicals(radicals);
+        let serialized = serde_json::to_string(&database).unwrap();
+        drop(database);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&radicals_path).unwrap();
+        let database = serde_json::from_str::<Database<CDBDictionary>>(&serialized).unwrap();
+        let unavailable = database.unavailable_dictionaries();
+        assert_eq!(unavailable.len(), 1);
+        assert_eq!(unavailable[0].0, 0);
+        assert_eq!(unavailable[0].1.display_name(), Some("unavailable"));
+        assert!(database.get("test").is_empty());
+        let unavailable = database.unavailable_stores();
+        assert_eq!(unavailable.len(), 1);
+        assert_eq!(unavailable[0].0, "radicals");
+        assert!(database.search_kanji_by_radicals(&['口']).is_none());
+        // Kept as it was, so that it loads again once its file is back.
+        assert_eq!(
+            serde_json::to_value(&database).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&serialized).unwrap()
+        );
+    }
 }
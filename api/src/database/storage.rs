@@ -0,0 +1,448 @@
+//! The folder dictionary files are stored in, which paths are serialized relative to.
+//!
+//! A configuration directory owns a `dictionaries` folder. While a database is serialized or deserialized inside
+//! [`DictionaryStorage::scope`], paths inside that folder are written relative to it, so the whole configuration
+//! directory can be moved or synced to another machine. Other paths are still written as they are.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    fs::{File, OpenOptions},
+    io,
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use tempfile::TempPath;
+use thiserror::Error;
+
+use super::{dictionary::Dictionary, Database};
+
+/// Name of the folder of a configuration directory which dictionary files are stored in.
+pub const DICTIONARIES_DIR_NAME: &str = "dictionaries";
+
+/// Temporary files made by [`create_temp_file`] in this process, which may belong to builds still running.
+static OWN_TEMP_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+thread_local! {
+    /// Root of the storage set by [`DictionaryStorage::scope`] on this thread.
+    static SCOPE_ROOT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// A dictionary stored in files, which the storage keeps track of.
+pub trait StoredDictionary: Dictionary {
+    /// Paths of every file of the dictionary.
+    fn files(&self) -> Vec<PathBuf>;
+
+    /// Paths of the files of a dictionary which failed to load, found in its serialized form.
+    ///
+    /// Only the paths are read, so that the files which are left are kept and those which are gone are reported.
+    fn serialized_files(serialized: &serde_json::Value) -> Vec<PathBuf>
+    where
+        Self: Sized;
+
+    /// Checks that every file of the dictionary is readable, and still matches the checksum of its metadata if any.
+    ///
+    /// This reads the whole dictionary, so it is left to the user rather than done on every load.
+    fn verify(&self) -> Result<(), IntegrityError> {
+        let found = checksum_files(&self.files())?;
+        match self.get_metadata().checksum() {
+            Some(expected) if expected != found => Err(IntegrityError::ChecksumMismatch {
+                expected: expected.to_owned(),
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Data besides dictionaries which is stored in a single file of the storage.
+pub trait StoredFile {
+    fn file(&self) -> &Path;
+
+    /// Path of the file of a store which failed to load, found in its serialized form.
+    fn serialized_file(serialized: &serde_json::Value) -> Option<PathBuf>
+    where
+        Self: Sized;
+}
+
+#[derive(Debug, Error)]
+pub enum IntegrityError {
+    #[error("dictionary file is unreadable: {}", .0)]
+    FileIo(#[from] io::Error),
+    #[error(
+        "dictionary files changed since they were built: checksum {found} instead of {expected}"
+    )]
+    ChecksumMismatch { expected: String, found: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct DictionaryStorage {
+    root: PathBuf,
+}
+
+/// Files found by [`DictionaryStorage::find_garbage`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StorageGarbage {
+    /// Files in the storage which nothing uses, such as dictionaries which were replaced or leftovers of failed imports.
+    pub orphaned: Vec<PathBuf>,
+    /// Files used by the database which don't exist anymore.
+    pub missing: Vec<PathBuf>,
+}
+
+impl DictionaryStorage {
+    /// Storage in the [`DICTIONARIES_DIR_NAME`] folder of `config_dir`.
+    pub fn new<P: AsRef<Path>>(config_dir: P) -> Self {
+        Self {
+            root: config_dir.as_ref().join(DICTIONARIES_DIR_NAME),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Reserves a new file named like `file_name`, creating the storage folder if needed, and returns its path.
+    ///
+    /// A number is added to the name (`jmdict-1.cdb`) while a file with that name exists. The file is created empty
+    /// so that concurrent imports can't pick the same name, and is meant to be replaced by the dictionary built there.
+    pub fn file_path(&self, file_name: &str) -> io::Result<PathBuf> {
+        std::fs::create_dir_all(&self.root)?;
+        let (stem, extension) = match file_name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+            _ => (file_name, None),
+        };
+
+        let mut path = self.root.join(file_name);
+        let mut suffix = 1;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            path = self.root.join(match extension {
+                Some(extension) => format!("{stem}-{suffix}.{extension}"),
+                None => format!("{stem}-{suffix}"),
+            });
+            suffix += 1;
+        }
+    }
+
+    /// Runs `f` with paths serialized relative to this storage, and relative paths deserialized from it, on this thread.
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        struct Restore(Option<PathBuf>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                SCOPE_ROOT.with(|root| *root.borrow_mut() = self.0.take());
+            }
+        }
+
+        let _restore = Restore(SCOPE_ROOT.with(|root| root.replace(Some(self.root.clone()))));
+        f()
+    }
+
+    /// Finds the files of the storage which `database` doesn't use, and those it uses which are missing.
+    ///
+    /// Temporary files made by this process are skipped, since their builds may still be running, while those left
+    /// by earlier runs are orphaned. Dictionaries which are built but not added to `database` yet are not skipped,
+    /// so callers should keep imports from running meanwhile.
+    pub fn find_garbage<D: StoredDictionary>(
+        &self,
+        database: &Database<D>,
+    ) -> io::Result<StorageGarbage> {
+        // Unavailable dictionaries are only serialized, with paths relative to the storage.
+        let files = self.scope(|| database.files());
+        let mut garbage = StorageGarbage {
+            missing: files
+                .iter()
+                .filter(|path| !path.exists())
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+
+        let files = files.into_iter().collect::<HashSet<_>>();
+        let entries = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(garbage),
+            Err(e) => return Err(e),
+        };
+        let mut own_temp_files = OWN_TEMP_FILES.lock().unwrap();
+        // Those which are gone were persisted or dropped, and their names are never used again.
+        own_temp_files.retain(|path| path.exists());
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() && !own_temp_files.contains(&path) && !files.contains(&path) {
+                garbage.orphaned.push(path);
+            }
+        }
+        garbage.orphaned.sort();
+
+        Ok(garbage)
+    }
+
+    /// Deletes the orphaned files found by [`DictionaryStorage::find_garbage`].
+    pub fn remove_orphaned(&self, garbage: &StorageGarbage) -> io::Result<()> {
+        for path in &garbage.orphaned {
+            // Only ever delete files of the storage, even if given a report from elsewhere.
+            if path.parent() == Some(self.root.as_path()) {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hashes the contents of `paths` in order, as hex encoded SHA-256.
+pub(crate) fn checksum_files(paths: &[PathBuf]) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for path in paths {
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Encodes bytes such as a hash as lowercase hex.
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Creates a temporary file next to `path` to build a file in, uniquely named so that builds for the same path can't clash.
+///
+/// The file is deleted when the returned [`TempPath`] is dropped, unless it's moved into place with [`persist_temp_file`].
+pub(crate) fn create_temp_file(path: &Path) -> io::Result<(File, TempPath)> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut prefix = path.file_name().unwrap_or_default().to_owned();
+    prefix.push(".");
+    let (file, tmp_path) = tempfile::Builder::new()
+        .prefix(&prefix)
+        .suffix(".tmp")
+        .tempfile_in(dir)?
+        .into_parts();
+    OWN_TEMP_FILES
+        .lock()
+        .unwrap()
+        .insert(tmp_path.to_path_buf());
+    Ok((file, tmp_path))
+}
+
+/// Flushes a file built with [`create_temp_file`] to disk and moves it to `path`, replacing any previous file.
+pub(crate) fn persist_temp_file(tmp_path: TempPath, path: &Path) -> io::Result<()> {
+    File::open(&tmp_path)?.sync_all()?;
+    tmp_path.persist(path).map_err(|error| error.error)?;
+    sync_parent_dir(path)
+}
+
+/// Flushes the renames of files next to `path` to disk. Directories can only be synced on Unix.
+pub(crate) fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Serializes a path, relative to the storage if inside of it and in a [`DictionaryStorage::scope`].
+pub(crate) fn serialize_path<S>(path: &Path, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let relative = SCOPE_ROOT.with(|root| {
+        let root = root.borrow();
+        let relative = path.strip_prefix(root.as_ref()?).ok()?;
+        // Joined with `/` whatever the platform, since the configuration may be synced to another one.
+        relative
+            .components()
+            .map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|components| components.join("/"))
+    });
+
+    match relative {
+        Some(relative) => serializer.serialize_str(&relative),
+        None => serializer.serialize_str(
+            path.to_str()
+                .ok_or(ser::Error::custom("path contains invalid UTF-8 characters"))?,
+        ),
+    }
+}
+
+/// Deserializes a path, resolving relative paths from the storage of the current [`DictionaryStorage::scope`].
+pub(crate) fn deserialize_path<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let path = PathBuf::from(String::deserialize(deserializer)?);
+    if path.is_absolute() {
+        return Ok(path);
+    }
+
+    SCOPE_ROOT.with(|root| match &*root.borrow() {
+        Some(root) => Ok(root.join(path)),
+        None => Err(de::Error::custom(format!(
+            "relative path {path:?} outside of a dictionary storage"
+        ))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::dictionary::{
+        cdb::{CDBDictionary, CDBDictionaryBuilder},
+        DictionaryBuilder, DictionaryEntry,
+    };
+
+    use super::*;
+
+    #[test]
+    fn basic() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let storage = DictionaryStorage::new(temp_dir.path());
+
+        let dict = CDBDictionaryBuilder::new(storage.file_path("test.cdb").unwrap())
+            .unwrap()
+            .build(Default::default())
+            .unwrap();
+        let database = Database::new();
+        database.add_dictionary(dict);
+
+        let serialized = storage.scope(|| serde_json::to_string(&database).unwrap());
+        assert!(serialized.contains(r#""test.cdb""#));
+        // Relative paths can't be resolved outside of a storage.
+        let database = serde_json::from_str::<Database<CDBDictionary>>(&serialized).unwrap();
+        assert_eq!(database.unavailable_dictionaries().len(), 1);
+
+        // The whole configuration directory is moved.
+        let moved_dir = tempfile::tempdir().expect("could not create temp dir");
+        let moved_storage = DictionaryStorage::new(moved_dir.path());
+        std::fs::rename(storage.root(), moved_storage.root()).unwrap();
+        let database = moved_storage
+            .scope(|| serde_json::from_str::<Database<CDBDictionary>>(&serialized).unwrap());
+        assert_eq!(
+            database.dictionaries()[0].available().unwrap().path(),
+            moved_storage.root().join("test.cdb")
+        );
+
+        std::fs::write(moved_storage.root().join("old.cdb"), b"").unwrap();
+        std::fs::write(moved_storage.root().join("test.cdb.1"), b"").unwrap();
+        // Left by an earlier run, unlike one of a build still running.
+        std::fs::write(moved_storage.root().join("new.cdb.a1b2c3.tmp"), b"").unwrap();
+        let (_, tmp_path) = create_temp_file(&moved_storage.root().join("new.cdb")).unwrap();
+        let garbage = moved_storage.find_garbage(&database).unwrap();
+        assert_eq!(
+            garbage.orphaned,
+            [
+                moved_storage.root().join("new.cdb.a1b2c3.tmp"),
+                moved_storage.root().join("old.cdb"),
+                moved_storage.root().join("test.cdb.1")
+            ]
+        );
+        assert!(!garbage.orphaned.contains(&tmp_path.to_path_buf()));
+        assert!(garbage.missing.is_empty());
+        moved_storage.remove_orphaned(&garbage).unwrap();
+        assert!(!moved_storage.root().join("old.cdb").exists());
+
+        std::fs::remove_file(moved_storage.root().join("test.cdb")).unwrap();
+        let garbage = moved_storage.find_garbage(&database).unwrap();
+        assert_eq!(garbage.missing, [moved_storage.root().join("test.cdb")]);
+
+        // Reserved names are taken even while their file is still empty.
+        assert_eq!(
+            moved_storage.file_path("new.cdb").unwrap(),
+            moved_storage.root().join("new.cdb")
+        );
+        assert_eq!(
+            moved_storage.file_path("new.cdb").unwrap(),
+            moved_storage.root().join("new-1.cdb")
+        );
+    }
+
+    #[test]
+    fn unavailable() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let storage = DictionaryStorage::new(temp_dir.path());
+
+        let entry = |index: usize| DictionaryEntry {
+            readings: vec![],
+            gloss: format!("gloss {index}"),
+            tags: vec![],
+            id: None,
+        };
+        let mut dict_builder = CDBDictionaryBuilder::new(storage.file_path("sharded.cdb").unwrap())
+            .unwrap()
+            .with_shard_len_limit(16 * 1024);
+        for index in 0..2000 {
+            dict_builder.add(&index.to_string(), entry(index)).unwrap();
+        }
+        let dict = dict_builder.build(Default::default()).unwrap();
+        let shards = dict.shard_paths();
+        assert!(shards.len() > 2);
+        let database = Database::new();
+        database.add_dictionary(dict);
+        let serialized = storage.scope(|| serde_json::to_string(&database).unwrap());
+        drop(database);
+
+        // The files left of a dictionary which fails to load are kept, and the missing one is reported.
+        std::fs::remove_file(&shards[1]).unwrap();
+        let database =
+            storage.scope(|| serde_json::from_str::<Database<CDBDictionary>>(&serialized).unwrap());
+        assert_eq!(database.unavailable_dictionaries().len(), 1);
+        let garbage = storage.find_garbage(&database).unwrap();
+        assert!(garbage.orphaned.is_empty());
+        assert_eq!(garbage.missing, [shards[1].clone()]);
+        storage.remove_orphaned(&garbage).unwrap();
+        assert!(shards[2..].iter().all(|path| path.exists()));
+
+        // Even without the first shard, which tells which the others are.
+        std::fs::remove_file(&shards[0]).unwrap();
+        let garbage = storage.find_garbage(&database).unwrap();
+        assert!(garbage.orphaned.is_empty());
+        assert_eq!(garbage.missing, [shards[0].clone()]);
+    }
+
+    #[test]
+    fn verify() {
+        let temp_dir = tempfile::tempdir().expect("could not create temp dir");
+        let path = temp_dir.path().join("verify");
+        let mut dict_builder = CDBDictionaryBuilder::new(&path).unwrap();
+        dict_builder
+            .add(
+                "test",
+                crate::database::dictionary::DictionaryEntry {
+                    readings: vec![],
+                    gloss: "test".to_owned(),
+                    tags: vec![],
+                    id: None,
+                },
+            )
+            .unwrap();
+        let dict = dict_builder.build(Default::default()).unwrap();
+        assert!(dict.get_metadata().checksum().is_some());
+        dict.verify().unwrap();
+
+        let mut data = std::fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        std::fs::write(&path, data).unwrap();
+        assert!(matches!(
+            dict.verify(),
+            Err(IntegrityError::ChecksumMismatch { .. })
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(dict.verify(), Err(IntegrityError::FileIo(_))));
+    }
+}
@@ -1,14 +1,45 @@
 //! Tauri commands module.
 
-use std::{path::PathBuf, sync::RwLock};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, RwLock},
+};
 
+use api::{
+    analysis::Tokenizer,
+    database::{
+        bundle::{self, BundleError},
+        dictionary::{
+            any::AnyDictionary,
+            cdb::{CDBDictionary, CDBDictionaryBuilder, CDBDictionaryBuilderError},
+            changes::{DictionaryChanges, UpdateError},
+            importer::{
+                self,
+                context::{CancellationToken, ImportContext, ImportProgress, ImportReport},
+                registry::{AnyImporterError, ImporterKind, SOURCE_EXTENSIONS},
+            },
+            user::{self, UserDictionaryBuilder},
+            Dictionary, DictionaryBuilder, DictionaryEntry, DictionaryMetadata, EntryId,
+            MutableDictionary,
+        },
+        storage::StorageGarbage,
+        DictionarySlot,
+    },
+};
+use serde::Serialize;
 use thiserror::Error;
 
 use crate::{
     config::{Config, ConfigFilePath, ConfigFileWriteError, CONFIG_FILE_NAME},
+    query::{EditableEntry, QueryToken},
     state::AppState,
 };
 
+/// zstd level imported dictionaries are compressed with.
+const DICTIONARY_COMPRESSION_LEVEL: i32 = 9;
+
 /// Sets up the app configuration if it's not already set up.
 ///
 /// If `path` is None, the default configuration directory path is used.
@@ -37,6 +68,437 @@ pub fn set_config_dir(path: Option<String>, state: tauri::State<AppState>) -> Re
     Ok(())
 }
 
+/// Imports a dictionary file or archive of any supported format and adds it to the database.
+///
+/// The dictionary is stored next to the configuration file.
+/// Progress is emitted to the calling window as `import_progress` events, see [`ImportProgressEvent`], whose
+/// import id can be given to [`cancel_import`] to stop the import.
+/// With `tolerant`, unknown fields and malformed words are skipped, and listed in the returned report.
+/// `display_name` replaces the name given by the dictionary itself.
+/// Runs off the main thread, since importing can take minutes.
+#[tauri::command(rename_all = "snake_case", async)]
+pub fn import_dictionary(
+    path: String,
+    tolerant: Option<bool>,
+    display_name: Option<String>,
+    window: tauri::Window,
+    state: tauri::State<AppState>,
+) -> Result<ImportReport, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let _storage = state.storage_lock.read().unwrap();
+
+    let source_path = PathBuf::from(path);
+    let file_stem = source_file_stem(&source_path)?;
+    let mut context = ImportContext::new().with_tolerance(tolerant.unwrap_or(false));
+    if let Some(display_name) = display_name {
+        context = context.with_display_name(display_name);
+    }
+    let (dict, report) = import_source(
+        &source_path,
+        &format!("{file_stem}.cdb"),
+        context,
+        window,
+        &state,
+    )?;
+
+    // Lookups keep going meanwhile, since the database swaps in the new dictionaries atomically.
+    let config = config.read().unwrap();
+    config.database.add_dictionary(dict.into());
+    config.write()?;
+
+    Ok(report)
+}
+
+/// Replaces the dictionary at `index` in the database with a newer release of it, imported like in [`import_dictionary`].
+///
+/// Returns the ids of the entries which were added, removed or changed.
+/// The files of the previous dictionary are left to [`clean_dictionary_storage`], since lookups which started
+/// before the update may still be reading them.
+#[tauri::command(rename_all = "snake_case", async)]
+pub fn update_dictionary(
+    index: usize,
+    path: String,
+    window: tauri::Window,
+    state: tauri::State<AppState>,
+) -> Result<DictionaryChanges, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let _storage = state.storage_lock.read().unwrap();
+
+    let source_path = PathBuf::from(path);
+    let file_stem = source_file_stem(&source_path)?;
+    // A name chosen by the user should survive updates.
+    let mut context = ImportContext::new();
+    if let Some(dict) = config
+        .read()
+        .unwrap()
+        .database
+        .dictionaries()
+        .get(index)
+        .and_then(DictionarySlot::available)
+    {
+        if dict.as_user().is_some() {
+            return Err(Error::NotImported(index));
+        }
+        let metadata = dict.get_metadata();
+        if metadata.display_name() != metadata.name() {
+            context = context.with_display_name(metadata.display_name());
+        }
+    }
+    let (dict, _) = import_source(
+        &source_path,
+        &format!("{file_stem}.cdb"),
+        context,
+        window,
+        &state,
+    )?;
+    let new_shard_paths = dict.shard_paths();
+
+    let config = config.read().unwrap();
+    let (_, changes) = match config.database.update_dictionary(index, dict.into()) {
+        Ok(update) => update,
+        Err(error) => {
+            for path in new_shard_paths {
+                let _ = std::fs::remove_file(path);
+            }
+            return Err(error.into());
+        }
+    };
+    config.write()?;
+
+    Ok(changes)
+}
+
+/// A dictionary which can't be used anymore and should be imported again.
+#[derive(Debug, Serialize)]
+pub struct BrokenDictionary {
+    pub index: usize,
+    pub name: Option<String>,
+    pub error: String,
+}
+
+/// Lists the dictionaries which could not be loaded.
+pub fn unavailable_dictionaries(config: &Config) -> Vec<BrokenDictionary> {
+    config
+        .database
+        .unavailable_dictionaries()
+        .into_iter()
+        .map(|(index, dict)| BrokenDictionary {
+            index,
+            name: dict.display_name().map(str::to_owned),
+            error: dict.error().to_owned(),
+        })
+        .collect()
+}
+
+/// Checks the files of every dictionary, returning those which could not be loaded or are damaged.
+///
+/// Runs off the main thread, since every dictionary file is read whole.
+#[tauri::command(async)]
+pub fn verify_dictionaries(state: tauri::State<AppState>) -> Result<Vec<BrokenDictionary>, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+    let dictionaries = config.database.dictionaries();
+
+    let mut broken = unavailable_dictionaries(&config);
+    broken.extend(
+        config
+            .database
+            .verify_dictionaries()
+            .into_iter()
+            .map(|(index, error)| BrokenDictionary {
+                index,
+                name: dictionaries[index]
+                    .available()
+                    .map(|dict| dict.get_metadata().display_name().to_owned()),
+                error: error.to_string(),
+            }),
+    );
+    broken.sort_by_key(|dict| dict.index);
+
+    Ok(broken)
+}
+
+/// Deletes the files of the dictionary storage which no dictionary uses anymore.
+///
+/// Returns the deleted files, along with the files of dictionaries which are missing and need to be imported again.
+/// Fails while dictionaries are being imported or installed, since their files aren't in the database yet.
+#[tauri::command]
+pub fn clean_dictionary_storage(state: tauri::State<AppState>) -> Result<StorageGarbage, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let _storage = state
+        .storage_lock
+        .try_write()
+        .map_err(|_| Error::StorageBusy)?;
+    let config = config.read().unwrap();
+    let storage = config.storage();
+
+    let garbage = storage
+        .find_garbage(&config.database)
+        .map_err(Error::DictionaryIo)?;
+    storage
+        .remove_orphaned(&garbage)
+        .map_err(Error::DictionaryIo)?;
+
+    Ok(garbage)
+}
+
+/// Writes every dictionary of the database into a single bundle file at `path`, which can be installed elsewhere.
+///
+/// Dictionaries which could not be loaded are left out.
+#[tauri::command(async)]
+pub fn export_bundle(path: String, state: tauri::State<AppState>) -> Result<(), Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    let file = File::create(path).map_err(Error::DictionaryIo)?;
+    bundle::export_bundle(&config.database, BufWriter::new(file))?;
+
+    Ok(())
+}
+
+/// Installs the dictionaries of a bundle file made by [`export_bundle`] into the dictionary storage.
+///
+/// Returns the display names of the installed dictionaries.
+#[tauri::command(async)]
+pub fn install_bundle(path: String, state: tauri::State<AppState>) -> Result<Vec<String>, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let _storage = state.storage_lock.read().unwrap();
+    let config = config.read().unwrap();
+
+    let file = File::open(path).map_err(Error::DictionaryIo)?;
+    let manifest =
+        bundle::install_bundle(BufReader::new(file), &config.storage(), &config.database)?;
+    config.write()?;
+
+    Ok(manifest
+        .dictionaries()
+        .map(|metadata| metadata.display_name().to_owned())
+        .collect())
+}
+
+/// Splits a sentence into words found in the dictionaries, along with their entries.
+///
+/// Unknown words and punctuation are kept as tokens without entries, so the tokens cover the whole sentence.
+#[tauri::command(async)]
+pub fn analyze_sentence(
+    sentence: String,
+    state: tauri::State<AppState>,
+) -> Result<Vec<QueryToken>, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    Ok(Tokenizer::new(&config.database)
+        .tokenize(&sentence)
+        .into_iter()
+        .map(QueryToken::from_token)
+        .collect())
+}
+
+/// Adds an empty user dictionary named `name` to the database, returning its index.
+///
+/// Its entries are made with [`add_user_entry`], and found by lookups along with those of imported dictionaries.
+#[tauri::command(rename_all = "snake_case")]
+pub fn create_user_dictionary(name: String, state: tauri::State<AppState>) -> Result<usize, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    let dict = UserDictionaryBuilder::new().build(DictionaryMetadata::builder(name).build())?;
+    let index = config.database.add_dictionary(AnyDictionary::User(dict));
+    config.write()?;
+
+    Ok(index)
+}
+
+/// Adds an entry under `word` to the user dictionary at `index`, returning the id to edit it with.
+#[tauri::command(rename_all = "snake_case")]
+pub fn add_user_entry(
+    index: usize,
+    word: String,
+    entry: DictionaryEntry,
+    state: tauri::State<AppState>,
+) -> Result<EntryId, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    let id = config
+        .database
+        .edit_user_dictionary(index, |dict| dict.insert(&word, entry))?;
+    config.write()?;
+
+    Ok(id)
+}
+
+/// Replaces the entry `id` of the user dictionary at `index`, moving it under `word`.
+#[tauri::command(rename_all = "snake_case")]
+pub fn update_user_entry(
+    index: usize,
+    id: EntryId,
+    word: String,
+    entry: DictionaryEntry,
+    state: tauri::State<AppState>,
+) -> Result<(), Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    config
+        .database
+        .edit_user_dictionary(index, |dict| dict.update(id, &word, entry))?;
+    config.write()?;
+
+    Ok(())
+}
+
+/// Removes the entry `id` from the user dictionary at `index`.
+#[tauri::command(rename_all = "snake_case")]
+pub fn remove_user_entry(
+    index: usize,
+    id: EntryId,
+    state: tauri::State<AppState>,
+) -> Result<(), Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    config
+        .database
+        .edit_user_dictionary(index, |dict| dict.remove(id))?;
+    config.write()?;
+
+    Ok(())
+}
+
+/// Gets the entries of the user dictionary at `index` along with their ids, only those under `word` if given.
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_user_entries(
+    index: usize,
+    word: Option<String>,
+    state: tauri::State<AppState>,
+) -> Result<Vec<EditableEntry>, Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let config = config.read().unwrap();
+
+    let dictionaries = config.database.dictionaries();
+    let dict = dictionaries
+        .get(index)
+        .and_then(DictionarySlot::available)
+        .and_then(|dict| dict.as_user())
+        .ok_or(user::Error::NotUserDictionary(index))?;
+
+    Ok(match word {
+        Some(word) => dict
+            .get_with_ids(&word)
+            .into_iter()
+            .map(|(id, entry)| EditableEntry::new(id, word.clone(), entry.clone()))
+            .collect(),
+        None => dict
+            .iter_with_ids()
+            .map(|(id, user_entry)| {
+                EditableEntry::new(id, user_entry.key.clone(), user_entry.entry.clone())
+            })
+            .collect(),
+    })
+}
+
+/// Gets the name of a source file without its format, compression and archive extensions, which names the
+/// dictionary file made from it. The storage adds a number to the name if it's already taken.
+fn source_file_stem(source_path: &Path) -> Result<&str, Error> {
+    let mut stem = source_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(Error::InvalidDictionaryPath)?;
+    // Names often contain a version like `jmdict-eng-3.5.0.json.zip`, so only known extensions are removed.
+    while let Some((rest, extension)) = stem.rsplit_once('.') {
+        let extension = extension.to_ascii_lowercase();
+        let known = SOURCE_EXTENSIONS.contains(&extension.as_str())
+            || ImporterKind::ALL
+                .iter()
+                .any(|kind| kind.extensions().contains(&extension.as_str()));
+        if rest.is_empty() || !known {
+            break;
+        }
+        stem = rest;
+    }
+    Ok(stem)
+}
+
+/// Imports a source into a dictionary file in the dictionary storage, reporting progress to `window`.
+fn import_source(
+    source_path: &Path,
+    dict_file_name: &str,
+    context: ImportContext,
+    window: tauri::Window,
+    state: &AppState,
+) -> Result<(CDBDictionary, ImportReport), Error> {
+    let config = state.config.get().ok_or(Error::ConfigNotSet)?;
+    let dict_path = config
+        .read()
+        .unwrap()
+        .storage()
+        .file_path(dict_file_name)
+        .map_err(Error::DictionaryIo)?;
+
+    let dict_builder = CDBDictionaryBuilder::new(
+        dict_path
+            .to_str()
+            .ok_or(Error::InvalidDictionaryPath)?
+            .to_owned(),
+    )
+    .map_err(Error::DictionaryIo)?
+    // Users often keep many dictionaries on small disks, and decompressing entries is cheap next to a lookup.
+    .with_compression(DICTIONARY_COMPRESSION_LEVEL);
+
+    let import_id = state.next_import_id.fetch_add(1, Ordering::Relaxed);
+    let cancellation = CancellationToken::new();
+    state
+        .import_cancellations
+        .lock()
+        .unwrap()
+        .insert(import_id, cancellation.clone());
+    // The window may have been closed in the meantime, which doesn't affect the import.
+    let emit_progress = move |progress: ImportProgress| {
+        let _ = window.emit(
+            "import_progress",
+            ImportProgressEvent {
+                import_id,
+                progress,
+            },
+        );
+    };
+    // Reported right away, so that the import can be cancelled before its first progress report.
+    emit_progress(context.progress());
+    let context = context
+        .with_progress(emit_progress)
+        .with_cancellation(cancellation);
+    let result = importer::registry::import_path(source_path, dict_builder, &context);
+    state
+        .import_cancellations
+        .lock()
+        .unwrap()
+        .remove(&import_id);
+    if result.is_err() {
+        // The file reserved for the dictionary is still empty.
+        let _ = std::fs::remove_file(&dict_path);
+    }
+
+    Ok((result?, context.report()))
+}
+
+/// Payload of the `import_progress` events.
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportProgressEvent {
+    /// Identifies the import among those running concurrently, see [`cancel_import`].
+    pub import_id: u64,
+    #[serde(flatten)]
+    pub progress: ImportProgress,
+}
+
+/// Cancels the dictionary import with the id of its `import_progress` events, if it's still running.
+#[tauri::command(rename_all = "snake_case")]
+pub fn cancel_import(import_id: u64, state: tauri::State<AppState>) {
+    if let Some(cancellation) = state.import_cancellations.lock().unwrap().get(&import_id) {
+        cancellation.cancel();
+    }
+}
+
 /// An error that gets sent back to the frontend.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -46,6 +508,24 @@ pub enum Error {
     ConfigDirIo(#[source] std::io::Error),
     #[error("config dir path is not a directory")]
     ConfigDirNotADir,
+    #[error("app configuration is not set up yet")]
+    ConfigNotSet,
+    #[error("dictionary path is not valid UTF-8")]
+    InvalidDictionaryPath,
+    #[error("dictionary IO error: {}", .0)]
+    DictionaryIo(#[source] std::io::Error),
+    #[error("dictionaries are being imported, try again once they are done")]
+    StorageBusy,
+    #[error("dictionary import error: {}", .0)]
+    Import(#[from] importer::Error<AnyImporterError, CDBDictionaryBuilderError>),
+    #[error("dictionary update error: {}", .0)]
+    Update(#[from] UpdateError),
+    #[error("dictionary {} was not imported from a source, so it can't be updated", .0)]
+    NotImported(usize),
+    #[error("user dictionary error: {}", .0)]
+    UserDictionary(#[from] user::Error),
+    #[error("dictionary bundle error: {}", .0)]
+    Bundle(#[from] BundleError),
 }
 
 impl serde::Serialize for Error {
@@ -1,8 +1,13 @@
 //! Configuration and settings module.
 
-use std::path::{Path, PathBuf};
-
-use api::database::{dictionary::cdb::CDBDictionary, Database};
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use api::database::{dictionary::any::AnyDictionary, storage::DictionaryStorage, Database};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sob::Sob;
@@ -29,6 +34,9 @@ pub static DEFAULT_CONFIG_FILE_PATH: Lazy<String> = Lazy::new(|| {
 
 pub const CONFIG_FILE_NAME: &str = "config.json";
 
+/// Held while configuration files are written, since commands running concurrently may all write them.
+static CONFIG_WRITER: Mutex<()> = Mutex::new(());
+
 /// Configuration data.
 ///
 /// Represents data being stored in the configuration directory, such as settings and dictionaries.
@@ -36,7 +44,7 @@ pub const CONFIG_FILE_NAME: &str = "config.json";
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub settings: Settings,
-    pub database: Database<CDBDictionary>,
+    pub database: Database<AnyDictionary>,
     // This shouldn't be serialized because if a [`Config`] is being deserialized, we obviously know where the file is.
     // A [`Config`] will only ever be deserialized from [`Config::read()`].
     #[serde(skip)]
@@ -69,25 +77,31 @@ impl Config {
     /// Reads configuration from the appropriate file.
     pub fn read() -> Result<Self, ConfigFileReadError> {
         let config_file: ConfigFile =
-            serde_json::from_slice(&std::fs::read(ConfigFilePath::Default.as_path())?)
-                .map_err(ConfigFileReadError::Deserialize)?;
+            storage_of(ConfigFilePath::Default.as_path()).scope(|| {
+                serde_json::from_slice(&std::fs::read(ConfigFilePath::Default.as_path())?)
+                    .map_err(ConfigFileReadError::Deserialize)
+            })?;
 
-        let Sob::Owned(config) = (match config_file {
-            ConfigFile::Config(config) => config,
+        let (config, path) = match config_file {
+            ConfigFile::Config(config) => (config, ConfigFilePath::Default),
             ConfigFile::Redirect(actual_path) => {
-                let actual_config_file: ConfigFile =
+                let actual_config_file: ConfigFile = storage_of(&actual_path).scope(|| {
                     serde_json::from_slice(&std::fs::read(actual_path.as_path())?)
-                        .map_err(ConfigFileReadError::Deserialize)?;
+                        .map_err(ConfigFileReadError::Deserialize)
+                })?;
 
                 match actual_config_file {
-                    ConfigFile::Config(config) => config,
+                    ConfigFile::Config(config) => (config, ConfigFilePath::Custom(actual_path)),
                     ConfigFile::Redirect(_) => return Err(ConfigFileReadError::TooManyRedirects),
                 }
             }
-        }) else {
+        };
+        let Sob::Owned(mut config) = config else {
             // A deserialized `Sob` will always be `Sob::Owned`.
             unreachable!();
         };
+        // The path isn't serialized, and dictionary paths are relative to it.
+        config.path = path;
 
         Ok(config)
     }
@@ -97,23 +111,32 @@ impl Config {
         &self.path
     }
 
+    /// Gets the storage of dictionary files, in the configuration directory.
+    pub fn storage(&self) -> DictionaryStorage {
+        storage_of(self.path.as_path())
+    }
+
     /// Writes the configuration file, overwriting any existing file.
     pub fn write(&self) -> Result<(), ConfigFileWriteError> {
+        let _writer = CONFIG_WRITER.lock().unwrap();
         let default_config_dir = Path::new(DEFAULT_CONFIG_DIRECTORY_PATH.as_str());
         if !default_config_dir.exists() {
             std::fs::create_dir(default_config_dir).map_err(ConfigFileWriteError::Io)?;
         }
 
         let config_file = ConfigFile::Config(Sob::Borrowed(&self));
-        let data = serde_json::to_vec(&config_file).map_err(ConfigFileWriteError::Serialize)?;
-        std::fs::write(self.path.as_path(), data).map_err(ConfigFileWriteError::Io)?;
+        let data = self
+            .storage()
+            .scope(|| serde_json::to_vec(&config_file))
+            .map_err(ConfigFileWriteError::Serialize)?;
+        replace_file(self.path.as_path(), &data).map_err(ConfigFileWriteError::Io)?;
 
         // If using a custom path, keep track of it by writing a redirect at the default path.
         if let ConfigFilePath::Custom(ref actual_path) = self.path {
             let redirect_config_file = ConfigFile::Redirect(actual_path.clone());
             let redirect_data = serde_json::to_vec(&redirect_config_file)
                 .map_err(ConfigFileWriteError::Serialize)?;
-            std::fs::write(ConfigFilePath::Default.as_path(), redirect_data)
+            replace_file(ConfigFilePath::Default.as_path(), &redirect_data)
                 .map_err(ConfigFileWriteError::Io)?;
         }
 
@@ -121,6 +144,25 @@ impl Config {
     }
 }
 
+/// Writes `data` to a file next to `path` and moves it over `path`, so that a crash never leaves it half written.
+///
+/// Must be called with [`CONFIG_WRITER`] held, since the temporary file's name is always the same.
+fn replace_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+    std::fs::rename(&tmp_path, path)
+}
+
+/// Gets the storage of the configuration directory containing the config file at `config_file_path`.
+fn storage_of(config_file_path: &Path) -> DictionaryStorage {
+    DictionaryStorage::new(config_file_path.parent().unwrap_or(Path::new("")))
+}
+
 /// A path to the actual config file.
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub enum ConfigFilePath {
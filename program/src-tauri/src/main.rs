@@ -1,7 +1,10 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::RwLock;
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicU64, Mutex, RwLock},
+};
 
 use once_cell::sync::OnceCell;
 use program::{
@@ -24,10 +27,42 @@ fn main() {
 
     let app_state = AppState {
         config: OnceCell::new(),
+        storage_lock: RwLock::new(()),
+        import_cancellations: Mutex::new(HashMap::new()),
+        next_import_id: AtomicU64::new(0),
     };
 
     if Config::exists() {
         let config = Config::read().expect("could not read config file");
+        // Broken dictionaries don't prevent using the others, but the user should know to import them again.
+        let unavailable = unavailable_dictionaries(&config)
+            .into_iter()
+            .map(|dict| {
+                let name = dict.name.unwrap_or_else(|| "unknown dictionary".to_owned());
+                (name, dict.error)
+            })
+            .chain(
+                config
+                    .database
+                    .unavailable_stores()
+                    .into_iter()
+                    .map(|(name, error)| (name.to_owned(), error)),
+            )
+            .collect::<Vec<_>>();
+        if !unavailable.is_empty() {
+            tauri::api::dialog::blocking::message(
+                None::<&tauri::Window>,
+                "Yomisama - Unavailable dictionaries",
+                format!(
+                    "These dictionaries could not be loaded and need to be imported again:\n{}",
+                    unavailable
+                        .iter()
+                        .map(|(name, error)| format!("- {name}: {error}"))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                ),
+            );
+        }
         app_state.config.get_or_init(|| RwLock::new(config));
     }
 
@@ -37,6 +72,19 @@ fn main() {
         .manage(app_state)
         .invoke_handler(tauri::generate_handler![
             set_config_dir,
+            import_dictionary,
+            update_dictionary,
+            clean_dictionary_storage,
+            verify_dictionaries,
+            export_bundle,
+            install_bundle,
+            analyze_sentence,
+            cancel_import,
+            create_user_dictionary,
+            add_user_entry,
+            update_user_entry,
+            remove_user_entry,
+            get_user_entries,
             program::windows::window_loaded,
             program::windows::window_unloading
         ])
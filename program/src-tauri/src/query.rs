@@ -1,6 +1,9 @@
 //! Database querying module.
 
-use api::database::dictionary::DictionaryEntry;
+use api::{
+    analysis::Token,
+    database::dictionary::{DictionaryEntry, EntryId},
+};
 use serde::{Deserialize, Serialize};
 
 /// An entry for a lookup query, to be sent to the frontend.
@@ -25,3 +28,52 @@ impl QueryEntry {
         }
     }
 }
+
+/// An entry of a user dictionary along with its id, to be sent to the frontend for editing.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EditableEntry {
+    pub id: EntryId,
+    #[serde(flatten)]
+    pub entry: QueryEntry,
+}
+
+impl EditableEntry {
+    pub fn new(id: EntryId, word: String, entry: DictionaryEntry) -> Self {
+        Self {
+            id,
+            entry: QueryEntry::from_dictionary_entry(entry, word),
+        }
+    }
+}
+
+/// A word of an analyzed sentence, to be sent to the frontend.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryToken {
+    pub surface: String,
+    /// Byte offset of the word in the sentence.
+    pub start: usize,
+    pub dictionary_form: String,
+    pub inflections: Vec<String>,
+    /// Entries of every dictionary, empty if the word is unknown.
+    pub entries: Vec<QueryEntry>,
+}
+
+impl QueryToken {
+    /// Converts a [`Token`] to a [`QueryToken`].
+    pub fn from_token<D>(token: Token<D>) -> Self {
+        let entries = token
+            .entries
+            .into_iter()
+            .flat_map(|(_, entries)| entries)
+            .map(|entry| QueryEntry::from_dictionary_entry(entry, token.dictionary_form.clone()))
+            .collect();
+
+        Self {
+            surface: token.surface,
+            start: token.start,
+            dictionary_form: token.dictionary_form,
+            inflections: token.inflections.into_iter().map(str::to_owned).collect(),
+            entries,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 //! Tauri app state module.
 
-use std::sync::RwLock;
+use std::{
+    collections::HashMap,
+    sync::{atomic::AtomicU64, Mutex, RwLock},
+};
 
+use api::database::dictionary::importer::context::CancellationToken;
 use once_cell::sync::OnceCell;
 
 use crate::config::Config;
@@ -9,4 +13,11 @@ use crate::config::Config;
 /// App state for Tauri.
 pub struct AppState {
     pub config: OnceCell<RwLock<Config>>,
+    /// Held for reading while dictionaries are added to the storage, and for writing while it's cleaned up,
+    /// so that cleaning up doesn't delete the files of a dictionary which isn't in the database yet.
+    pub storage_lock: RwLock<()>,
+    /// Cancellation tokens of the dictionary imports currently running, by import id.
+    pub import_cancellations: Mutex<HashMap<u64, CancellationToken>>,
+    /// Id of the next dictionary import.
+    pub next_import_id: AtomicU64,
 }